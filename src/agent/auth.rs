@@ -0,0 +1,471 @@
+use std::{collections::HashMap, env, sync::Arc};
+
+use agent_client_protocol::{AuthenticateResponse, Error};
+use codex_app_server_protocol::AuthMode;
+use codex_core::{AuthManager, auth::CLIENT_ID};
+use codex_login::{LoginServer, ServerOptions, login_with_api_key, run_login_server};
+use serde::Serialize;
+use serde_json::{Map, Value, json};
+use tokio::time::{self, Duration};
+use tracing::{info, warn};
+
+use super::{core::CodexAgent, utils};
+
+/// How long `authenticate` waits for the user to finish the ChatGPT login.
+const LOGIN_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+/// How often stored credentials are re-checked while the login is pending.
+const LOGIN_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How the agent is currently signed in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(super) enum AccountMode {
+    ChatGpt,
+    ApiKey,
+    SignedOut,
+}
+
+impl AccountMode {
+    /// Label used in human-readable output such as `/status`.
+    pub(super) fn label(self) -> &'static str {
+        match self {
+            AccountMode::ChatGpt => "ChatGPT",
+            AccountMode::ApiKey => "API key",
+            AccountMode::SignedOut => "Not signed in",
+        }
+    }
+}
+
+/// Account details reported by `/status` and the `codex/account` extension method.
+#[derive(Debug, Clone, Serialize)]
+pub(super) struct AccountInfo {
+    pub auth_mode: AccountMode,
+    pub email: Option<String>,
+    pub plan: Option<String>,
+}
+
+impl CodexAgent {
+    /// Current auth manager handle; the lock is only held while cloning it.
+    pub(super) fn current_auth_manager(&self) -> Option<Arc<AuthManager>> {
+        self.auth_manager.read().ok().map(|am| am.clone())
+    }
+
+    /// Reload credentials from Codex's auth storage and return the active mode.
+    pub(super) fn reload_auth_mode(&self) -> Option<AuthMode> {
+        let am = self.current_auth_manager()?;
+        am.reload();
+        am.auth().map(|auth| auth.mode)
+    }
+
+    /// Describe the signed-in account from the cached Codex credentials.
+    pub(super) async fn account_info(&self) -> AccountInfo {
+        let Some(auth) = self.current_auth_manager().and_then(|am| am.auth()) else {
+            return AccountInfo {
+                auth_mode: AccountMode::SignedOut,
+                email: None,
+                plan: None,
+            };
+        };
+        match auth.get_token_data().await {
+            Ok(td) => AccountInfo {
+                auth_mode: AccountMode::ChatGpt,
+                email: td.id_token.email.clone(),
+                plan: td.id_token.get_chatgpt_plan_type(),
+            },
+            Err(_) => AccountInfo {
+                auth_mode: AccountMode::ApiKey,
+                email: None,
+                plan: None,
+            },
+        }
+    }
+
+    /// Remove stored credentials and require authentication before the next prompt.
+    ///
+    /// Conversations share the agent's `AuthManager`, so clearing it also drops
+    /// the cached auth used by every existing session. Returns whether any
+    /// credentials were removed from storage.
+    pub(super) fn logout(&self) -> Result<bool, Error> {
+        let am = self
+            .current_auth_manager()
+            .ok_or_else(|| Error::internal_error().data("auth manager is unavailable"))?;
+        let removed = am.logout().map_err(|err| {
+            Error::internal_error().data(format!("failed to remove stored credentials: {err}"))
+        })?;
+        if let Some(pending) = self.login_shutdown.take() {
+            pending.shutdown();
+        }
+        self.auth_invalidated.set(true);
+        info!(removed, "Signed out");
+        Ok(removed)
+    }
+
+    /// Fail with `auth_required` after a logout until the client signs in again.
+    pub(super) fn ensure_authenticated(&self) -> Result<(), Error> {
+        if !self.auth_invalidated.get() {
+            return Ok(());
+        }
+        if self.reload_auth_mode().is_some() {
+            self.auth_invalidated.set(false);
+            return Ok(());
+        }
+        Err(Error::auth_required().data("Signed out. Authenticate again to continue."))
+    }
+
+    /// Sign in with ChatGPT by driving the Codex login server.
+    ///
+    /// The authorization URL is sent to the client as a `codex/login` extension
+    /// notification so the IDE can open it; the call then waits until the
+    /// callback stores tokens, the server fails, or the login times out.
+    pub(super) async fn login_with_chatgpt(&self) -> Result<AuthenticateResponse, Error> {
+        if self.reload_auth_mode() == Some(AuthMode::ChatGPT) {
+            return Ok(Default::default());
+        }
+
+        let mut opts = ServerOptions::new(
+            self.config.codex_home.clone(),
+            CLIENT_ID.to_string(),
+            self.config.forced_chatgpt_workspace_id.clone(),
+            self.config.cli_auth_credentials_store_mode,
+        );
+        opts.open_browser = false;
+
+        // Only one login server can own the callback port; cancel a stale
+        // attempt before starting a new one.
+        if let Some(previous) = self.login_shutdown.take() {
+            previous.shutdown();
+        }
+        let server = run_login_server(opts).map_err(|err| {
+            Error::internal_error().data(format!("failed to start ChatGPT login server: {err}"))
+        })?;
+        self.login_shutdown.replace(Some(server.cancel_handle()));
+
+        info!(
+            auth_url = %server.auth_url,
+            port = server.actual_port,
+            "Started ChatGPT login flow"
+        );
+        self.notify_client(
+            "codex/login",
+            json!({
+                "method": "chatgpt",
+                "auth_url": server.auth_url,
+                "callback_port": server.actual_port,
+            }),
+        );
+
+        let shutdown = server.cancel_handle();
+        let result = self.wait_for_chatgpt_login(server).await;
+        shutdown.shutdown();
+        result
+    }
+
+    async fn wait_for_chatgpt_login(
+        &self,
+        server: LoginServer,
+    ) -> Result<AuthenticateResponse, Error> {
+        let done = server.block_until_done();
+        let deadline = time::sleep(LOGIN_TIMEOUT);
+        let mut poll = time::interval(LOGIN_POLL_INTERVAL);
+        tokio::pin!(done, deadline);
+
+        loop {
+            tokio::select! {
+                result = &mut done => {
+                    if let Err(err) = result {
+                        warn!(error = %err, "ChatGPT login server failed");
+                        return Err(Error::auth_required()
+                            .data(format!("ChatGPT login did not complete: {err}")));
+                    }
+                    return if self.reload_auth_mode() == Some(AuthMode::ChatGPT) {
+                        Ok(Default::default())
+                    } else {
+                        Err(Error::auth_required()
+                            .data("ChatGPT login finished but no credentials were stored"))
+                    };
+                }
+                _ = poll.tick() => {
+                    if self.reload_auth_mode() == Some(AuthMode::ChatGPT) {
+                        return Ok(Default::default());
+                    }
+                }
+                _ = &mut deadline => {
+                    return Err(Error::auth_required()
+                        .data("Timed out waiting for the ChatGPT login to complete"));
+                }
+            }
+        }
+    }
+
+    /// Authenticate with an OpenAI API key.
+    ///
+    /// A key passed as `_meta.api_key` is persisted through Codex's auth storage;
+    /// otherwise existing credentials (`auth.json`, keyring) are reloaded.
+    pub(super) async fn login_with_api_key(
+        &self,
+        meta: Option<&Map<String, Value>>,
+    ) -> Result<AuthenticateResponse, Error> {
+        let api_key = meta
+            .and_then(|meta| meta.get("api_key"))
+            .and_then(Value::as_str)
+            .map(str::trim)
+            .filter(|key| !key.is_empty());
+
+        if let Some(api_key) = api_key {
+            login_with_api_key(
+                &self.config.codex_home,
+                api_key,
+                self.config.cli_auth_credentials_store_mode,
+            )
+            .map_err(|err| {
+                Error::internal_error().data(format!("failed to store API key: {err}"))
+            })?;
+            info!("Stored API key from authenticate request");
+        }
+
+        match self.reload_auth_mode() {
+            Some(AuthMode::ApiKey) => Ok(Default::default()),
+            Some(_) if api_key.is_none() => Ok(Default::default()),
+            _ => Err(Error::auth_required().data(
+                "No API key found. Pass one as `_meta.api_key` in the authenticate request.",
+            )),
+        }
+    }
+
+    /// Authenticate against the configured custom model provider.
+    ///
+    /// Checks that the provider is configured, that its `env_key` is set, that
+    /// OpenAI credentials exist when the provider requires them and, when
+    /// `[acp.provider_auth] probe_models` is enabled, that its models endpoint
+    /// accepts the credentials. Each failure carries a `reason` code and an
+    /// actionable `message` in the error data.
+    pub(super) async fn authenticate_custom_provider(&self) -> Result<AuthenticateResponse, Error> {
+        let provider_id = self.config.model_provider_id.as_str();
+        if !utils::is_custom_provider(provider_id) {
+            return Err(Error::invalid_params()
+                .data("Custom provider auth method is only available for custom providers"));
+        }
+
+        let Some(provider) = self.config.model_providers.get(provider_id) else {
+            return Err(provider_auth_error(
+                provider_id,
+                "provider_not_configured",
+                format!("Add a [model_providers.{provider_id}] entry to your Codex config."),
+            ));
+        };
+
+        let api_key = match provider.env_key.as_deref() {
+            Some(env_key) => match env::var(env_key) {
+                Ok(value) if !value.trim().is_empty() => Some(value),
+                _ => {
+                    let mut message =
+                        format!("Set the `{env_key}` environment variable for the agent process.");
+                    if let Some(instructions) = provider.env_key_instructions.as_deref() {
+                        message.push(' ');
+                        message.push_str(instructions);
+                    }
+                    return Err(provider_auth_error(provider_id, "missing_env_key", message));
+                }
+            },
+            None => provider.experimental_bearer_token.clone(),
+        };
+
+        if provider.requires_openai_auth && self.reload_auth_mode().is_none() {
+            return Err(provider_auth_error(
+                provider_id,
+                "openai_auth_required",
+                "This provider uses OpenAI credentials. Authenticate with `chatgpt` or `apikey` first.",
+            ));
+        }
+
+        let probe = self.settings.provider_auth;
+        if probe.probe_models {
+            let Some(base_url) = provider.base_url.as_deref() else {
+                return Err(provider_auth_error(
+                    provider_id,
+                    "missing_base_url",
+                    format!(
+                        "Set `base_url` in [model_providers.{provider_id}] to probe the provider."
+                    ),
+                ));
+            };
+            probe_models_endpoint(
+                base_url,
+                api_key.as_deref(),
+                provider.http_headers.as_ref(),
+                provider.query_params.as_ref(),
+                probe.probe_timeout(),
+            )
+            .await
+            .map_err(|failure| failure.into_error(provider_id))?;
+        }
+
+        info!(provider = provider_id, "Custom provider authenticated");
+        Ok(Default::default())
+    }
+}
+
+/// Build an `auth_required` error with a machine-readable reason for the client.
+fn provider_auth_error(provider_id: &str, reason: &str, message: impl Into<String>) -> Error {
+    Error::auth_required().data(json!({
+        "provider": provider_id,
+        "reason": reason,
+        "message": message.into(),
+    }))
+}
+
+/// Why the provider's models endpoint did not confirm the credentials.
+enum ProbeFailure {
+    Unreachable { url: String, error: String },
+    Unauthorized { url: String, status: u16 },
+    Status { url: String, status: u16 },
+}
+
+impl ProbeFailure {
+    fn into_error(self, provider_id: &str) -> Error {
+        match self {
+            ProbeFailure::Unreachable { url, error } => provider_auth_error(
+                provider_id,
+                "provider_unreachable",
+                format!("Could not reach {url}: {error}. Check `base_url` and your network."),
+            ),
+            ProbeFailure::Unauthorized { url, status } => provider_auth_error(
+                provider_id,
+                "invalid_credentials",
+                format!("{url} rejected the credentials (HTTP {status}). Check the API key."),
+            ),
+            ProbeFailure::Status { url, status } => provider_auth_error(
+                provider_id,
+                "provider_error",
+                format!("{url} returned HTTP {status}."),
+            ),
+        }
+    }
+}
+
+/// Call `GET {base_url}/models` to verify the provider is reachable and
+/// accepts the configured key.
+async fn probe_models_endpoint(
+    base_url: &str,
+    api_key: Option<&str>,
+    headers: Option<&HashMap<String, String>>,
+    query_params: Option<&HashMap<String, String>>,
+    timeout: Duration,
+) -> Result<(), ProbeFailure> {
+    let url = format!("{}/models", base_url.trim_end_matches('/'));
+    let unreachable = |error: reqwest::Error| ProbeFailure::Unreachable {
+        url: url.clone(),
+        error: error.to_string(),
+    };
+
+    let client = reqwest::Client::builder()
+        .timeout(timeout)
+        .build()
+        .map_err(unreachable)?;
+    let mut request = client.get(&url);
+    if let Some(query_params) = query_params {
+        request = request.query(query_params);
+    }
+    for (name, value) in headers.into_iter().flatten() {
+        request = request.header(name, value);
+    }
+    if let Some(api_key) = api_key {
+        request = request.bearer_auth(api_key);
+    }
+
+    let status = request.send().await.map_err(unreachable)?.status();
+    match status.as_u16() {
+        200..=299 => Ok(()),
+        status @ (401 | 403) => Err(ProbeFailure::Unauthorized { url, status }),
+        status => Err(ProbeFailure::Status { url, status }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+        task::JoinHandle,
+    };
+
+    use super::*;
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// Answer one request with `status`; the task returns the request it got.
+    async fn stub_provider(status: &'static str) -> (String, JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}/v1", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = vec![0; 8192];
+            let len = stream.read(&mut request).await.unwrap();
+            let response =
+                format!("HTTP/1.1 {status}\r\ncontent-length: 2\r\nconnection: close\r\n\r\n{{}}");
+            stream.write_all(response.as_bytes()).await.unwrap();
+            String::from_utf8_lossy(&request[..len]).to_ascii_lowercase()
+        });
+        (base_url, server)
+    }
+
+    fn reason(failure: ProbeFailure) -> String {
+        let error = failure.into_error("stub");
+        error.data.unwrap()["reason"].as_str().unwrap().to_string()
+    }
+
+    #[tokio::test]
+    async fn probe_accepts_success_and_sends_credentials() {
+        let (base_url, server) = stub_provider("200 OK").await;
+        let headers = HashMap::from([("x-team".to_string(), "acp".to_string())]);
+        let query = HashMap::from([("api-version".to_string(), "1".to_string())]);
+
+        let result = probe_models_endpoint(
+            &base_url,
+            Some("sk-test"),
+            Some(&headers),
+            Some(&query),
+            TIMEOUT,
+        )
+        .await;
+
+        assert!(result.is_ok());
+        let request = server.await.unwrap();
+        assert!(request.starts_with("get /v1/models?api-version=1 "));
+        assert!(request.contains("authorization: bearer sk-test\r\n"));
+        assert!(request.contains("x-team: acp\r\n"));
+    }
+
+    #[tokio::test]
+    async fn probe_reports_rejected_credentials() {
+        for status in ["401 Unauthorized", "403 Forbidden"] {
+            let (base_url, _server) = stub_provider(status).await;
+            let failure = probe_models_endpoint(&base_url, Some("sk-bad"), None, None, TIMEOUT)
+                .await
+                .unwrap_err();
+            assert_eq!(reason(failure), "invalid_credentials", "{status}");
+        }
+    }
+
+    #[tokio::test]
+    async fn probe_reports_other_statuses() {
+        let (base_url, _server) = stub_provider("500 Internal Server Error").await;
+        let failure = probe_models_endpoint(&base_url, None, None, None, TIMEOUT)
+            .await
+            .unwrap_err();
+        assert_eq!(reason(failure), "provider_error");
+    }
+
+    #[tokio::test]
+    async fn probe_reports_unreachable_provider() {
+        // Take a free port and close it again so the connection is refused.
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}/v1", listener.local_addr().unwrap());
+        drop(listener);
+
+        let failure = probe_models_endpoint(&base_url, None, None, None, TIMEOUT)
+            .await
+            .unwrap_err();
+        assert_eq!(reason(failure), "provider_unreachable");
+    }
+}
@@ -1,6 +1,6 @@
 use std::{path::Path, sync::LazyLock};
 
-use crate::CodexAgent;
+use crate::{CodexAgent, agent::utils};
 use agent_client_protocol::{AvailableCommand, SessionId};
 use codex_core::protocol::{AskForApproval, Op, ReviewRequest, ReviewTarget, SandboxPolicy};
 use codex_protocol::user_input::UserInput;
@@ -34,10 +34,18 @@ impl CodexAgent {
                 self.session_manager
                     .with_session_state_mut(session_id, |state| {
                         state.token_usage = None;
+                        state.context_tokens = None;
                     });
                 msg = "🧠 Compacting conversation to reduce context size...\n\n".into();
                 Some(Op::Compact)
             }
+            "logout" => {
+                msg = match self.logout() {
+                    Ok(_) => "👋 Signed out. Authenticate again to continue.\n\n".into(),
+                    Err(err) => format!("Failed to sign out: {}\n\n", err.message),
+                };
+                None
+            }
             "review" => {
                 msg = "🔍 Asking Codex to review current changes...\n\n".into();
                 Some(Op::Review {
@@ -63,18 +71,24 @@ impl CodexAgent {
     async fn render_status(&self, session_id: &SessionId) -> String {
         let sid_str = session_id.0.as_ref();
         // Session snapshot
-        let (approval_mode, sandbox_mode, token_usage) = {
+        let (approval_mode, sandbox_mode, token_usage, rate_limits, disk_fallback, fs_tools) = {
             if let Some(state) = self.session_manager.sessions().borrow().get(sid_str) {
                 (
                     state.current_approval,
                     state.current_sandbox.clone(),
                     state.token_usage.clone(),
+                    state.rate_limits.clone(),
+                    state.fs_disk_fallback,
+                    state.fs_tools,
                 )
             } else {
                 (
                     AskForApproval::OnRequest,
                     SandboxPolicy::new_workspace_write_policy(),
                     None,
+                    None,
+                    self.settings.fs.disk_fallback,
+                    self.settings.fs.tools,
                 )
             }
         };
@@ -82,34 +96,29 @@ impl CodexAgent {
         // Workspace
         let cwd = self.shorten_home(&self.config.cwd);
 
+        // File access
+        let fs = &self.settings.fs;
+        let read_limit = format!("{} lines / {} bytes", fs.read_line_limit, fs.max_read_bytes);
+        let disk_fallback = if disk_fallback { "on" } else { "off" };
+        let bridge_timeouts = format!(
+            "read {}s, write {}s, connect {}s",
+            fs.read_timeout_secs, fs.write_timeout_secs, fs.connect_timeout_secs
+        );
+        let fs_mcp_timeouts = format!(
+            "startup {}s, tool {}s",
+            fs.startup_timeout_secs,
+            self.fs_tool_timeout().as_secs()
+        );
+        let mcp_timeouts = format!(
+            "startup {}s, tool {}s",
+            self.settings.mcp.startup_timeout_secs, self.settings.mcp.tool_timeout_secs
+        );
+
         // Account
-        let (auth_mode, email, plan): (String, String, String) =
-            match self.auth_manager.read().ok().and_then(|am| am.auth()) {
-                Some(auth) => match auth.get_token_data().await {
-                    Ok(td) => {
-                        let email = td
-                            .id_token
-                            .email
-                            .clone()
-                            .unwrap_or_else(|| "(none)".to_string());
-                        let plan = td
-                            .id_token
-                            .get_chatgpt_plan_type()
-                            .unwrap_or_else(|| "(unknown)".to_string());
-                        ("ChatGPT".to_string(), email, plan)
-                    }
-                    Err(_) => (
-                        "API key".to_string(),
-                        "(none)".to_string(),
-                        "(unknown)".to_string(),
-                    ),
-                },
-                None => (
-                    "Not signed in".to_string(),
-                    "(none)".to_string(),
-                    "(unknown)".to_string(),
-                ),
-            };
+        let account = self.account_info().await;
+        let auth_mode = account.auth_mode.label();
+        let email = account.email.unwrap_or_else(|| "(none)".to_string());
+        let plan = account.plan.unwrap_or_else(|| "(unknown)".to_string());
 
         // Model
         let model = self.config.model.clone().unwrap_or_default();
@@ -129,6 +138,24 @@ impl CodexAgent {
             None => ("0".to_string(), "0".to_string(), "0".to_string()),
         };
 
+        // Rate limits (ChatGPT plans only)
+        let (primary_limit, secondary_limit) = match rate_limits {
+            Some(snapshot) => (
+                snapshot
+                    .primary
+                    .as_ref()
+                    .map(utils::format_rate_limit_window),
+                snapshot
+                    .secondary
+                    .as_ref()
+                    .map(utils::format_rate_limit_window),
+            ),
+            None => (None, None),
+        };
+        let not_available = || "(not available)".to_string();
+        let primary_limit = primary_limit.unwrap_or_else(not_available);
+        let secondary_limit = secondary_limit.unwrap_or_else(not_available);
+
         let status = format!(
             r#"
 📂 Workspace
@@ -137,6 +164,15 @@ impl CodexAgent {
     Approval Mode: {approval}
     Sandbox:       {sandbox}
 
+🗂️ File Access (acp_fs)
+
+    File Tools:          {fs_tools}
+    Read Limit:          {read_limit}
+    Disk Fallback:       {disk_fallback}
+    Bridge Timeouts:     {bridge_timeouts}
+    acp_fs Timeouts:     {fs_mcp_timeouts}
+    Client MCP Timeouts: {mcp_timeouts}
+
 👤 Account
 
     Signed in with: {auth_mode}
@@ -156,10 +192,21 @@ impl CodexAgent {
     Input:          {input}
     Output:         {output}
     Total:          {total}
+
+⏱️ Rate Limits
+
+    Primary:        {primary_limit}
+    Secondary:      {secondary_limit}
 "#,
             cwd = cwd,
             approval = approval_mode,
             sandbox = sandbox_mode,
+            fs_tools = fs_tools.as_str(),
+            read_limit = read_limit,
+            disk_fallback = disk_fallback,
+            bridge_timeouts = bridge_timeouts,
+            fs_mcp_timeouts = fs_mcp_timeouts,
+            mcp_timeouts = mcp_timeouts,
             auth_mode = auth_mode,
             email = email,
             plan = plan,
@@ -171,6 +218,8 @@ impl CodexAgent {
             input = input,
             output = output,
             total = total,
+            primary_limit = primary_limit,
+            secondary_limit = secondary_limit,
         );
         status
     }
@@ -207,9 +256,10 @@ fn built_in_commands() -> Vec<AvailableCommand> {
             "summarize conversation to prevent hitting the context limit",
         ),
         AvailableCommand::new("review", "review my current changes and find issues"),
+        AvailableCommand::new("logout", "sign out and clear stored credentials"),
         AvailableCommand::new(
             "status",
-            "show current session configuration and token usage",
+            "show current session configuration, token usage and rate limits",
         ),
     ]
 }
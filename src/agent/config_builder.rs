@@ -1,20 +1,40 @@
 use std::{collections::HashMap, env, time::Duration};
 
 use agent_client_protocol::{Error, HttpHeader, McpServer};
-use codex_core::config::{
-    Config,
-    types::{McpServerConfig, McpServerTransportConfig},
+use codex_core::{
+    config::{
+        Config,
+        types::{McpServerConfig, McpServerTransportConfig},
+    },
+    features::Feature,
+    protocol::SandboxPolicy,
 };
 
-use crate::fs::FsBridge;
+use tracing::warn;
+
+use crate::{
+    fs::{FsBridge, FsPolicy, FsSessionMode},
+    settings::FsToolMode,
+};
 
 use super::core::CodexAgent;
 
+/// Extra time Codex gives an `acp_fs` tool call beyond the bridge timeout.
+const FS_TOOL_TIMEOUT_MARGIN: Duration = Duration::from_secs(5);
+
+/// Codex's experimental file tools, offered to models whose family lists them.
+const NATIVE_FILE_TOOLS: [&str; 3] = ["read_file", "list_dir", "grep_files"];
+
+/// Appended to the filesystem guidance when `acp_fs` is the only file tool.
+const FS_EXCLUSIVE_GUIDANCE: &str = "In this session acp_fs is the only way to read or change files: Codex's apply_patch, read_file, list_dir and grep_files tools are disabled, and the shell must not be used to read, write, move or delete workspace files.";
+
 impl CodexAgent {
     /// Prepare the filesystem MCP server configuration for a session.
     ///
     /// This creates a stdio-based MCP server that communicates with the
-    /// filesystem bridge, enabling file operations within the session.
+    /// filesystem bridge, enabling file operations within the session. The
+    /// session must already be registered via [`Self::sync_fs_policy`] so
+    /// that its bridge token can be handed to the server.
     pub(super) fn prepare_fs_mcp_server_config(
         &self,
         session_id: &str,
@@ -24,12 +44,39 @@ impl CodexAgent {
             Error::internal_error().data(format!("failed to locate agent binary: {err}"))
         })?;
 
+        let token = bridge.session_token(session_id).ok_or_else(|| {
+            Error::internal_error().data(format!(
+                "fs session {session_id} is not registered with the bridge"
+            ))
+        })?;
+
         let mut env = HashMap::new();
         env.insert(
             "ACP_FS_BRIDGE_ADDR".to_string(),
             bridge.address().to_string(),
         );
         env.insert("ACP_FS_SESSION_ID".to_string(), session_id.to_string());
+        env.insert("ACP_FS_BRIDGE_TOKEN".to_string(), token);
+        env.insert(
+            "ACP_FS_READ_TIMEOUT_SECS".to_string(),
+            self.settings.fs.read_timeout_secs.to_string(),
+        );
+        env.insert(
+            "ACP_FS_WRITE_TIMEOUT_SECS".to_string(),
+            self.settings.fs.write_timeout_secs.to_string(),
+        );
+        env.insert(
+            "ACP_FS_CONNECT_TIMEOUT_SECS".to_string(),
+            self.settings.fs.connect_timeout_secs.to_string(),
+        );
+        env.insert(
+            "ACP_FS_READ_LINE_LIMIT".to_string(),
+            self.settings.fs.read_line_limit.to_string(),
+        );
+        env.insert(
+            "ACP_FS_MAX_READ_BYTES".to_string(),
+            self.settings.fs.max_read_bytes.to_string(),
+        );
 
         Ok(McpServerConfig {
             transport: McpServerTransportConfig::Stdio {
@@ -40,25 +87,85 @@ impl CodexAgent {
                 cwd: None,
             },
             enabled: true,
-            startup_timeout_sec: Some(Duration::from_secs(5)),
-            tool_timeout_sec: Some(Duration::from_secs(30)),
+            startup_timeout_sec: Some(self.settings.fs.startup_timeout()),
+            tool_timeout_sec: Some(self.fs_tool_timeout()),
             enabled_tools: None,
             disabled_tools: {
                 let caps = self.session_manager.client_capabilities();
                 let mut v: Vec<String> = Vec::new();
                 if !caps.fs.read_text_file {
                     v.push("read_text_file".to_string());
+                    v.push("read_notebook".to_string());
                 }
                 if !caps.fs.write_text_file {
                     v.push("write_text_file".to_string());
                     v.push("edit_text_file".to_string());
                     v.push("multi_edit_text_file".to_string());
+                    v.push("insert_at_line".to_string());
+                    v.push("replace_line_range".to_string());
+                    v.push("apply_edit_set".to_string());
+                    v.push("apply_patch".to_string());
+                    v.push("edit_notebook".to_string());
                 }
                 if v.is_empty() { None } else { Some(v) }
             },
         })
     }
 
+    /// How long Codex lets an `acp_fs` tool call run. Leaves room for the
+    /// bridge's own timeout to surface as a tool error.
+    pub(super) fn fs_tool_timeout(&self) -> Duration {
+        self.settings.fs.max_timeout() + FS_TOOL_TIMEOUT_MARGIN
+    }
+
+    /// The file tools a session actually gets for the requested mode.
+    ///
+    /// `acp_fs_only` turns off Codex's own file tools, so without a client
+    /// that can write files the session could not edit anything; it falls
+    /// back to `both` instead.
+    pub(super) fn effective_fs_tools(&self, requested: FsToolMode) -> FsToolMode {
+        if requested == FsToolMode::AcpFsOnly
+            && !self
+                .session_manager
+                .client_capabilities()
+                .fs
+                .write_text_file
+        {
+            warn!(
+                "acp_fs_only requested but the client cannot write files; using both file tool sets"
+            );
+            return FsToolMode::Both;
+        }
+        requested
+    }
+
+    /// Register the session's FS confinement policy and mode with the bridge.
+    ///
+    /// Must run before the conversation starts the `acp_fs` server and again
+    /// whenever the session's mode changes.
+    pub(super) fn sync_fs_policy(
+        &self,
+        fs_session_id: &str,
+        sandbox: &SandboxPolicy,
+        mode: FsSessionMode,
+    ) {
+        if let Some(bridge) = &self.fs_bridge {
+            let policy = FsPolicy::from_sandbox(
+                sandbox,
+                &self.config.cwd,
+                &self.settings.fs.read_allow_paths(),
+            );
+            bridge.set_session_policy(fs_session_id, policy, mode);
+        }
+    }
+
+    /// Unregister an FS session from the bridge when its conversation is dropped.
+    pub(super) fn release_fs_session(&self, fs_session_id: &str) {
+        if let Some(bridge) = &self.fs_bridge {
+            bridge.remove_session(fs_session_id);
+        }
+    }
+
     /// Build a streamable HTTP-based MCP server configuration.
     fn build_streamable_http_server(
         name: String,
@@ -149,16 +256,56 @@ impl CodexAgent {
     /// Build a session-specific Codex configuration.
     ///
     /// This clones the base config and adds:
-    /// - Filesystem guidance instructions
     /// - Session-specific MCP servers
-    /// - The acp_fs MCP server if filesystem bridge is available
+    /// - The acp_fs MCP server and its guidance instructions, if the
+    ///   filesystem bridge is available and `fs_tools` allows it
+    /// - With [`FsToolMode::AcpFsOnly`], Codex's own `apply_patch`, `read_file`,
+    ///   `list_dir` and `grep_files` tools turned off. The shell cannot be
+    ///   restricted this way; it stays available and is only steered away from
+    ///   file access by the guidance. The experimental tools are dropped from
+    ///   the session's model family, so switching to another model that offers
+    ///   them brings them back.
     pub(super) fn build_session_config(
         &self,
         session_id: &str,
+        fs_tools: FsToolMode,
         mcp_servers: Vec<McpServer>,
     ) -> Result<Config, Error> {
         let mut session_config = self.config.clone();
-        let fs_guidance = include_str!("../../prompt_fs_guidance.md");
+
+        let startup_timeout = Some(self.settings.mcp.startup_timeout());
+        let tool_timeout = Some(self.settings.mcp.tool_timeout());
+
+        // Add requested MCP servers
+        session_config.mcp_servers.extend(
+            mcp_servers
+                .into_iter()
+                .filter_map(|srv| self.build_mcp_server(srv, startup_timeout, tool_timeout)),
+        );
+
+        // Native-only sessions get neither the acp_fs server nor its guidance.
+        let Some(bridge) = self.fs_bridge.as_ref().filter(|_| fs_tools.uses_acp_fs()) else {
+            return Ok(session_config);
+        };
+
+        let server_config = self.prepare_fs_mcp_server_config(session_id, bridge.as_ref())?;
+        session_config
+            .mcp_servers
+            .insert("acp_fs".to_string(), server_config);
+
+        let mut fs_guidance = include_str!("../../prompt_fs_guidance.md").to_string();
+        if fs_tools == FsToolMode::AcpFsOnly {
+            session_config.include_apply_patch_tool = false;
+            session_config.features.disable(Feature::ApplyPatchFreeform);
+            session_config
+                .model_family
+                .experimental_supported_tools
+                .retain(|tool| !NATIVE_FILE_TOOLS.contains(&tool.as_str()));
+            fs_guidance.push('\n');
+            fs_guidance.push_str(FS_EXCLUSIVE_GUIDANCE);
+            fs_guidance.push('\n');
+        }
+        let fs_guidance = fs_guidance.as_str();
 
         // Inject filesystem guidance into instructions
         if let Some(mut base) = session_config.base_instructions.take() {
@@ -184,24 +331,6 @@ impl CodexAgent {
             };
         }
 
-        let startup_timeout = Some(Duration::from_secs(5));
-        let tool_timeout = Some(Duration::from_secs(30));
-
-        // Add requested MCP servers
-        session_config.mcp_servers.extend(
-            mcp_servers
-                .into_iter()
-                .filter_map(|srv| self.build_mcp_server(srv, startup_timeout, tool_timeout)),
-        );
-
-        // Add acp_fs MCP server if bridge is available
-        if let Some(bridge) = &self.fs_bridge {
-            let server_config = self.prepare_fs_mcp_server_config(session_id, bridge.as_ref())?;
-            session_config
-                .mcp_servers
-                .insert("acp_fs".to_string(), server_config);
-        }
-
         Ok(session_config)
     }
 }
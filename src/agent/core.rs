@@ -1,4 +1,5 @@
 use std::{
+    cell::{Cell, RefCell},
     collections::HashMap,
     env,
     sync::{Arc, RwLock},
@@ -6,20 +7,21 @@ use std::{
 
 use agent_client_protocol::{
     AgentCapabilities, AuthMethod, AuthMethodId, AuthenticateRequest, AuthenticateResponse,
-    AvailableCommandsUpdate, Error, Implementation, InitializeRequest, InitializeResponse,
-    LoadSessionRequest, LoadSessionResponse, McpCapabilities, ModelId, NewSessionRequest,
-    NewSessionResponse, PromptCapabilities, ProtocolVersion, ReadTextFileRequest,
-    ReadTextFileResponse, RequestPermissionRequest, RequestPermissionResponse, SessionId,
-    SessionModeId, SessionModeState, SessionModelState, SessionNotification, SessionUpdate,
-    SetSessionModeRequest, SetSessionModeResponse, SetSessionModelRequest, SetSessionModelResponse,
-    WriteTextFileRequest, WriteTextFileResponse,
+    AvailableCommandsUpdate, Error, ExtNotification, Implementation, InitializeRequest,
+    InitializeResponse, LoadSessionRequest, LoadSessionResponse, McpCapabilities, ModelId,
+    NewSessionRequest, NewSessionResponse, PromptCapabilities, ProtocolVersion,
+    ReadTextFileRequest, ReadTextFileResponse, RequestPermissionRequest, RequestPermissionResponse,
+    SessionId, SessionModeId, SessionModeState, SessionModelState, SessionNotification,
+    SessionUpdate, SetSessionModeRequest, SetSessionModeResponse, SetSessionModelRequest,
+    SetSessionModelResponse, WriteTextFileRequest, WriteTextFileResponse,
 };
-use codex_app_server_protocol::AuthMode;
 use codex_core::{
     AuthManager, ConversationManager, NewConversation,
     config::{Config, profile::ConfigProfile},
     protocol::{Op, SessionSource},
 };
+use codex_login::ShutdownHandle;
+use serde_json::value::to_raw_value;
 use tokio::{
     sync::{mpsc::UnboundedSender, oneshot},
     task,
@@ -27,7 +29,11 @@ use tokio::{
 use tracing::{info, warn};
 use uuid::Uuid;
 
-use crate::{agent::utils, fs::FsBridge};
+use crate::{
+    agent::utils,
+    fs::{FsBridge, FsSessionMode},
+    settings::AcpSettings,
+};
 
 use super::{
     commands,
@@ -37,7 +43,8 @@ use super::{
 /// Operations that require client interaction.
 ///
 /// These operations are sent to the client handler to request permissions,
-/// read files, or write files based on client capabilities.
+/// read files, or write files based on client capabilities, or to push
+/// extension notifications that need no response.
 pub enum ClientOp {
     RequestPermission {
         request: RequestPermissionRequest,
@@ -51,6 +58,9 @@ pub enum ClientOp {
         request: WriteTextFileRequest,
         response_tx: oneshot::Sender<Result<WriteTextFileResponse, Error>>,
     },
+    ExtNotification {
+        notification: ExtNotification,
+    },
 }
 
 /// The main ACP agent implementation.
@@ -61,9 +71,12 @@ pub struct CodexAgent {
     pub(super) session_manager: SessionManager,
     pub(super) config: Config,
     pub(super) profiles: HashMap<String, ConfigProfile>,
+    pub(super) settings: AcpSettings,
     pub(super) auth_manager: Arc<RwLock<Arc<AuthManager>>>,
     pub(super) client_tx: UnboundedSender<ClientOp>,
     pub(super) fs_bridge: Option<Arc<FsBridge>>,
+    pub(super) login_shutdown: RefCell<Option<ShutdownHandle>>,
+    pub(super) auth_invalidated: Cell<bool>,
 }
 
 impl CodexAgent {
@@ -78,6 +91,7 @@ impl CodexAgent {
         client_tx: UnboundedSender<ClientOp>,
         config: Config,
         profiles: HashMap<String, ConfigProfile>,
+        settings: AcpSettings,
         fs_bridge: Option<Arc<FsBridge>>,
     ) -> Self {
         let auth = AuthManager::shared(
@@ -94,9 +108,34 @@ impl CodexAgent {
             session_manager,
             config,
             profiles,
+            settings,
             auth_manager: Arc::new(RwLock::new(auth)),
             client_tx,
             fs_bridge,
+            login_shutdown: RefCell::new(None),
+            auth_invalidated: Cell::new(false),
+        }
+    }
+
+    /// Send a fire-and-forget extension notification to the client.
+    pub(super) fn notify_client(&self, method: &str, params: serde_json::Value) {
+        let params = match to_raw_value(&params) {
+            Ok(raw) => Arc::from(raw),
+            Err(err) => {
+                warn!(error = %err, method, "failed to encode extension notification");
+                return;
+            }
+        };
+        let notification = ExtNotification::new(method, params);
+        if self
+            .client_tx
+            .send(ClientOp::ExtNotification { notification })
+            .is_err()
+        {
+            warn!(
+                method,
+                "client channel closed, dropping extension notification"
+            );
         }
     }
 
@@ -109,10 +148,12 @@ impl CodexAgent {
 
         // Advertise supported auth methods based on the configured provider
         let mut auth_methods = vec![
-            AuthMethod::new(AuthMethodId::new("chatgpt"), "ChatGPT")
-                .description("Sign in with ChatGPT to use your plan"),
-            AuthMethod::new(AuthMethodId::new("apikey"), "OpenAI API Key")
-                .description("Use OPENAI_API_KEY from environment or auth.json"),
+            AuthMethod::new(AuthMethodId::new("chatgpt"), "ChatGPT").description(
+                "Sign in with ChatGPT to use your plan (the login URL is sent as a `codex/login` notification)",
+            ),
+            AuthMethod::new(AuthMethodId::new("apikey"), "OpenAI API Key").description(
+                "Pass an API key as `_meta.api_key`, or use credentials stored in auth.json",
+            ),
         ];
 
         // Add custom provider auth method if using a custom provider
@@ -155,70 +196,23 @@ impl CodexAgent {
         &self,
         args: AuthenticateRequest,
     ) -> Result<AuthenticateResponse, Error> {
-        info!(?args, "Received authenticate request");
+        // Do not log `args` itself: `_meta` may carry an API key.
+        info!(method_id = %args.method_id.0, "Received authenticate request");
 
         let method = args.method_id.0.as_ref();
-        match method {
-            "apikey" => {
-                if let Ok(am) = self.auth_manager.write() {
-                    // Persisting the API key is handled by Codex core when reloading;
-                    // here we simply reload and check.
-                    am.reload();
-                    if am.auth().is_some() {
-                        return Ok(Default::default());
-                    }
-                }
-                Err(Error::auth_required().data("Failed to load API key auth"))
-            }
-            "chatgpt" => {
-                if let Ok(am) = self.auth_manager.write() {
-                    am.reload();
-                    if let Some(auth) = am.auth()
-                        && auth.mode == AuthMode::ChatGPT
-                    {
-                        return Ok(Default::default());
-                    }
-                }
-                Err(Error::auth_required()
-                    .data("ChatGPT login not found. Run `codex login` to connect your plan."))
-            }
-            "custom_provider" => {
-                // For custom providers, check if the provider is configured
-                if !utils::is_custom_provider(&self.config.model_provider_id) {
-                    return Err(Error::invalid_params().data(
-                        "Custom provider auth method is only available for custom providers",
-                    ));
-                }
-
-                // Verify the custom provider is properly configured in model_providers
-                if !self
-                    .config
-                    .model_providers
-                    .contains_key(&self.config.model_provider_id)
-                {
-                    return Err(Error::auth_required().data(format!(
-                        "Custom provider '{}' is not configured in model_providers",
-                        self.config.model_provider_id
-                    )));
-                }
-
-                // For custom providers, we assume authentication is handled via the provider's
-                // configuration (e.g., API keys in the provider settings). If auth_manager
-                // has valid auth, accept it; otherwise require configuration.
-                if let Ok(am) = self.auth_manager.write() {
-                    am.reload();
-                    if am.auth().is_some() {
-                        return Ok(Default::default());
-                    }
-                }
-
-                Err(Error::auth_required().data(format!(
-                    "Custom provider '{}' requires authentication. Please configure API credentials in your Codex config.",
-                    self.config.model_provider_id
-                )))
+        let result = match method {
+            "apikey" => self.login_with_api_key(args.meta.as_ref()).await,
+            "chatgpt" => self.login_with_chatgpt().await,
+            // Advertised under the provider id; `custom_provider` is kept for older clients.
+            m if m == self.config.model_provider_id || m == "custom_provider" => {
+                self.authenticate_custom_provider().await
             }
             other => Err(Error::invalid_params().data(format!("unknown auth method: {}", other))),
+        };
+        if result.is_ok() {
+            self.auth_invalidated.set(false);
         }
+        result
     }
 
     /// Create a new session with the given configuration.
@@ -231,6 +225,11 @@ impl CodexAgent {
     ) -> Result<NewSessionResponse, Error> {
         info!(?args, "Received new session request");
         let fs_session_id = Uuid::new_v4().to_string();
+        let disk_fallback =
+            utils::fs_disk_fallback(args.meta.as_ref()).unwrap_or(self.settings.fs.disk_fallback);
+        let fs_tools = self.effective_fs_tools(
+            utils::fs_tool_mode(args.meta.as_ref())?.unwrap_or(self.settings.fs.tools),
+        );
 
         let modes = utils::session_modes_for_config(&self.config);
         let current_mode = modes
@@ -238,7 +237,17 @@ impl CodexAgent {
             .map(|m| m.current_mode_id.clone())
             .unwrap_or_else(|| SessionModeId::new("auto"));
 
-        let session_config = self.build_session_config(&fs_session_id, args.mcp_servers)?;
+        self.sync_fs_policy(
+            &fs_session_id,
+            &self.config.sandbox_policy,
+            FsSessionMode {
+                read_only: utils::is_read_only_mode(&current_mode),
+                approval: self.config.approval_policy,
+                disk_fallback,
+            },
+        );
+        let session_config =
+            self.build_session_config(&fs_session_id, fs_tools, args.mcp_servers)?;
 
         let new_conv = self
             .session_manager
@@ -254,6 +263,7 @@ impl CodexAgent {
             }) => (conversation, conversation_id),
             Err(e) => {
                 warn!(error = %e, "Failed to create Codex conversation");
+                self.release_fs_session(&fs_session_id);
                 return Err(Error::into_internal_error(e));
             }
         };
@@ -266,6 +276,8 @@ impl CodexAgent {
             acp_session_id.clone(),
             SessionState::new(
                 fs_session_id.clone(),
+                disk_fallback,
+                fs_tools,
                 Some(conversation.clone()),
                 &self.config,
                 current_mode.clone(),
@@ -308,6 +320,14 @@ impl CodexAgent {
         )
     }
 
+    /// Forget a session whose conversation has shut down, unregistering its
+    /// FS session so the bridge stops accepting its token.
+    pub(super) fn close_session(&self, session_id: &SessionId) {
+        if let Some(state) = self.session_manager.remove_session(session_id) {
+            self.release_fs_session(&state.fs_session_id);
+        }
+    }
+
     /// Load an existing session and return its current state.
     pub(super) async fn load_session(
         &self,
@@ -381,6 +401,24 @@ impl CodexAgent {
             )
             .await?;
 
+        let fs_session = self
+            .session_manager
+            .sessions()
+            .borrow()
+            .get(args.session_id.0.as_ref())
+            .map(|state| (state.fs_session_id.clone(), state.fs_disk_fallback));
+        if let Some((fs_session_id, disk_fallback)) = fs_session {
+            self.sync_fs_policy(
+                &fs_session_id,
+                &preset.sandbox,
+                FsSessionMode {
+                    read_only: self.session_manager.is_read_only(&args.session_id),
+                    approval: preset.approval,
+                    disk_fallback,
+                },
+            );
+        }
+
         Ok(SetSessionModeResponse::default())
     }
 
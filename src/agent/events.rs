@@ -54,9 +54,13 @@ impl EventHandler {
         call_id: &str,
         invocation: &McpInvocation,
     ) -> SessionUpdate {
-        let (title, locations) = utils::describe_mcp_tool(invocation, &self.cwd);
+        let utils::McpToolDescription {
+            title,
+            kind,
+            locations,
+        } = utils::describe_mcp_tool(invocation, &self.cwd);
         let tool = ToolCall::new(ToolCallId::new(call_id), title)
-            .kind(ToolKind::Fetch)
+            .kind(kind)
             .status(ToolCallStatus::InProgress)
             .locations(locations)
             .raw_input(invocation.arguments.clone());
@@ -76,7 +80,15 @@ impl EventHandler {
         } else {
             ToolCallStatus::Failed
         };
-        let (title, locations) = utils::describe_mcp_tool(invocation, &self.cwd);
+        let utils::McpToolDescription {
+            title,
+            mut locations,
+            ..
+        } = utils::describe_mcp_tool(invocation, &self.cwd);
+        let result_locations = utils::mcp_result_locations(invocation, result, &self.cwd);
+        if !result_locations.is_empty() {
+            locations = result_locations;
+        }
         let fields = ToolCallUpdateFields::new()
             .status(status)
             .title(title)
@@ -1,6 +1,7 @@
 use agent_client_protocol::{self as acp, Agent};
 
 // Submodules
+mod auth;
 mod commands;
 mod config_builder;
 mod core;
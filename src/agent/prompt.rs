@@ -1,11 +1,12 @@
 use agent_client_protocol::{
     CancelNotification, ContentBlock, EmbeddedResourceResource, Error, ExtNotification, ExtRequest,
     ExtResponse, Plan, PlanEntry, PlanEntryPriority, PlanEntryStatus, PromptRequest,
-    PromptResponse, RequestPermissionResponse, SessionUpdate, StopReason, ToolCall, ToolCallId,
-    ToolCallStatus, ToolCallUpdate, ToolCallUpdateFields, ToolKind,
+    PromptResponse, RequestPermissionResponse, SessionId, SessionUpdate, StopReason, ToolCall,
+    ToolCallId, ToolCallStatus, ToolCallUpdate, ToolCallUpdateFields, ToolKind,
 };
-use codex_core::protocol::{
-    ErrorEvent, EventMsg, Op, PatchApplyEndEvent, StreamErrorEvent, WebSearchEndEvent,
+use codex_core::{
+    CodexConversation,
+    protocol::{ErrorEvent, EventMsg, Op, PatchApplyEndEvent, StreamErrorEvent, WebSearchEndEvent},
 };
 use codex_protocol::{
     plan_tool::{StepStatus, UpdatePlanArgs},
@@ -13,9 +14,12 @@ use codex_protocol::{
 };
 use serde_json::json;
 use tokio::sync::oneshot;
-use tracing::info;
+use tracing::{info, warn};
 
-use crate::agent::events::{EventHandler, ExecEndArgs, ReasoningAggregator};
+use crate::agent::{
+    events::{EventHandler, ExecEndArgs, ReasoningAggregator},
+    utils,
+};
 
 use super::{
     core::{ClientOp, CodexAgent},
@@ -32,6 +36,7 @@ impl CodexAgent {
     /// - Approval requests for commands and file operations
     pub(super) async fn prompt(&self, args: PromptRequest) -> Result<PromptResponse, Error> {
         info!(?args, "Received prompt request");
+        self.ensure_authenticated()?;
         let event_handler = EventHandler::new(
             self.config.cwd.clone(),
             self.session_manager.support_terminal(),
@@ -99,6 +104,7 @@ impl CodexAgent {
             Some(op) => op,
             None => Op::UserInput { items },
         };
+        let compacting = matches!(op, Op::Compact);
 
         // Enqueue work and then stream corresponding events back as ACP updates.
         let submit_id = conversation
@@ -317,10 +323,32 @@ impl CodexAgent {
                         .await?;
                 }
                 EventMsg::TokenCount(tc) => {
+                    if let Some(snapshot) = tc.rate_limits {
+                        let thresholds = self.settings.rate_limit_thresholds();
+                        let warnings = self
+                            .session_manager
+                            .with_session_state_mut(&args.session_id, |state| {
+                                let warnings =
+                                    state.rate_limit_warnings.observe(&snapshot, &thresholds);
+                                state.rate_limits = Some(snapshot);
+                                warnings
+                            })
+                            .unwrap_or_default();
+                        for warning in warnings {
+                            self.session_manager
+                                .send_message_chunk(&args.session_id, warning.into())
+                                .await?;
+                        }
+                    }
                     if let Some(info) = tc.info {
+                        let context_window = info
+                            .model_context_window
+                            .or(self.config.model_context_window);
                         self.session_manager
                             .with_session_state_mut(&args.session_id, |state| {
                                 state.token_usage = Some(info.total_token_usage.clone());
+                                state.context_tokens = Some(info.last_token_usage.total_tokens);
+                                state.context_window = context_window;
                             });
                     }
                 }
@@ -368,7 +396,11 @@ impl CodexAgent {
                         .send_message_chunk(&args.session_id, msg.into())
                         .await?;
                 }
-                EventMsg::ShutdownComplete | EventMsg::TurnAborted(_) => {
+                EventMsg::ShutdownComplete => {
+                    self.close_session(&args.session_id);
+                    break StopReason::Cancelled;
+                }
+                EventMsg::TurnAborted(_) => {
                     break StopReason::Cancelled;
                 }
                 // Ignore other events for now.
@@ -384,9 +416,101 @@ impl CodexAgent {
                 .await?;
         }
 
+        if matches!(stop_reason, StopReason::EndTurn) && !compacting {
+            self.auto_compact_if_needed(&args.session_id, &conversation)
+                .await?;
+        }
+
         Ok(PromptResponse::new(stop_reason))
     }
 
+    /// Compact the conversation between turns once the context window usage
+    /// crosses the auto-compaction threshold configured for the session's profile.
+    ///
+    /// The compaction is announced to the client the same way as `/compact`
+    /// and awaited so the next prompt starts from the summarized history.
+    async fn auto_compact_if_needed(
+        &self,
+        session_id: &SessionId,
+        conversation: &CodexConversation,
+    ) -> Result<(), Error> {
+        let snapshot = self
+            .session_manager
+            .sessions()
+            .borrow()
+            .get(session_id.0.as_ref())
+            .map(|state| {
+                (
+                    state.context_usage_percent(),
+                    utils::profile_name_for_model(
+                        &self.config,
+                        &self.profiles,
+                        state.current_model.as_deref(),
+                    ),
+                )
+            });
+        let Some((Some(usage), profile)) = snapshot else {
+            return Ok(());
+        };
+
+        let settings = self.settings.auto_compact_for(profile.as_deref());
+        if !settings.enabled || usage < settings.threshold_percent {
+            return Ok(());
+        }
+
+        info!(
+            session_id = %session_id.0,
+            usage,
+            threshold = settings.threshold_percent,
+            "Context window nearly full, compacting conversation"
+        );
+        self.session_manager
+            .with_session_state_mut(session_id, |state| {
+                state.token_usage = None;
+                state.context_tokens = None;
+            });
+        self.session_manager
+            .send_message_chunk(
+                session_id,
+                format!(
+                    "🧠 Context window is {usage}% full; compacting conversation to reduce context size...\n\n"
+                )
+                .into(),
+            )
+            .await?;
+
+        let submit_id = conversation
+            .submit(Op::Compact)
+            .await
+            .map_err(Error::into_internal_error)?;
+        loop {
+            let event = conversation
+                .next_event()
+                .await
+                .map_err(Error::into_internal_error)?;
+            if event.id != submit_id {
+                continue;
+            }
+            match event.msg {
+                EventMsg::Error(ErrorEvent { message, .. })
+                | EventMsg::StreamError(StreamErrorEvent { message, .. }) => {
+                    warn!(error = %message, "Automatic compaction reported an error");
+                    self.session_manager
+                        .send_message_chunk(session_id, format!("{message}\n\n").into())
+                        .await?;
+                }
+                EventMsg::TaskComplete(_) | EventMsg::TurnAborted(_) => break,
+                EventMsg::ShutdownComplete => {
+                    self.close_session(session_id);
+                    break;
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
     /// Cancel an ongoing prompt operation.
     pub(super) async fn cancel(&self, args: CancelNotification) -> Result<(), Error> {
         info!(?args, "Received cancel request");
@@ -401,10 +525,20 @@ impl CodexAgent {
 
     /// Handle extension method calls.
     ///
-    /// This is a placeholder for future extensions.
+    /// Supported methods:
+    /// - `codex/logout`: clear stored credentials and require re-authentication.
+    /// - `codex/account`: report the current auth mode, email and plan.
     pub(super) async fn ext_method(&self, args: ExtRequest) -> Result<ExtResponse, Error> {
         info!(method = %args.method, params = ?args.params, "Received extension method call");
-        let raw = serde_json::value::to_raw_value(&json!({"example": "response"}))?;
+        let response = match args.method.as_ref() {
+            "codex/logout" => {
+                let removed = self.logout()?;
+                json!({ "signed_out": true, "credentials_removed": removed })
+            }
+            "codex/account" => serde_json::to_value(self.account_info().await)?,
+            _ => return Err(Error::method_not_found()),
+        };
+        let raw = serde_json::value::to_raw_value(&response)?;
         Ok(ExtResponse::new(std::sync::Arc::from(raw)))
     }
 
@@ -7,7 +7,7 @@ use agent_client_protocol::{
 use codex_core::{
     CodexConversation, ConversationManager,
     config::Config,
-    protocol::{AskForApproval, Op, SandboxPolicy, TokenUsage},
+    protocol::{AskForApproval, Op, RateLimitSnapshot, SandboxPolicy, TokenUsage},
 };
 use codex_protocol::{ConversationId, openai_models::ReasoningEffort};
 use tokio::sync::{
@@ -15,7 +15,7 @@ use tokio::sync::{
     oneshot::{self, Sender},
 };
 
-use crate::agent::utils;
+use crate::{agent::utils, settings::FsToolMode};
 
 /// Per-session state shared across the agent runtime.
 ///
@@ -24,9 +24,16 @@ use crate::agent::utils;
 ///   from the ACP session id (which is the key in the `sessions` map).
 /// - `conversation` is lazily loaded on demand; `None` until first use.
 /// - Reasoning text is aggregated across streaming events.
+/// - `context_tokens`/`context_window` track how full the model context is,
+///   based on the most recent `TokenCount` event.
+/// - `rate_limits` is the latest ChatGPT plan usage snapshot, if any.
 #[derive(Clone)]
 pub struct SessionState {
     pub fs_session_id: String,
+    /// Whether the session's `acp_fs` reads and writes may fall back to disk.
+    pub fs_disk_fallback: bool,
+    /// Which file tools the session was given.
+    pub fs_tools: FsToolMode,
     pub conversation: Option<Arc<CodexConversation>>,
     pub current_approval: AskForApproval,
     pub current_sandbox: SandboxPolicy,
@@ -34,12 +41,18 @@ pub struct SessionState {
     pub current_model: Option<String>,
     pub current_effort: Option<ReasoningEffort>,
     pub token_usage: Option<TokenUsage>,
+    pub context_tokens: Option<i64>,
+    pub context_window: Option<i64>,
+    pub rate_limits: Option<RateLimitSnapshot>,
+    pub rate_limit_warnings: RateLimitWarnings,
 }
 
 impl SessionState {
     /// Create a new SessionState initialized from config.
     pub fn new(
         fs_session_id: String,
+        fs_disk_fallback: bool,
+        fs_tools: FsToolMode,
         conversation: Option<Arc<CodexConversation>>,
         config: &Config,
         current_mode: SessionModeId,
@@ -48,6 +61,8 @@ impl SessionState {
         let model_name = config.model.as_deref().unwrap_or("unknown");
         Self {
             fs_session_id,
+            fs_disk_fallback,
+            fs_tools,
             conversation,
             current_approval: config.approval_policy,
             current_sandbox: config.sandbox_policy.clone(),
@@ -55,8 +70,54 @@ impl SessionState {
             current_model: Some(format!("{}@{}", provider_id, model_name)),
             current_effort: config.model_reasoning_effort,
             token_usage: None,
+            context_tokens: None,
+            context_window: config.model_context_window,
+            rate_limits: None,
+            rate_limit_warnings: RateLimitWarnings::default(),
         }
     }
+
+    /// Percentage of the model context window filled by the last turn, if known.
+    pub fn context_usage_percent(&self) -> Option<u8> {
+        let tokens = self.context_tokens?;
+        let window = self.context_window.filter(|window| *window > 0)?;
+        let percent = tokens.max(0).saturating_mul(100) / window;
+        Some(percent.min(100) as u8)
+    }
+}
+
+/// Highest rate-limit warning threshold already reported for each window, so
+/// that every crossing is announced once until the window resets.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RateLimitWarnings {
+    primary: Option<u8>,
+    secondary: Option<u8>,
+}
+
+impl RateLimitWarnings {
+    /// Record a new snapshot and return warnings for newly crossed thresholds.
+    pub fn observe(&mut self, snapshot: &RateLimitSnapshot, thresholds: &[u8]) -> Vec<String> {
+        let mut warnings = Vec::new();
+        let windows = [
+            (snapshot.primary.as_ref(), &mut self.primary),
+            (snapshot.secondary.as_ref(), &mut self.secondary),
+        ];
+        for (window, warned) in windows {
+            let Some(window) = window else {
+                continue;
+            };
+            let crossed = thresholds
+                .iter()
+                .copied()
+                .filter(|threshold| window.used_percent >= f64::from(*threshold))
+                .max();
+            if crossed > *warned {
+                warnings.push(utils::rate_limit_warning(window));
+            }
+            *warned = crossed;
+        }
+        warnings
+    }
 }
 
 /// Manages session state, conversations, and client communication.
@@ -104,6 +165,11 @@ impl SessionManager {
         sessions.get_mut(key).map(f)
     }
 
+    /// Remove a session whose conversation has ended.
+    pub fn remove_session(&self, session_id: &SessionId) -> Option<SessionState> {
+        self.sessions.borrow_mut().remove(session_id.0.as_ref())
+    }
+
     /// Shared internal helper to resolve a session state by ACP id or FS id.
     fn resolve_state<'a>(
         sessions: &'a HashMap<String, SessionState>,
@@ -2,18 +2,22 @@ use std::{
     collections::{HashMap, HashSet},
     path::{Path, PathBuf},
     sync::LazyLock,
+    time::{SystemTime, UNIX_EPOCH},
 };
 
 use agent_client_protocol::{
-    ModelId, ModelInfo, SessionMode, SessionModeId, SessionModeState, ToolCallLocation, ToolKind,
+    Error, ModelId, ModelInfo, SessionMode, SessionModeId, SessionModeState, ToolCallLocation,
+    ToolKind,
 };
 use codex_common::approval_presets::{ApprovalPreset, builtin_approval_presets};
 use codex_core::{
     config::{Config, profile::ConfigProfile},
-    protocol::McpInvocation,
+    protocol::{McpInvocation, RateLimitWindow},
 };
 use codex_protocol::{openai_models::ReasoningEffort, parse_command::ParsedCommand};
 
+use crate::settings::FsToolMode;
+
 /// All available approval presets used to derive ACP session modes.
 static APPROVAL_PRESETS: LazyLock<Vec<ApprovalPreset>> = LazyLock::new(builtin_approval_presets);
 
@@ -33,6 +37,18 @@ pub struct FsToolMetadata {
     pub display_path: String,
     pub location_path: PathBuf,
     pub line: Option<u32>,
+    /// Glob or search pattern, for `glob` and `search_files` calls.
+    pub pattern: Option<String>,
+    /// Display and location path of the target, for `move_file` calls.
+    pub destination: Option<(String, PathBuf)>,
+}
+
+/// Summary of an MCP tool call used by ACP updates.
+#[derive(Clone, Debug)]
+pub struct McpToolDescription {
+    pub title: String,
+    pub kind: ToolKind,
+    pub locations: Vec<ToolCallLocation>,
 }
 
 /// Format a tool/command call for display in the client, summarizing a
@@ -119,51 +135,122 @@ pub fn fs_tool_metadata(invocation: &McpInvocation, cwd: &Path) -> Option<FsTool
         return None;
     }
 
-    match invocation.tool.as_str() {
-        "read_text_file" | "write_text_file" | "edit_text_file" => {}
-        _ => return None,
-    }
-
     let args = invocation.arguments.as_ref()?.as_object()?;
-    let path = args.get("path")?.as_str()?.to_string();
+    let path = match invocation.tool.as_str() {
+        "read_text_file" | "write_text_file" | "edit_text_file" | "insert_at_line"
+        | "read_notebook" | "edit_notebook" | "replace_line_range" | "delete_file"
+        | "create_directory" => args.get("path")?.as_str()?.to_string(),
+        "move_file" => args.get("source")?.as_str()?.to_string(),
+        // Listings default to the workspace root.
+        "list_directory" | "glob" | "search_files" => args
+            .get("path")
+            .and_then(|value| value.as_str())
+            .unwrap_or(".")
+            .to_string(),
+        _ => return None,
+    };
     let line = args
         .get("line")
+        .or_else(|| args.get("start_line"))
         .and_then(|value| value.as_u64())
         .map(|value| value as u32);
+    let pattern = args
+        .get("pattern")
+        .and_then(|value| value.as_str())
+        .map(str::to_string);
+    let destination = args
+        .get("destination")
+        .and_then(|value| value.as_str())
+        .map(|destination| (display_fs_path(cwd, destination), cwd.join(destination)));
     let display_path = display_fs_path(cwd, &path);
-    let location_path = PathBuf::from(&path);
+    let location_path = cwd.join(&path);
 
     Some(FsToolMetadata {
         display_path,
         location_path,
         line,
+        pattern,
+        destination,
     })
 }
 
-/// Describe an MCP tool call for ACP by creating a human-friendly title and
-/// mapping to zero or more `ToolCallLocation`s. When the invocation is an
-/// FS tool, the title includes the display path and a single location entry.
-pub fn describe_mcp_tool(
-    invocation: &McpInvocation,
-    cwd: &Path,
-) -> (String, Vec<ToolCallLocation>) {
-    if let Some(metadata) = fs_tool_metadata(invocation, cwd) {
-        let location = ToolCallLocation::new(metadata.location_path).line(metadata.line);
+/// ACP tool kind for an MCP tool call. `acp_fs` listings are searches and
+/// file-management tools map to their own kinds; everything else is reported
+/// as a fetch.
+pub fn mcp_tool_kind(invocation: &McpInvocation) -> ToolKind {
+    match (invocation.server.as_str(), invocation.tool.as_str()) {
+        ("acp_fs", "list_directory" | "glob" | "search_files") => ToolKind::Search,
+        ("acp_fs", "delete_file") => ToolKind::Delete,
+        ("acp_fs", "move_file") => ToolKind::Move,
         (
-            format!(
-                "{}.{} ({})",
-                invocation.server, invocation.tool, metadata.display_path
-            ),
-            vec![location],
-        )
+            "acp_fs",
+            "create_directory" | "insert_at_line" | "replace_line_range" | "apply_edit_set"
+            | "apply_patch" | "edit_notebook",
+        ) => ToolKind::Edit,
+        _ => ToolKind::Fetch,
+    }
+}
+
+/// Describe an MCP tool call for ACP by creating a human-friendly title,
+/// picking its tool kind and mapping it to zero or more `ToolCallLocation`s.
+/// When the invocation is an FS tool, the title includes the display path
+/// (and glob pattern or move destination) and its locations.
+pub fn describe_mcp_tool(invocation: &McpInvocation, cwd: &Path) -> McpToolDescription {
+    let kind = mcp_tool_kind(invocation);
+    if let Some(metadata) = fs_tool_metadata(invocation, cwd) {
+        let mut locations = vec![ToolCallLocation::new(metadata.location_path).line(metadata.line)];
+        let target = match (metadata.pattern, metadata.destination) {
+            (Some(pattern), _) => format!("{pattern} in {}", metadata.display_path),
+            (None, Some((display_destination, destination))) => {
+                locations.push(ToolCallLocation::new(destination));
+                format!("{} → {display_destination}", metadata.display_path)
+            }
+            (None, None) => metadata.display_path,
+        };
+        McpToolDescription {
+            title: format!("{}.{} ({target})", invocation.server, invocation.tool),
+            kind,
+            locations,
+        }
     } else {
-        (
-            format!("{}.{}", invocation.server, invocation.tool),
-            Vec::new(),
-        )
+        McpToolDescription {
+            title: format!("{}.{}", invocation.server, invocation.tool),
+            kind,
+            locations: Vec::new(),
+        }
     }
 }
 
+/// Locations reported in an `acp_fs` tool result, such as `search_files`
+/// matches, resolved against `cwd`. Empty for other tools.
+pub fn mcp_result_locations(
+    invocation: &McpInvocation,
+    result: &serde_json::Value,
+    cwd: &Path,
+) -> Vec<ToolCallLocation> {
+    if invocation.server != "acp_fs" || invocation.tool != "search_files" {
+        return Vec::new();
+    }
+    // `result` is a serialized `Result<CallToolResult, String>`.
+    let result = result.get("Ok").unwrap_or(result);
+    let Some(content) = result.get("content").and_then(|value| value.as_array()) else {
+        return Vec::new();
+    };
+    content
+        .iter()
+        .filter_map(|item| item.pointer("/_meta/codex_fs_search/matches")?.as_array())
+        .flatten()
+        .filter_map(|found| {
+            let path = found.get("path")?.as_str()?;
+            let line = found
+                .get("line")
+                .and_then(|value| value.as_u64())
+                .map(|value| value as u32);
+            Some(ToolCallLocation::new(cwd.join(path)).line(line))
+        })
+        .collect()
+}
+
 /// Build the ACP `SessionModeState` (current + available) from a Codex `Config`.
 pub fn session_modes_for_config(config: &Config) -> Option<SessionModeState> {
     let current_mode_id = current_mode_id_for_config(config)?;
@@ -191,6 +278,28 @@ pub fn is_read_only_mode(mode_id: &SessionModeId) -> bool {
     mode_id.0.as_ref() == "read-only"
 }
 
+/// `_meta.acp_fs.disk_fallback` of a new-session request, if set.
+pub fn fs_disk_fallback(meta: Option<&serde_json::Map<String, serde_json::Value>>) -> Option<bool> {
+    meta?.get("acp_fs")?.get("disk_fallback")?.as_bool()
+}
+
+/// `_meta.acp_fs.tools` of a new-session request, if set.
+pub fn fs_tool_mode(
+    meta: Option<&serde_json::Map<String, serde_json::Value>>,
+) -> Result<Option<FsToolMode>, Error> {
+    let Some(value) = meta
+        .and_then(|meta| meta.get("acp_fs"))
+        .and_then(|acp_fs| acp_fs.get("tools"))
+    else {
+        return Ok(None);
+    };
+    serde_json::from_value(value.clone()).map(Some).map_err(|_| {
+        Error::invalid_params().data(format!(
+            "invalid _meta.acp_fs.tools {value}; expected \"both\", \"acp_fs_only\" or \"native_only\""
+        ))
+    })
+}
+
 /// Available modes derived from approval presets.
 pub fn available_modes() -> Vec<SessionMode> {
     APPROVAL_PRESETS
@@ -217,6 +326,33 @@ pub fn current_model_id_from_config(config: &Config) -> ModelId {
     ModelId::new(format!("{}@{}", config.model_provider_id, model_name))
 }
 
+/// Return the name of the Codex profile that selects `model_id` (`provider@model`).
+///
+/// Falls back to the profile Codex was started with when the session still uses
+/// the configured model or no profile matches.
+pub fn profile_name_for_model(
+    config: &Config,
+    profiles: &HashMap<String, ConfigProfile>,
+    model_id: Option<&str>,
+) -> Option<String> {
+    let configured = current_model_id_from_config(config);
+    let switched = model_id.filter(|id| *id != configured.0.as_ref());
+    let matched =
+        switched
+            .and_then(|id| id.split_once('@'))
+            .and_then(|(provider_id, model_name)| {
+                profiles
+                    .iter()
+                    .filter(|(_, profile)| {
+                        profile.model_provider.as_deref() == Some(provider_id)
+                            && profile.model.as_deref() == Some(model_name)
+                    })
+                    .map(|(name, _)| name)
+                    .min()
+            });
+    matched.cloned().or_else(|| config.active_profile.clone())
+}
+
 /// Build a `ModelInfo` for display to the client.
 fn build_model_info(config: &Config, provider_id: &str, model_name: &str) -> Option<ModelInfo> {
     let provider_info = config.model_providers.get(provider_id)?;
@@ -323,3 +459,65 @@ pub fn parse_and_validate_model(
 
     None
 }
+
+/// Human-friendly label for a rate-limit window length (e.g. `5h`, `weekly`).
+pub fn rate_limit_window_label(window_minutes: Option<i64>) -> Option<String> {
+    let minutes = window_minutes.filter(|minutes| *minutes > 0)?;
+    Some(match minutes {
+        10_080 => "weekly".to_string(),
+        m if m % 1_440 == 0 => format!("{}d", m / 1_440),
+        m if m % 60 == 0 => format!("{}h", m / 60),
+        m => format!("{m}m"),
+    })
+}
+
+/// Describe when a rate-limit window resets relative to now (e.g. `in 2h 5m`).
+pub fn format_rate_limit_reset(resets_at: Option<i64>) -> Option<String> {
+    let resets_at = resets_at?;
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).ok()?.as_secs() as i64;
+    let remaining = resets_at.saturating_sub(now).max(0);
+    let (days, hours, minutes) = (
+        remaining / 86_400,
+        remaining % 86_400 / 3_600,
+        remaining % 3_600 / 60,
+    );
+    Some(if days > 0 {
+        format!("in {days}d {hours}h")
+    } else if hours > 0 {
+        format!("in {hours}h {minutes}m")
+    } else {
+        format!("in {}m", minutes.max(1))
+    })
+}
+
+/// Summarize a rate-limit window for `/status`, e.g. `42% used (5h window, resets in 2h 3m)`.
+pub fn format_rate_limit_window(window: &RateLimitWindow) -> String {
+    let mut details = Vec::new();
+    if let Some(label) = rate_limit_window_label(window.window_minutes) {
+        details.push(format!("{label} window"));
+    }
+    if let Some(reset) = format_rate_limit_reset(window.resets_at) {
+        details.push(format!("resets {reset}"));
+    }
+    if details.is_empty() {
+        format!("{:.0}% used", window.used_percent)
+    } else {
+        format!("{:.0}% used ({})", window.used_percent, details.join(", "))
+    }
+}
+
+/// Build the message chunk warning the user that a rate-limit window is filling up.
+pub fn rate_limit_warning(window: &RateLimitWindow) -> String {
+    let label = rate_limit_window_label(window.window_minutes)
+        .map(|label| format!("{label} "))
+        .unwrap_or_default();
+    let mut warning = format!(
+        "⚠️ You have used {:.0}% of your {label}rate limit",
+        window.used_percent
+    );
+    if let Some(reset) = format_rate_limit_reset(window.resets_at) {
+        warning.push_str(&format!(" (resets {reset})"));
+    }
+    warning.push_str(".\n\n");
+    warning
+}
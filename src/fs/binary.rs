@@ -0,0 +1,79 @@
+//! Binary file detection for the bridge's `read` operation.
+//!
+//! Binary files are described instead of being decoded as text. Images small
+//! enough to inline are returned base64-encoded so they can be passed to the
+//! model as MCP image content.
+
+use std::path::Path;
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// Bytes inspected for NUL when deciding whether a file is binary.
+pub(crate) const BINARY_SNIFF_BYTES: usize = 8 * 1024;
+/// Larger images are summarized instead of inlined.
+const MAX_INLINE_IMAGE_BYTES: usize = 5 * 1024 * 1024;
+/// Image types models accept as input.
+const INLINE_IMAGE_TYPES: &[&str] = &["image/png", "image/jpeg", "image/gif", "image/webp"];
+
+/// Description of a binary file returned instead of its content.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct BinarySummary {
+    pub size: u64,
+    pub mime_type: String,
+    /// Hex SHA-256 of the file.
+    pub sha256: String,
+    /// Base64 image data, for images small enough to inline.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image: Option<String>,
+}
+
+/// Summarize `bytes` if they are not text.
+pub(crate) fn summarize(path: &Path, bytes: &[u8]) -> Option<BinarySummary> {
+    if !is_binary(path, bytes) {
+        return None;
+    }
+    let mime_type = mime_type(path);
+    let image = (INLINE_IMAGE_TYPES.contains(&mime_type.as_str())
+        && bytes.len() <= MAX_INLINE_IMAGE_BYTES)
+        .then(|| STANDARD.encode(bytes));
+    Some(BinarySummary {
+        size: bytes.len() as u64,
+        sha256: hex_digest(Sha256::digest(bytes).as_slice()),
+        mime_type,
+        image,
+    })
+}
+
+/// Summary of a binary file too large to load, from its streamed hash.
+pub(crate) fn summarize_large(path: &Path, size: u64, sha256: &[u8]) -> BinarySummary {
+    BinarySummary {
+        size,
+        mime_type: mime_type(path),
+        sha256: hex_digest(sha256),
+        image: None,
+    }
+}
+
+/// Whether a file starting with `head` is binary: raster images by
+/// extension, and anything with a NUL byte near the start that is not UTF-16.
+pub(crate) fn is_binary(path: &Path, head: &[u8]) -> bool {
+    let is_image = mime_guess::from_path(path)
+        .first()
+        .is_some_and(|mime| mime.type_() == mime_guess::mime::IMAGE && mime.subtype() != "svg");
+    let utf16 = head.starts_with(&[0xFF, 0xFE]) || head.starts_with(&[0xFE, 0xFF]);
+    let has_nul = head[..head.len().min(BINARY_SNIFF_BYTES)].contains(&0);
+    is_image || (has_nul && !utf16)
+}
+
+fn mime_type(path: &Path) -> String {
+    mime_guess::from_path(path)
+        .first()
+        .map(|mime| mime.essence_str().to_string())
+        .unwrap_or_else(|| "application/octet-stream".to_string())
+}
+
+fn hex_digest(digest: &[u8]) -> String {
+    digest.iter().map(|byte| format!("{byte:02x}")).collect()
+}
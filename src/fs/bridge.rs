@@ -1,42 +1,132 @@
 use std::{
-    net::SocketAddr,
+    cell::{Cell, RefCell},
+    collections::{HashMap, HashSet, hash_map::Entry},
     path::{Path, PathBuf},
-    sync::Arc,
+    rc::Rc,
+    sync::{Arc, RwLock},
 };
 
-use agent_client_protocol::{ReadTextFileRequest, SessionId, WriteTextFileRequest};
+use agent_client_protocol::{
+    Diff, PermissionOption, PermissionOptionKind, ReadTextFileRequest, RequestPermissionOutcome,
+    RequestPermissionRequest, SessionId, ToolCallContent, ToolCallId, ToolCallLocation,
+    ToolCallStatus, ToolCallUpdate, ToolCallUpdateFields, ToolKind, WriteTextFileRequest,
+};
+use codex_core::protocol::AskForApproval;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use tokio::{
     fs,
-    io::{AsyncBufReadExt, AsyncWriteExt, BufReader, BufWriter},
-    net::{TcpListener, TcpStream},
-    sync::{mpsc::UnboundedSender, oneshot},
+    io::{
+        AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader, BufWriter,
+    },
+    sync::{
+        mpsc::{self, UnboundedSender},
+        oneshot,
+    },
     task,
 };
 use tracing::{debug, error, warn};
+use uuid::Uuid;
 
 use crate::agent::ClientOp;
 
+use super::{
+    binary::{self, BINARY_SNIFF_BYTES, BinarySummary},
+    encoding::{self, TextFormat},
+    line_index::{LARGE_FILE_BYTES, LineIndexCache},
+    manage,
+    policy::{self, FsAccess, FsPolicy, PolicyDecision},
+    search::{self, ContentSource, Search, SearchOptions, SearchPage},
+    walk::{self, ListEntry, ListingPage},
+    watch::ChangeTracker,
+};
+
+/// Entries per listing page when the caller does not ask for a limit.
+pub const DEFAULT_LIST_LIMIT: u32 = 500;
+/// Deepest `list_directory` recursion a caller may request.
+const MAX_LIST_DEPTH: u32 = 10;
+/// Matches per search page when the caller does not ask for a limit.
+pub const DEFAULT_SEARCH_LIMIT: u32 = 100;
+/// Most context lines a search may request around each match.
+const MAX_SEARCH_CONTEXT: u32 = 10;
+/// Searches over more files than this read from disk rather than one client
+/// request per file.
+const MAX_CLIENT_SEARCH_FILES: usize = 200;
+
+/// File name of the bridge socket inside its private directory.
+#[cfg(unix)]
+const SOCKET_FILE_NAME: &str = "bridge.sock";
+
+/// Local endpoint of the FS bridge.
+///
+/// On Unix the bridge listens on a socket inside a private (0700) temporary
+/// directory; other platforms fall back to a loopback TCP port. Either way a
+/// client must present its session's token before sending requests.
 #[derive(Clone)]
 pub struct FsBridge {
-    address: SocketAddr,
-    _inner: Arc<FsBridgeInner>,
+    address: String,
+    inner: Arc<FsBridgeInner>,
+    /// Keeps the socket directory alive for as long as the bridge exists.
+    #[cfg(unix)]
+    _socket_dir: Arc<tempfile::TempDir>,
 }
 
 impl FsBridge {
+    #[cfg(unix)]
+    pub async fn start(
+        client_tx: UnboundedSender<ClientOp>,
+        workspace_root: PathBuf,
+    ) -> anyhow::Result<Arc<FsBridge>> {
+        use std::{fs::Permissions, os::unix::fs::PermissionsExt};
+
+        use tokio::net::UnixListener;
+
+        let socket_dir = tempfile::Builder::new().prefix("codex-acp-fs-").tempdir()?;
+        std::fs::set_permissions(socket_dir.path(), Permissions::from_mode(0o700))?;
+        let socket_path = socket_dir.path().join(SOCKET_FILE_NAME);
+        let listener = UnixListener::bind(&socket_path)?;
+        std::fs::set_permissions(&socket_path, Permissions::from_mode(0o600))?;
+
+        let inner = Arc::new(FsBridgeInner::new(client_tx, workspace_root));
+        let accept_inner = inner.clone();
+        task::spawn_local(async move {
+            loop {
+                match listener.accept().await {
+                    Ok((stream, _)) => {
+                        let connection_inner = accept_inner.clone();
+                        task::spawn_local(async move {
+                            if let Err(err) = handle_connection(stream, connection_inner).await {
+                                warn!(error = %err, "fs bridge connection errored");
+                            }
+                        });
+                    }
+                    Err(err) => {
+                        error!(error = %err, "fs bridge listener failed");
+                        break;
+                    }
+                }
+            }
+        });
+
+        Ok(Arc::new(FsBridge {
+            address: socket_path.to_string_lossy().into_owned(),
+            inner,
+            _socket_dir: Arc::new(socket_dir),
+        }))
+    }
+
+    #[cfg(not(unix))]
     pub async fn start(
         client_tx: UnboundedSender<ClientOp>,
         workspace_root: PathBuf,
     ) -> anyhow::Result<Arc<FsBridge>> {
+        use tokio::net::TcpListener;
+
         let listener = TcpListener::bind(("127.0.0.1", 0)).await?;
         let address = listener.local_addr()?;
-        let inner = Arc::new(FsBridgeInner {
-            client_tx,
-            workspace_root,
-        });
+        let inner = Arc::new(FsBridgeInner::new(client_tx, workspace_root));
         let accept_inner = inner.clone();
         task::spawn_local(async move {
-            let listener = listener;
             loop {
                 match listener.accept().await {
                     Ok((stream, addr)) => {
@@ -56,13 +146,94 @@ impl FsBridge {
         });
 
         Ok(Arc::new(FsBridge {
-            address,
-            _inner: inner,
+            address: address.to_string(),
+            inner,
         }))
     }
 
-    pub fn address(&self) -> SocketAddr {
-        self.address
+    /// Socket path (Unix) or loopback address the MCP server connects to.
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+
+    /// Token the `acp_fs` server of an FS session must present to the bridge.
+    ///
+    /// `None` until the session is registered via [`FsBridge::set_session_policy`].
+    pub fn session_token(&self, fs_session_id: &str) -> Option<String> {
+        let sessions = self.inner.sessions.read().ok()?;
+        sessions
+            .get(fs_session_id)
+            .map(|session| session.token.clone())
+    }
+
+    /// Register or update the confinement policy and mode for an FS session id.
+    ///
+    /// Reads and writes the user already approved for the session are kept.
+    /// A newly registered session starts without any tracked files.
+    pub fn set_session_policy(&self, fs_session_id: &str, policy: FsPolicy, mode: FsSessionMode) {
+        let Ok(mut sessions) = self.inner.sessions.write() else {
+            return;
+        };
+        match sessions.entry(fs_session_id.to_string()) {
+            Entry::Occupied(mut entry) => {
+                let session = entry.get_mut();
+                session.policy = policy;
+                session.mode = mode;
+            }
+            Entry::Vacant(entry) => {
+                self.inner.changes.forget(fs_session_id);
+                entry.insert(SessionFsState {
+                    policy,
+                    mode,
+                    token: generate_token(),
+                    approved_reads: HashSet::new(),
+                    approved_writes: HashSet::new(),
+                    grants: HashMap::new(),
+                });
+            }
+        }
+    }
+
+    /// Unregister an FS session once its conversation is gone.
+    ///
+    /// The session's token stops being accepted, so a leftover `acp_fs`
+    /// server can no longer reach the bridge, and the files tracked for it
+    /// are forgotten.
+    pub fn remove_session(&self, fs_session_id: &str) {
+        if let Ok(mut sessions) = self.inner.sessions.write() {
+            sessions.remove(fs_session_id);
+        }
+        self.inner.changes.forget(fs_session_id);
+    }
+}
+
+/// Session mode settings that gate file-management ops (delete, move, mkdir)
+/// and say where file content may come from.
+#[derive(Debug, Clone, Copy)]
+pub struct FsSessionMode {
+    /// Mirrors `SessionManager::is_read_only`; all management ops are refused.
+    pub read_only: bool,
+    pub approval: AskForApproval,
+    /// Whether reads and writes may go to disk when the client fails them.
+    /// When off, a failing client read or write is an error, and large files
+    /// are read through the client too, so the session never sees saved
+    /// content in place of an unsaved editor buffer.
+    pub disk_fallback: bool,
+}
+
+impl FsSessionMode {
+    /// Whether `op` needs the user's approval under this mode. Deletes and
+    /// moves prompt unless approvals are disabled, writes prompt under
+    /// `on-request` and `untrusted`, and creating a directory only prompts
+    /// when every action must be approved.
+    fn requires_approval(&self, op: BridgeOp) -> bool {
+        match (op, self.approval) {
+            (_, AskForApproval::Never) => false,
+            (BridgeOp::Delete | BridgeOp::Move, _) => true,
+            (BridgeOp::Write, AskForApproval::OnRequest) => true,
+            (_, AskForApproval::UnlessTrusted) => true,
+            _ => false,
+        }
     }
 }
 
@@ -71,27 +242,211 @@ impl FsBridge {
 pub enum BridgeOp {
     Read,
     Write,
+    ListDirectory,
+    Glob,
+    Search,
+    Delete,
+    Move,
+    CreateDirectory,
+    /// Check and approve every change of an edit set at once.
+    ApproveSet,
+    /// Release the grant of an applied or abandoned edit set.
+    EndSet,
+    /// Give up on an earlier request of the same connection, e.g. after the
+    /// client timed out waiting for it. A write, delete, move or edit set
+    /// approval that has not been carried out yet is then refused, even if
+    /// the user approves it later.
+    Cancel,
 }
 
-#[derive(Debug, Deserialize)]
-struct BridgeRequest {
-    id: u64,
-    session_id: String,
-    op: BridgeOp,
-    path: String,
-    line: Option<u32>,
-    limit: Option<u32>,
-    content: Option<String>,
+impl BridgeOp {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            BridgeOp::Read => "read",
+            BridgeOp::Write => "write",
+            BridgeOp::ListDirectory => "list_directory",
+            BridgeOp::Glob => "glob",
+            BridgeOp::Search => "search",
+            BridgeOp::Delete => "delete",
+            BridgeOp::Move => "move",
+            BridgeOp::CreateDirectory => "create_directory",
+            BridgeOp::ApproveSet => "approve_set",
+            BridgeOp::EndSet => "end_set",
+            BridgeOp::Cancel => "cancel",
+        }
+    }
+
+    pub fn access(self) -> FsAccess {
+        match self {
+            BridgeOp::Read
+            | BridgeOp::ListDirectory
+            | BridgeOp::Glob
+            | BridgeOp::Search
+            | BridgeOp::Cancel => FsAccess::Read,
+            BridgeOp::Write
+            | BridgeOp::Delete
+            | BridgeOp::Move
+            | BridgeOp::CreateDirectory
+            | BridgeOp::ApproveSet
+            | BridgeOp::EndSet => FsAccess::Write,
+        }
+    }
+
+    /// Whether the op acts on what a symlink points to, rather than on the
+    /// link itself as deleting or moving it does.
+    fn follows_links(self) -> bool {
+        !matches!(self, BridgeOp::Delete | BridgeOp::Move)
+    }
+
+    /// Ops that change the file tree without going through the client.
+    fn is_management(self) -> bool {
+        matches!(
+            self,
+            BridgeOp::Delete | BridgeOp::Move | BridgeOp::CreateDirectory
+        )
+    }
+}
+
+/// Where the content of a read came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReadSource {
+    /// The client, including unsaved changes in its editor buffers.
+    Client,
+    /// The file as saved on disk.
+    Disk,
+}
+
+/// First message on every bridge connection; binds it to one FS session.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct BridgeHandshake {
+    pub session_id: String,
+    pub token: String,
 }
 
-#[derive(Debug, Serialize)]
-struct BridgeResponse {
-    id: u64,
-    success: bool,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    content: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    error: Option<String>,
+/// A request on an authenticated connection. Requests are handled
+/// concurrently; responses carry the request `id` and may arrive out of order.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct BridgeRequest {
+    pub id: u64,
+    pub session_id: String,
+    pub op: BridgeOp,
+    #[serde(flatten)]
+    pub args: BridgeArgs,
+}
+
+/// Operation arguments of a [`BridgeRequest`]; fields an op does not use stay `None`.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct BridgeArgs {
+    pub path: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub line: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+    /// Entries to skip before the first returned one (listings).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub offset: Option<u32>,
+    /// How many directory levels to descend (`list_directory`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub depth: Option<u32>,
+    /// Glob pattern relative to `path` (`glob`), or the text to find (`search`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pattern: Option<String>,
+    /// Match `pattern` as plain text rather than a regex (`search`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub literal: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub case_insensitive: Option<bool>,
+    /// Only search files matching one of these globs (`search`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub include: Option<Vec<String>>,
+    /// Skip files matching one of these globs (`search`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exclude: Option<Vec<String>>,
+    /// Context lines around each match (`search`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context: Option<u32>,
+    /// Target path (`move`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub destination: Option<String>,
+    /// Delete non-empty directories (`delete`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recursive: Option<bool>,
+    /// Replace an existing destination (`move`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub overwrite: Option<bool>,
+    /// Refuse the write unless the file is still at this version (`write`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expected_version: Option<String>,
+    /// Keep `content`'s final newline, or lack of one, instead of the file's
+    /// (`write`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exact_newline: Option<bool>,
+    /// Read the last this many lines instead of starting at `line` (`read`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tail: Option<u32>,
+    /// Every change of an edit set (`approve_set`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub changes: Option<Vec<SetChange>>,
+    /// Grant from `approve_set` that covers this write or delete, so it does
+    /// not prompt again (`write`, `delete`, `end_set`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub grant: Option<String>,
+    /// Id of the request to give up on (`cancel`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request: Option<u64>,
+}
+
+/// One file of an edit set submitted for approval.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SetChange {
+    pub path: String,
+    /// New content, or `None` to delete the file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+}
+
+impl BridgeArgs {
+    pub fn new(path: impl Into<String>) -> Self {
+        Self {
+            path: path.into(),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct BridgeResponse {
+    pub id: u64,
+    pub success: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Content version of the file after a read or write.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    /// Set instead of `content` when a read hits a binary file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub binary: Option<BinarySummary>,
+    /// 1-based line the returned text of a read starts at.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start_line: Option<u64>,
+    /// Lines in the whole file, for reads.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total_lines: Option<u64>,
+    /// Where a read's content came from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<ReadSource>,
+    /// Why a read came from disk rather than the client.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fallback_reason: Option<String>,
+    /// Files the session read or wrote that have since changed outside it,
+    /// as the session named them.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub changed: Vec<String>,
 }
 
 impl BridgeResponse {
@@ -102,6 +457,44 @@ impl BridgeResponse {
             success: true,
             content,
             error: None,
+            version: None,
+            binary: None,
+            start_line: None,
+            total_lines: None,
+            source: None,
+            fallback_reason: None,
+            changed: Vec::new(),
+        }
+    }
+
+    /// Attach the file's content version to a response.
+    fn with_version(mut self, version: String) -> Self {
+        self.version = Some(version);
+        self
+    }
+
+    /// Attach where a read's window starts and the file's line count.
+    fn with_range(mut self, start_line: u64, total_lines: u64) -> Self {
+        self.start_line = Some(start_line);
+        self.total_lines = Some(total_lines);
+        self
+    }
+
+    /// Attach where a read's content came from.
+    fn with_source(mut self, source: ReadSource, fallback_reason: Option<String>) -> Self {
+        self.source = Some(source);
+        self.fallback_reason = fallback_reason;
+        self
+    }
+
+    fn binary(id: u64, summary: BinarySummary) -> Self {
+        // The first 16 hex digits of the SHA-256, like `content_version`.
+        let version = summary.sha256[..16].to_string();
+        Self {
+            binary: Some(summary),
+            ..Self::success(id, None)
+                .with_version(version)
+                .with_source(ReadSource::Disk, Some(BINARY_FROM_DISK.to_string()))
         }
     }
 
@@ -112,6 +505,13 @@ impl BridgeResponse {
             success: false,
             content: None,
             error: Some(error),
+            version: None,
+            binary: None,
+            start_line: None,
+            total_lines: None,
+            source: None,
+            fallback_reason: None,
+            changed: Vec::new(),
         }
     }
 }
@@ -119,13 +519,98 @@ impl BridgeResponse {
 struct FsBridgeInner {
     client_tx: UnboundedSender<ClientOp>,
     workspace_root: PathBuf,
+    sessions: RwLock<HashMap<String, SessionFsState>>,
+    line_indexes: Arc<LineIndexCache>,
+    changes: Arc<ChangeTracker>,
+}
+
+impl FsBridgeInner {
+    fn new(client_tx: UnboundedSender<ClientOp>, workspace_root: PathBuf) -> Self {
+        // Canonical like the paths the policy hands back, so comparisons
+        // against it hold.
+        let workspace_root =
+            policy::canonicalize_lenient(&workspace_root).unwrap_or(workspace_root);
+        Self {
+            client_tx,
+            workspace_root,
+            sessions: RwLock::new(HashMap::new()),
+            line_indexes: Arc::default(),
+            changes: ChangeTracker::start(),
+        }
+    }
+
+    /// Check a handshake against the token registered for its session.
+    fn verify_handshake(&self, handshake: &BridgeHandshake) -> bool {
+        let Ok(sessions) = self.sessions.read() else {
+            return false;
+        };
+        sessions
+            .get(&handshake.session_id)
+            .is_some_and(|session| constant_time_eq(&session.token, &handshake.token))
+    }
+}
+
+/// Confinement state of one FS session.
+struct SessionFsState {
+    policy: FsPolicy,
+    mode: FsSessionMode,
+    /// Secret the session's `acp_fs` server authenticates with.
+    token: String,
+    /// Canonical paths the user allowed reading for the rest of the session.
+    approved_reads: HashSet<PathBuf>,
+    /// Paths the user allowed writing for the rest of the session.
+    approved_writes: HashSet<PathBuf>,
+    /// Edit sets the user approved as a whole: grant -> canonical paths its
+    /// writes and deletes (rollbacks included) may touch without prompting.
+    grants: HashMap<String, HashSet<PathBuf>>,
 }
 
-async fn handle_connection(stream: TcpStream, inner: Arc<FsBridgeInner>) -> anyhow::Result<()> {
-    let (read_half, write_half) = stream.into_split();
+async fn handle_connection<S>(stream: S, inner: Arc<FsBridgeInner>) -> anyhow::Result<()>
+where
+    S: AsyncRead + AsyncWrite + 'static,
+{
+    let (read_half, write_half) = tokio::io::split(stream);
     let mut reader = BufReader::new(read_half).lines();
     let mut writer = BufWriter::new(write_half);
 
+    let Some(line) = reader.next_line().await? else {
+        return Ok(());
+    };
+    let session_id = match serde_json::from_str::<BridgeHandshake>(&line) {
+        Ok(handshake) if inner.verify_handshake(&handshake) => handshake.session_id,
+        Ok(handshake) => {
+            warn!(session_id = %handshake.session_id, "fs bridge rejected connection with invalid token");
+            write_response(
+                &mut writer,
+                &BridgeResponse::error(0, "invalid bridge token".to_string()),
+            )
+            .await?;
+            return Ok(());
+        }
+        Err(err) => {
+            warn!(error = %err, "fs bridge rejected connection without handshake");
+            write_response(
+                &mut writer,
+                &BridgeResponse::error(0, "expected bridge handshake".to_string()),
+            )
+            .await?;
+            return Ok(());
+        }
+    };
+    write_response(&mut writer, &BridgeResponse::success(0, None)).await?;
+
+    // Requests run concurrently so a slow one (e.g. waiting on a permission
+    // prompt) does not block the rest; a single task owns the writer. Each
+    // running request has a flag a later `cancel` request can set.
+    let (response_tx, mut response_rx) = mpsc::unbounded_channel::<BridgeResponse>();
+    let writer_task = task::spawn_local(async move {
+        while let Some(response) = response_rx.recv().await {
+            write_response(&mut writer, &response).await?;
+        }
+        anyhow::Ok(())
+    });
+    let in_flight = Rc::new(RefCell::new(HashMap::<u64, Rc<Cell<bool>>>::new()));
+
     while let Some(line) = reader.next_line().await? {
         if line.trim().is_empty() {
             continue;
@@ -139,107 +624,1153 @@ async fn handle_connection(stream: TcpStream, inner: Arc<FsBridgeInner>) -> anyh
             }
         };
 
-        let response = inner.handle_request(request).await;
-        let response_json = serde_json::to_string(&response)?;
-        writer.write_all(response_json.as_bytes()).await?;
-        writer.write_all(b"\n").await?;
-        writer.flush().await?;
+        if request.session_id != session_id {
+            let _ = response_tx.send(BridgeResponse::error(
+                request.id,
+                "connection is not authorized for this session".to_string(),
+            ));
+            continue;
+        }
+
+        if matches!(request.op, BridgeOp::Cancel) {
+            if let Some(cancelled) = request
+                .args
+                .request
+                .and_then(|target| in_flight.borrow().get(&target).cloned())
+            {
+                cancelled.set(true);
+            }
+            let _ = response_tx.send(BridgeResponse::success(request.id, None));
+            continue;
+        }
+
+        let cancelled = Rc::new(Cell::new(false));
+        in_flight.borrow_mut().insert(request.id, cancelled.clone());
+        let request_inner = inner.clone();
+        let request_tx = response_tx.clone();
+        let request_in_flight = in_flight.clone();
+        task::spawn_local(async move {
+            let (id, session_id) = (request.id, request.session_id.clone());
+            let mut response = request_inner.handle_request(request, &cancelled).await;
+            request_in_flight.borrow_mut().remove(&id);
+            response.changed = request_inner.changes.take(&session_id);
+            let _ = request_tx.send(response);
+        });
     }
 
+    drop(response_tx);
+    writer_task.await??;
+    Ok(())
+}
+
+async fn write_response<W>(writer: &mut W, response: &BridgeResponse) -> anyhow::Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    let response_json = serde_json::to_string(response)?;
+    writer.write_all(response_json.as_bytes()).await?;
+    writer.write_all(b"\n").await?;
+    writer.flush().await?;
     Ok(())
 }
 
+#[derive(Debug, Clone, Copy)]
+struct Page {
+    offset: usize,
+    limit: usize,
+}
+
+/// Run a directory walk off the async runtime and paginate its result.
+async fn list_blocking<F>(page: Page, run: F) -> Result<ListingPage, String>
+where
+    F: FnOnce() -> Result<(Vec<ListEntry>, bool), String> + Send + 'static,
+{
+    let (entries, walk_truncated) = task::spawn_blocking(run)
+        .await
+        .map_err(|err| format!("directory walk failed: {err}"))??;
+    Ok(walk::paginate(
+        entries,
+        page.offset,
+        page.limit,
+        walk_truncated,
+    ))
+}
+
+/// Fail with a version conflict unless `current` (the file's content, `None`
+/// if it does not exist) is at version `expected`.
+fn check_version(path: &Path, expected: &str, current: Option<&str>) -> Result<(), String> {
+    let Some(current) = current else {
+        return Err(format!(
+            "{VERSION_CONFLICT}: {} no longer exists (expected version {expected})",
+            path.display()
+        ));
+    };
+    let current = content_version(current);
+    if current == expected {
+        Ok(())
+    } else {
+        Err(format!(
+            "{VERSION_CONFLICT}: {} changed since version {expected} (now {current})",
+            path.display()
+        ))
+    }
+}
+
+/// What a read found at a path.
+enum FileContent {
+    Text(String),
+    Binary(BinarySummary),
+}
+
+/// A read's content and where it came from.
+struct FileRead {
+    content: FileContent,
+    source: ReadSource,
+    /// Why the content came from disk rather than the client.
+    fallback_reason: Option<String>,
+}
+
+impl FileRead {
+    fn disk(content: FileContent, reason: impl Into<String>) -> Self {
+        Self {
+            content,
+            source: ReadSource::Disk,
+            fallback_reason: Some(reason.into()),
+        }
+    }
+}
+
+const BINARY_FROM_DISK: &str = "binary files are read from disk";
+
+/// The first [`BINARY_SNIFF_BYTES`] of `path`, or all of a shorter file.
+async fn read_head(path: &Path) -> std::io::Result<Vec<u8>> {
+    let mut head = Vec::with_capacity(BINARY_SNIFF_BYTES);
+    fs::File::open(path)
+        .await?
+        .take(BINARY_SNIFF_BYTES as u64)
+        .read_to_end(&mut head)
+        .await?;
+    Ok(head)
+}
+
+/// Read and decode all of `path` from disk, summarizing binary files.
+/// `reason` says why the read did not go through the client.
+async fn read_from_disk(path: &Path, reason: impl Into<String>) -> Result<FileRead, String> {
+    let bytes = fs::read(path)
+        .await
+        .map_err(|err| format!("failed to read {}: {err}", path.display()))?;
+    if let Some(summary) = binary::summarize(path, &bytes) {
+        return Ok(FileRead::disk(
+            FileContent::Binary(summary),
+            BINARY_FROM_DISK,
+        ));
+    }
+    let decoded = encoding::decode(path, &bytes)?;
+    Ok(FileRead::disk(FileContent::Text(decoded.text), reason))
+}
+
+/// Prefix of the error returned when a write's expected version is stale.
+pub const VERSION_CONFLICT: &str = "version conflict";
+
+/// Version identifier of a file's content: the first 16 hex digits of its
+/// SHA-256.
+pub fn content_version(content: &str) -> String {
+    let digest = Sha256::digest(content.as_bytes());
+    digest[..8]
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+/// `limit` lines of `content` from 1-based `start_line`, or the rest of it.
+fn slice_lines(content: &str, start_line: u64, limit: Option<u32>) -> String {
+    let start = start_line.saturating_sub(1) as usize;
+    let count = limit.map_or(usize::MAX, |limit| limit as usize);
+    // Keep each line's terminator so CRLF and the final newline survive.
+    content
+        .split_inclusive('\n')
+        .skip(start)
+        .take(count)
+        .collect()
+}
+
+/// First line of a read window: the last `tail` lines, or from `line`.
+fn window_start(line: Option<u32>, tail: Option<u32>, total_lines: u64) -> u64 {
+    match tail {
+        Some(tail) => total_lines.saturating_sub(u64::from(tail)) + 1,
+        None => u64::from(line.unwrap_or(1).max(1)),
+    }
+}
+
+fn cancelled_error(op: BridgeOp) -> String {
+    format!(
+        "{} request was cancelled by the client before it was carried out",
+        op.as_str()
+    )
+}
+
+/// Two v4 UUIDs, i.e. 244 random bits.
+fn generate_token() -> String {
+    format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple())
+}
+
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0u8, |diff, (x, y)| diff | (x ^ y))
+            == 0
+}
+
 impl FsBridgeInner {
-    async fn handle_request(&self, request: BridgeRequest) -> BridgeResponse {
+    /// Handle one request. Once `cancelled` is set, the request fails instead
+    /// of changing anything it has not changed yet.
+    async fn handle_request(
+        &self,
+        request: BridgeRequest,
+        cancelled: &Cell<bool>,
+    ) -> BridgeResponse {
         let BridgeRequest {
             id,
             session_id,
             op,
-            path,
-            line,
-            limit,
-            content,
+            args,
         } = request;
 
-        let resolved_path = match self.resolve_path(&path) {
+        match op {
+            BridgeOp::ApproveSet => {
+                let changes = args.changes.unwrap_or_default();
+                return match self.approve_set(id, &session_id, changes).await {
+                    Ok(grant) if cancelled.get() => {
+                        self.end_set(&session_id, &grant);
+                        BridgeResponse::error(id, cancelled_error(op))
+                    }
+                    Ok(grant) => BridgeResponse::success(id, Some(grant)),
+                    Err(err) => BridgeResponse::error(id, err),
+                };
+            }
+            BridgeOp::EndSet => {
+                if let Some(grant) = args.grant {
+                    self.end_set(&session_id, &grant);
+                }
+                return BridgeResponse::success(id, None);
+            }
+            _ => {}
+        }
+
+        let requested_path = match policy::normalize_path(&self.workspace_root, &args.path) {
             Ok(p) => p,
             Err(err) => {
                 return BridgeResponse::error(id, err);
             }
         };
 
-        let session_id = SessionId::new(session_id);
+        // From here on the op works on the canonical path the policy
+        // approved, so a symlink cannot be swapped in between check and use.
+        let resolved_path = match self
+            .authorize(
+                id,
+                &session_id,
+                &requested_path,
+                op.access(),
+                op.follows_links(),
+            )
+            .await
+        {
+            Ok(canonical) => canonical,
+            Err(err) => return BridgeResponse::error(id, err),
+        };
 
-        match op {
-            BridgeOp::Read => {
+        let destination = match (op, args.destination.as_deref()) {
+            (BridgeOp::Move, Some(destination)) => {
+                let resolved = match policy::normalize_path(&self.workspace_root, destination) {
+                    Ok(p) => p,
+                    Err(err) => return BridgeResponse::error(id, err),
+                };
                 match self
-                    .read_with_fallback(&session_id, &resolved_path, line, limit)
+                    .authorize(id, &session_id, &resolved, FsAccess::Write, false)
                     .await
                 {
-                    Ok(text) => BridgeResponse::success(id, Some(text)),
-                    Err(err) => BridgeResponse::error(id, err),
+                    Ok(canonical) => Some(canonical),
+                    Err(err) => return BridgeResponse::error(id, err),
+                }
+            }
+            (BridgeOp::Move, None) => {
+                return BridgeResponse::error(id, "missing destination for move".to_string());
+            }
+            _ => None,
+        };
+
+        let granted = self.granted(&session_id, args.grant.as_deref(), &resolved_path);
+        if op.is_management()
+            && let Err(err) = self
+                .approve_management(
+                    id,
+                    &session_id,
+                    op,
+                    &resolved_path,
+                    destination.as_deref(),
+                    granted,
+                )
+                .await
+        {
+            return BridgeResponse::error(id, err);
+        }
+
+        let session_id = SessionId::new(session_id);
+
+        match op {
+            BridgeOp::Read => {
+                let large = if self.disk_fallback(&session_id.0) {
+                    self.read_large(id, &resolved_path, &args).await
+                } else {
+                    None
+                };
+                let response = match large {
+                    Some(response) => response,
+                    None => self.read(id, &session_id, &resolved_path, &args).await,
+                };
+                if response.success {
+                    self.changes
+                        .record(&session_id.0, &resolved_path, &args.path);
                 }
+                response
             }
             BridgeOp::Write => {
-                let Some(content) = content else {
+                let Some(content) = args.content else {
                     return BridgeResponse::error(id, "missing content for write".to_string());
                 };
 
+                let format = match self.disk_format(&resolved_path).await {
+                    Ok(format) => format,
+                    Err(err) => return BridgeResponse::error(id, err),
+                };
+                let content = if args.exact_newline == Some(true) {
+                    TextFormat {
+                        final_newline: false,
+                        ..format
+                    }
+                    .normalize(&content)
+                } else {
+                    format.normalize(&content)
+                };
+
+                let needs_approval = match self.write_needs_approval(&session_id.0, &resolved_path)
+                {
+                    Ok(needs_approval) => needs_approval && !granted,
+                    Err(err) => return BridgeResponse::error(id, err),
+                };
+                // The text the user approves in the permission prompt's diff.
+                let shown = if needs_approval {
+                    let current = match self.current_text(&session_id, &resolved_path).await {
+                        Ok(current) => current,
+                        Err(err) => return BridgeResponse::error(id, err),
+                    };
+                    // Do not ask about a write the version check would refuse.
+                    if let Some(expected) = args.expected_version.as_deref()
+                        && let Err(err) =
+                            check_version(&resolved_path, expected, current.as_deref())
+                    {
+                        return BridgeResponse::error(id, err);
+                    }
+                    if let Err(err) = self
+                        .request_write_approval(
+                            id,
+                            &session_id.0,
+                            &resolved_path,
+                            current.as_deref(),
+                            &content,
+                        )
+                        .await
+                    {
+                        return BridgeResponse::error(id, err);
+                    }
+                    Some(current)
+                } else {
+                    None
+                };
+
+                // The version check is the last step before the write, after
+                // any approval prompt: the user can take a while to answer,
+                // and the file must still be at the expected version, and
+                // match the diff the user approved, when the write lands.
+                if args.expected_version.is_some() || shown.is_some() {
+                    let current = match self.current_text(&session_id, &resolved_path).await {
+                        Ok(current) => current,
+                        Err(err) => return BridgeResponse::error(id, err),
+                    };
+                    if let Some(expected) = args.expected_version.as_deref()
+                        && let Err(err) =
+                            check_version(&resolved_path, expected, current.as_deref())
+                    {
+                        return BridgeResponse::error(id, err);
+                    }
+                    if let Some(shown) = shown
+                        && shown != current
+                    {
+                        return BridgeResponse::error(
+                            id,
+                            format!(
+                                "{VERSION_CONFLICT}: {} changed while the write was awaiting approval",
+                                resolved_path.display()
+                            ),
+                        );
+                    }
+                }
+
+                if cancelled.get() {
+                    return BridgeResponse::error(id, cancelled_error(op));
+                }
+                let version = content_version(&content);
+                self.changes
+                    .begin_write(&session_id.0, &resolved_path, &args.path);
+                let result = self
+                    .write_with_fallback(&session_id, &resolved_path, content, &format)
+                    .await;
+                self.changes
+                    .record(&session_id.0, &resolved_path, &args.path);
+                match result {
+                    Ok(()) => BridgeResponse::success(id, None).with_version(version),
+                    Err(err) => BridgeResponse::error(id, err),
+                }
+            }
+            BridgeOp::ListDirectory | BridgeOp::Glob => {
+                let page = Page {
+                    offset: args.offset.unwrap_or(0) as usize,
+                    limit: args.limit.unwrap_or(DEFAULT_LIST_LIMIT) as usize,
+                };
+                let listing = match op {
+                    BridgeOp::Glob => {
+                        let Some(pattern) = args.pattern else {
+                            return BridgeResponse::error(
+                                id,
+                                "missing pattern for glob".to_string(),
+                            );
+                        };
+                        list_blocking(page, move || walk::glob(&resolved_path, &pattern)).await
+                    }
+                    _ => {
+                        let depth = args.depth.unwrap_or(1).clamp(1, MAX_LIST_DEPTH) as usize;
+                        list_blocking(page, move || walk::list_directory(&resolved_path, depth))
+                            .await
+                    }
+                };
+                match listing.and_then(|page| {
+                    serde_json::to_string(&page)
+                        .map_err(|err| format!("failed to encode listing: {err}"))
+                }) {
+                    Ok(json) => BridgeResponse::success(id, Some(json)),
+                    Err(err) => BridgeResponse::error(id, err),
+                }
+            }
+            BridgeOp::Delete | BridgeOp::Move | BridgeOp::CreateDirectory => {
+                if resolved_path == self.workspace_root {
+                    return BridgeResponse::error(
+                        id,
+                        format!("refusing to {} the workspace root", op.as_str()),
+                    );
+                }
+                if cancelled.get() {
+                    return BridgeResponse::error(id, cancelled_error(op));
+                }
+                let result = match (op, &destination) {
+                    (BridgeOp::Delete, _) => {
+                        self.changes
+                            .begin_write(&session_id.0, &resolved_path, &args.path);
+                        let result =
+                            manage::delete_path(&resolved_path, args.recursive.unwrap_or(false))
+                                .await;
+                        self.changes
+                            .record(&session_id.0, &resolved_path, &args.path);
+                        result
+                    }
+                    (BridgeOp::Move, Some(destination)) => {
+                        self.changes
+                            .begin_write(&session_id.0, &resolved_path, &args.path);
+                        self.changes.begin_write(
+                            &session_id.0,
+                            destination,
+                            args.destination.as_deref().unwrap_or_default(),
+                        );
+                        let result = manage::move_path(
+                            &resolved_path,
+                            destination,
+                            args.overwrite.unwrap_or(false),
+                        )
+                        .await;
+                        self.changes
+                            .record(&session_id.0, &resolved_path, &args.path);
+                        self.changes.record(
+                            &session_id.0,
+                            destination,
+                            args.destination.as_deref().unwrap_or_default(),
+                        );
+                        result
+                    }
+                    _ => manage::create_directory(&resolved_path).await,
+                };
+                match result {
+                    Ok(message) => BridgeResponse::success(id, Some(message)),
+                    Err(err) => BridgeResponse::error(id, err),
+                }
+            }
+            BridgeOp::Search => {
+                let Some(pattern) = args.pattern else {
+                    return BridgeResponse::error(id, "missing pattern for search".to_string());
+                };
+                let options = SearchOptions {
+                    pattern,
+                    literal: args.literal.unwrap_or(false),
+                    case_insensitive: args.case_insensitive.unwrap_or(false),
+                    include: args.include.unwrap_or_default(),
+                    exclude: args.exclude.unwrap_or_default(),
+                    context: args.context.unwrap_or(0).min(MAX_SEARCH_CONTEXT) as usize,
+                    offset: args.offset.unwrap_or(0) as usize,
+                    limit: args.limit.unwrap_or(DEFAULT_SEARCH_LIMIT) as usize,
+                };
                 match self
-                    .write_with_fallback(&session_id, &resolved_path, content)
+                    .search(&session_id, resolved_path, options)
                     .await
-                {
-                    Ok(()) => BridgeResponse::success(id, None),
+                    .and_then(|page| {
+                        serde_json::to_string(&page)
+                            .map_err(|err| format!("failed to encode search results: {err}"))
+                    }) {
+                    Ok(json) => BridgeResponse::success(id, Some(json)),
                     Err(err) => BridgeResponse::error(id, err),
                 }
             }
+            BridgeOp::ApproveSet | BridgeOp::EndSet | BridgeOp::Cancel => {
+                unreachable!("{} is handled before the path checks", op.as_str())
+            }
         }
     }
 
-    fn resolve_path(&self, path: &str) -> Result<PathBuf, String> {
-        let candidate = PathBuf::from(path);
-        if candidate.is_absolute() {
-            return Ok(candidate);
+    /// Search files under `root`, reading them through the client so unsaved
+    /// buffers are seen. Large searches, and clients that cannot read files,
+    /// are served from disk instead.
+    async fn search(
+        &self,
+        session_id: &SessionId,
+        root: PathBuf,
+        options: SearchOptions,
+    ) -> Result<SearchPage, String> {
+        let mut search = Search::new(&options)?;
+        let workspace_root = self.workspace_root.clone();
+        let (include, exclude) = (options.include.clone(), options.exclude.clone());
+        let (candidates, walk_truncated) = task::spawn_blocking(move || {
+            search::candidate_files(&root, &workspace_root, &include, &exclude)
+        })
+        .await
+        .map_err(|err| format!("search walk failed: {err}"))??;
+
+        if candidates.len() <= MAX_CLIENT_SEARCH_FILES {
+            let mut client_ok = true;
+            for candidate in &candidates {
+                if search.is_done() {
+                    break;
+                }
+                match self
+                    .read_via_client(
+                        session_id.clone(),
+                        candidate.path.display().to_string(),
+                        None,
+                        None,
+                    )
+                    .await
+                {
+                    // Skip binary files the client returned verbatim.
+                    Ok(content) if content.contains('\0') => {}
+                    Ok(content) => search.scan(&candidate.display, &content),
+                    Err(err) => {
+                        debug!(error = %err, "client read failed during search, searching disk instead");
+                        client_ok = false;
+                        break;
+                    }
+                }
+            }
+            if client_ok {
+                return Ok(search.finish(ContentSource::Client, walk_truncated));
+            }
+            search = Search::new(&options)?;
         }
 
-        let mut resolved = self.workspace_root.clone();
-        for component in Path::new(&candidate).components() {
-            use std::path::Component;
-            match component {
-                Component::CurDir => {}
-                Component::ParentDir => {
-                    if !resolved.pop() {
-                        return Err("path escapes workspace root".to_string());
+        task::spawn_blocking(move || {
+            for candidate in &candidates {
+                if search.is_done() {
+                    break;
+                }
+                if let Some(content) = search::read_searchable(&candidate.path) {
+                    search.scan(&candidate.display, &content);
+                }
+            }
+            search.finish(ContentSource::Disk, walk_truncated)
+        })
+        .await
+        .map_err(|err| format!("search failed: {err}"))
+    }
+
+    /// Enforce the session's confinement policy for `path` and return the
+    /// canonical path it approved. With `follow_links` off, a symlink in the
+    /// last component is checked and returned as the link itself.
+    ///
+    /// Reads outside the allowed roots are turned into a permission request;
+    /// "allow always" approvals are remembered for the rest of the session.
+    async fn authorize(
+        &self,
+        request_id: u64,
+        fs_session_id: &str,
+        path: &Path,
+        access: FsAccess,
+        follow_links: bool,
+    ) -> Result<PathBuf, String> {
+        let decision = {
+            let sessions = self
+                .sessions
+                .read()
+                .map_err(|_| "fs bridge session registry is poisoned".to_string())?;
+            let session = sessions
+                .get(fs_session_id)
+                .ok_or_else(|| format!("unknown fs session: {fs_session_id}"))?;
+            let decision = if follow_links {
+                session.policy.check(path, access)
+            } else {
+                session.policy.check_entry(path, access)
+            };
+            match decision {
+                PolicyDecision::NeedsApproval { canonical }
+                    if session.approved_reads.contains(&canonical) =>
+                {
+                    PolicyDecision::Allowed { canonical }
+                }
+                decision => decision,
+            }
+        };
+
+        match decision {
+            PolicyDecision::Allowed { canonical } => Ok(canonical),
+            PolicyDecision::Denied(reason) => Err(format!("access denied: {reason}")),
+            PolicyDecision::NeedsApproval { canonical } => {
+                self.request_read_approval(request_id, fs_session_id, path, canonical.clone())
+                    .await?;
+                Ok(canonical)
+            }
+        }
+    }
+
+    /// Gate a file-management op on the session's mode: refuse it in
+    /// read-only sessions and ask the user when the approval policy says so.
+    async fn approve_management(
+        &self,
+        request_id: u64,
+        fs_session_id: &str,
+        op: BridgeOp,
+        path: &Path,
+        destination: Option<&Path>,
+        granted: bool,
+    ) -> Result<(), String> {
+        let mode = {
+            let sessions = self
+                .sessions
+                .read()
+                .map_err(|_| "fs bridge session registry is poisoned".to_string())?;
+            sessions
+                .get(fs_session_id)
+                .map(|session| session.mode)
+                .ok_or_else(|| format!("unknown fs session: {fs_session_id}"))?
+        };
+        if mode.read_only {
+            return Err(format!(
+                "{} is disabled while session mode is read-only",
+                op.as_str()
+            ));
+        }
+        if granted || !mode.requires_approval(op) {
+            return Ok(());
+        }
+
+        let (kind, title) = match (op, destination) {
+            (BridgeOp::Delete, _) => (ToolKind::Delete, format!("Delete {}", path.display())),
+            (BridgeOp::Move, Some(destination)) => (
+                ToolKind::Move,
+                format!("Move {} to {}", path.display(), destination.display()),
+            ),
+            _ => (
+                ToolKind::Edit,
+                format!("Create directory {}", path.display()),
+            ),
+        };
+        let mut locations = vec![ToolCallLocation::new(path.to_path_buf())];
+        locations.extend(
+            destination.map(|destination| ToolCallLocation::new(destination.to_path_buf())),
+        );
+        let fields = ToolCallUpdateFields::new()
+            .kind(kind)
+            .status(ToolCallStatus::Pending)
+            .title(title)
+            .locations(locations);
+        let request = RequestPermissionRequest::new(
+            SessionId::new(fs_session_id.to_string()),
+            ToolCallUpdate::new(
+                ToolCallId::new(format!("acp_fs-{}-{request_id}", op.as_str())),
+                fields,
+            ),
+            management_permission_options(),
+        );
+
+        let rejected = || {
+            format!(
+                "{} of {} was rejected by the user",
+                op.as_str(),
+                path.display()
+            )
+        };
+        let (tx, rx) = oneshot::channel();
+        self.client_tx
+            .send(ClientOp::RequestPermission {
+                request,
+                response_tx: tx,
+            })
+            .map_err(|_| rejected())?;
+        match rx.await {
+            Ok(Ok(response)) => match response.outcome {
+                RequestPermissionOutcome::Selected(selected)
+                    if selected.option_id.0.as_ref() == "approved" =>
+                {
+                    Ok(())
+                }
+                _ => Err(rejected()),
+            },
+            _ => Err(rejected()),
+        }
+    }
+
+    /// Check every change of an edit set against the policy and ask the user
+    /// once for all of them, showing each file's diff. Returns a grant that
+    /// lets the set's writes and deletes, including rolling them back, go
+    /// ahead without prompting again.
+    async fn approve_set(
+        &self,
+        request_id: u64,
+        fs_session_id: &str,
+        changes: Vec<SetChange>,
+    ) -> Result<String, String> {
+        if changes.is_empty() {
+            return Err("missing changes for approve_set".to_string());
+        }
+        let session_id = SessionId::new(fs_session_id.to_string());
+        let mode = {
+            let sessions = self
+                .sessions
+                .read()
+                .map_err(|_| "fs bridge session registry is poisoned".to_string())?;
+            sessions
+                .get(fs_session_id)
+                .map(|session| session.mode)
+                .ok_or_else(|| format!("unknown fs session: {fs_session_id}"))?
+        };
+
+        let mut paths = HashSet::new();
+        let mut writes = Vec::new();
+        let mut needs_approval = false;
+        let mut locations = Vec::new();
+        let mut diffs = Vec::new();
+        for change in changes {
+            let requested = policy::normalize_path(&self.workspace_root, &change.path)?;
+            let deleting = change.content.is_none();
+            let path = self
+                .authorize(
+                    request_id,
+                    fs_session_id,
+                    &requested,
+                    FsAccess::Write,
+                    !deleting,
+                )
+                .await?;
+            if deleting && mode.read_only {
+                return Err("delete is disabled while session mode is read-only".to_string());
+            }
+            needs_approval |= if deleting {
+                mode.requires_approval(BridgeOp::Delete)
+            } else {
+                self.write_needs_approval(fs_session_id, &path)?
+            };
+            let current = self.current_text(&session_id, &path).await?;
+            locations.push(ToolCallLocation::new(path.clone()));
+            diffs.push(ToolCallContent::from(
+                Diff::new(path.clone(), change.content.unwrap_or_default()).old_text(current),
+            ));
+            if !deleting {
+                writes.push(path.clone());
+            }
+            paths.insert(path);
+        }
+
+        if needs_approval {
+            let rejected = || "the edit set was rejected by the user".to_string();
+            let fields = ToolCallUpdateFields::new()
+                .kind(ToolKind::Edit)
+                .status(ToolCallStatus::Pending)
+                .title(format!("Apply changes to {} files", paths.len()))
+                .locations(locations)
+                .content(diffs);
+            let request = RequestPermissionRequest::new(
+                session_id,
+                ToolCallUpdate::new(ToolCallId::new(format!("acp_fs-set-{request_id}")), fields),
+                session_permission_options(),
+            );
+            let (tx, rx) = oneshot::channel();
+            self.client_tx
+                .send(ClientOp::RequestPermission {
+                    request,
+                    response_tx: tx,
+                })
+                .map_err(|_| rejected())?;
+            let outcome = match rx.await {
+                Ok(Ok(response)) => response.outcome,
+                _ => return Err(rejected()),
+            };
+            let RequestPermissionOutcome::Selected(selected) = outcome else {
+                return Err(rejected());
+            };
+            match selected.option_id.0.as_ref() {
+                "approved-for-session" => {
+                    if let Ok(mut sessions) = self.sessions.write()
+                        && let Some(session) = sessions.get_mut(fs_session_id)
+                    {
+                        session.approved_writes.extend(writes);
                     }
                 }
-                Component::Normal(part) => resolved.push(part),
-                Component::RootDir => {}
-                Component::Prefix(_) => {}
+                "approved" => {}
+                _ => return Err(rejected()),
+            }
+        }
+
+        let grant = generate_token();
+        let mut sessions = self
+            .sessions
+            .write()
+            .map_err(|_| "fs bridge session registry is poisoned".to_string())?;
+        let session = sessions
+            .get_mut(fs_session_id)
+            .ok_or_else(|| format!("unknown fs session: {fs_session_id}"))?;
+        session.grants.insert(grant.clone(), paths);
+        Ok(grant)
+    }
+
+    /// Release an edit set's grant.
+    fn end_set(&self, fs_session_id: &str, grant: &str) {
+        if let Ok(mut sessions) = self.sessions.write()
+            && let Some(session) = sessions.get_mut(fs_session_id)
+        {
+            session.grants.remove(grant);
+        }
+    }
+
+    /// Whether `grant` is an approved edit set of the session covering `path`.
+    fn granted(&self, fs_session_id: &str, grant: Option<&str>, path: &Path) -> bool {
+        let Some(grant) = grant else {
+            return false;
+        };
+        self.sessions.read().is_ok_and(|sessions| {
+            sessions
+                .get(fs_session_id)
+                .and_then(|session| session.grants.get(grant))
+                .is_some_and(|paths| paths.contains(path))
+        })
+    }
+
+    async fn request_read_approval(
+        &self,
+        request_id: u64,
+        fs_session_id: &str,
+        path: &Path,
+        canonical: PathBuf,
+    ) -> Result<(), String> {
+        let denied = || {
+            format!(
+                "access denied: {} is outside the workspace; add it to [acp.fs] read_allow or approve the read",
+                path.display()
+            )
+        };
+
+        let fields = ToolCallUpdateFields::new()
+            .kind(ToolKind::Read)
+            .status(ToolCallStatus::Pending)
+            .title(format!("Read {} (outside the workspace)", path.display()))
+            .locations(vec![ToolCallLocation::new(path.to_path_buf())]);
+        let request = RequestPermissionRequest::new(
+            SessionId::new(fs_session_id.to_string()),
+            ToolCallUpdate::new(ToolCallId::new(format!("acp_fs-read-{request_id}")), fields),
+            session_permission_options(),
+        );
+
+        let (tx, rx) = oneshot::channel();
+        self.client_tx
+            .send(ClientOp::RequestPermission {
+                request,
+                response_tx: tx,
+            })
+            .map_err(|_| denied())?;
+        let outcome = match rx.await {
+            Ok(Ok(response)) => response.outcome,
+            _ => return Err(denied()),
+        };
+
+        let RequestPermissionOutcome::Selected(selected) = outcome else {
+            return Err(denied());
+        };
+        match selected.option_id.0.as_ref() {
+            "approved-for-session" => {
+                if let Ok(mut sessions) = self.sessions.write()
+                    && let Some(session) = sessions.get_mut(fs_session_id)
+                {
+                    session.approved_reads.insert(canonical);
+                }
+                Ok(())
             }
+            "approved" => Ok(()),
+            _ => Err(denied()),
         }
+    }
 
-        Ok(resolved)
+    /// Whether writing `path` needs the user's approval: the session's
+    /// approval policy asks for it and the path was not approved for the
+    /// session already.
+    fn write_needs_approval(&self, fs_session_id: &str, path: &Path) -> Result<bool, String> {
+        let sessions = self
+            .sessions
+            .read()
+            .map_err(|_| "fs bridge session registry is poisoned".to_string())?;
+        let session = sessions
+            .get(fs_session_id)
+            .ok_or_else(|| format!("unknown fs session: {fs_session_id}"))?;
+        Ok(session.mode.requires_approval(BridgeOp::Write)
+            && !session.approved_writes.contains(path))
     }
 
+    /// Ask the user to approve a write, showing it as a diff against the
+    /// current content (`None` for a new file).
+    async fn request_write_approval(
+        &self,
+        request_id: u64,
+        fs_session_id: &str,
+        path: &Path,
+        current: Option<&str>,
+        content: &str,
+    ) -> Result<(), String> {
+        let rejected = || format!("write to {} was rejected by the user", path.display());
+
+        let title = match current {
+            Some(_) => format!("Edit {}", path.display()),
+            None => format!("Create {}", path.display()),
+        };
+        let diff = Diff::new(path.to_path_buf(), content.to_string())
+            .old_text(current.map(str::to_string));
+        let fields = ToolCallUpdateFields::new()
+            .kind(ToolKind::Edit)
+            .status(ToolCallStatus::Pending)
+            .title(title)
+            .locations(vec![ToolCallLocation::new(path.to_path_buf())])
+            .content(vec![ToolCallContent::from(diff)]);
+        let request = RequestPermissionRequest::new(
+            SessionId::new(fs_session_id.to_string()),
+            ToolCallUpdate::new(
+                ToolCallId::new(format!("acp_fs-write-{request_id}")),
+                fields,
+            ),
+            session_permission_options(),
+        );
+
+        let (tx, rx) = oneshot::channel();
+        self.client_tx
+            .send(ClientOp::RequestPermission {
+                request,
+                response_tx: tx,
+            })
+            .map_err(|_| rejected())?;
+        let outcome = match rx.await {
+            Ok(Ok(response)) => response.outcome,
+            _ => return Err(rejected()),
+        };
+
+        let RequestPermissionOutcome::Selected(selected) = outcome else {
+            return Err(rejected());
+        };
+        match selected.option_id.0.as_ref() {
+            "approved-for-session" => {
+                if let Ok(mut sessions) = self.sessions.write()
+                    && let Some(session) = sessions.get_mut(fs_session_id)
+                {
+                    session.approved_writes.insert(path.to_path_buf());
+                }
+                Ok(())
+            }
+            "approved" => Ok(()),
+            _ => Err(rejected()),
+        }
+    }
+
+    /// Current text of `path`, or `None` if it does not exist.
+    async fn current_text(
+        &self,
+        session_id: &SessionId,
+        path: &Path,
+    ) -> Result<Option<String>, String> {
+        match self.read_with_fallback(session_id, path).await {
+            Ok(content) => Ok(Some(content)),
+            Err(_) if !path.exists() => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Read through the client, falling back to disk.
+    ///
+    /// The disk copy is decoded first: files that are not UTF-8 are refused,
+    /// and UTF-16 or BOM-prefixed files are served from disk because the
+    /// client's view of them may differ from what the bridge writes back.
     async fn read_with_fallback(
         &self,
         session_id: &SessionId,
         path: &Path,
-        line: Option<u32>,
-        limit: Option<u32>,
     ) -> Result<String, String> {
+        match self.read_file(session_id, path).await?.content {
+            FileContent::Text(text) => Ok(text),
+            FileContent::Binary(summary) => Err(format!(
+                "{} is a binary file ({}, {} bytes)",
+                path.display(),
+                summary.mime_type,
+                summary.size
+            )),
+        }
+    }
+
+    /// Serve a read of a large file from disk by range, using the cached line
+    /// index. `None` for files below the size threshold.
+    async fn read_large(&self, id: u64, path: &Path, args: &BridgeArgs) -> Option<BridgeResponse> {
+        let metadata = fs::metadata(path).await.ok()?;
+        if !metadata.is_file() || metadata.len() < LARGE_FILE_BYTES {
+            return None;
+        }
+
+        let cache = self.line_indexes.clone();
+        let path = path.to_path_buf();
+        let (line, limit, tail) = (args.line, args.limit, args.tail);
+        let result = task::spawn_blocking(move || -> Result<BridgeResponse, String> {
+            let index = cache.get(&path)?;
+            if let Some(summary) = &index.binary {
+                return Ok(BridgeResponse::binary(id, summary.clone()));
+            }
+            let start_line = window_start(line, tail, index.total_lines);
+            let count = tail.or(limit).map_or(u64::MAX, u64::from);
+            let text = index.read_lines(&path, start_line, count)?;
+            Ok(BridgeResponse::success(id, Some(text))
+                .with_version(index.version.clone())
+                .with_range(start_line, index.total_lines)
+                .with_source(
+                    ReadSource::Disk,
+                    Some(format!(
+                        "files of {} MB or more are read from disk by line range",
+                        LARGE_FILE_BYTES / (1024 * 1024)
+                    )),
+                ))
+        })
+        .await
+        .map_err(|err| format!("ranged read failed: {err}"));
+        Some(match result {
+            Ok(Ok(response)) => response,
+            Ok(Err(err)) | Err(err) => BridgeResponse::error(id, err),
+        })
+    }
+
+    /// Read the whole file so the version covers all of it, then return the
+    /// requested window.
+    async fn read(
+        &self,
+        id: u64,
+        session_id: &SessionId,
+        path: &Path,
+        args: &BridgeArgs,
+    ) -> BridgeResponse {
+        let read = match self.read_file(session_id, path).await {
+            Ok(read) => read,
+            Err(err) => return BridgeResponse::error(id, err),
+        };
+        match read.content {
+            FileContent::Text(text) => {
+                let version = content_version(&text);
+                let total_lines = text.split_inclusive('\n').count() as u64;
+                let start_line = window_start(args.line, args.tail, total_lines);
+                let window = slice_lines(&text, start_line, args.tail.or(args.limit));
+                BridgeResponse::success(id, Some(window))
+                    .with_version(version)
+                    .with_range(start_line, total_lines)
+                    .with_source(read.source, read.fallback_reason)
+            }
+            FileContent::Binary(summary) => BridgeResponse::binary(id, summary),
+        }
+    }
+
+    /// Like [`Self::read_with_fallback`], but binary files on disk are
+    /// summarized instead of failing, and the result says where it came from.
+    ///
+    /// Only the start of the file is read from disk up front, to spot binary,
+    /// byte-order-marked and non-UTF-8 files. The whole file is read from disk
+    /// for the first two, or when the client read fails and may fall back.
+    async fn read_file(&self, session_id: &SessionId, path: &Path) -> Result<FileRead, String> {
+        if let Ok(head) = read_head(path).await {
+            if binary::is_binary(path, &head) || encoding::has_bom(&head) {
+                return read_from_disk(
+                    path,
+                    "UTF-16 and byte-order-marked files are read from disk",
+                )
+                .await;
+            }
+            encoding::check_utf8_head(path, &head)?;
+        }
+
         match self
-            .read_via_client(session_id.clone(), path.display().to_string(), line, limit)
+            .read_via_client(session_id.clone(), path.display().to_string(), None, None)
             .await
         {
-            Ok(content) => Ok(content),
+            Ok(content) => Ok(FileRead {
+                content: FileContent::Text(content),
+                source: ReadSource::Client,
+                fallback_reason: None,
+            }),
+            Err(err) if !self.disk_fallback(&session_id.0) => Err(format!(
+                "client read of {} failed and disk fallback is disabled for this session: {err}",
+                path.display()
+            )),
             Err(err) => {
                 debug!(error = %err, path = %path.display(), "client read failed, falling back to local read");
-                self.read_locally(path, line, limit).await
+                read_from_disk(path, format!("client read failed: {err}")).await
             }
         }
     }
 
+    /// Whether the session lets reads and writes fall back to disk.
+    fn disk_fallback(&self, fs_session_id: &str) -> bool {
+        self.sessions
+            .read()
+            .ok()
+            .and_then(|sessions| {
+                sessions
+                    .get(fs_session_id)
+                    .map(|session| session.mode.disk_fallback)
+            })
+            .unwrap_or(true)
+    }
+
+    /// How the existing file at `path` is stored; the default (plain UTF-8)
+    /// for new or unreadable files. Fails for files that are not valid text
+    /// so they are not overwritten with a different encoding.
+    async fn disk_format(&self, path: &Path) -> Result<TextFormat, String> {
+        match fs::read(path).await {
+            Ok(bytes) => encoding::decode(path, &bytes).map(|decoded| decoded.format),
+            Err(_) => Ok(TextFormat::default()),
+        }
+    }
+
     async fn read_via_client(
         &self,
         session_id: SessionId,
@@ -265,38 +1796,16 @@ impl FsBridgeInner {
         }
     }
 
-    async fn read_locally(
-        &self,
-        path: &Path,
-        line: Option<u32>,
-        limit: Option<u32>,
-    ) -> Result<String, String> {
-        let content = fs::read_to_string(path)
-            .await
-            .map_err(|err| format!("failed to read {}: {err}", path.display()))?;
-
-        match line {
-            Some(start_line) => {
-                let start = start_line.saturating_sub(1) as usize;
-                let count = limit.unwrap_or(u32::MAX) as usize;
-                let lines: Vec<&str> = content.lines().collect();
-                if start >= lines.len() {
-                    Ok(String::new())
-                } else {
-                    let end = (start + count).min(lines.len());
-                    Ok(lines[start..end].join("\n"))
-                }
-            }
-            None => Ok(content),
-        }
-    }
-
     async fn write_with_fallback(
         &self,
         session_id: &SessionId,
         path: &Path,
         content: String,
+        format: &TextFormat,
     ) -> Result<(), String> {
+        if format.needs_local_write() {
+            return self.write_locally(path, format.encode(&content)).await;
+        }
         match self
             .write_via_client(
                 session_id.clone(),
@@ -306,9 +1815,13 @@ impl FsBridgeInner {
             .await
         {
             Ok(()) => Ok(()),
+            Err(err) if !self.disk_fallback(&session_id.0) => Err(format!(
+                "client write of {} failed and disk fallback is disabled for this session: {err}",
+                path.display()
+            )),
             Err(err) => {
                 debug!(error = %err, path = %path.display(), "client write failed, falling back to local write");
-                self.write_locally(path, content).await
+                self.write_locally(path, format.encode(&content)).await
             }
         }
     }
@@ -335,7 +1848,7 @@ impl FsBridgeInner {
         }
     }
 
-    async fn write_locally(&self, path: &Path, content: String) -> Result<(), String> {
+    async fn write_locally(&self, path: &Path, content: Vec<u8>) -> Result<(), String> {
         if let Some(parent) = path.parent() {
             fs::create_dir_all(parent).await.map_err(|err| {
                 format!(
@@ -349,3 +1862,25 @@ impl FsBridgeInner {
             .map_err(|err| format!("failed to write {}: {err}", path.display()))
     }
 }
+
+/// Permission options offered for file-management ops.
+fn management_permission_options() -> Vec<PermissionOption> {
+    vec![
+        PermissionOption::new("approved", "Allow", PermissionOptionKind::AllowOnce),
+        PermissionOption::new("abort", "Reject", PermissionOptionKind::RejectOnce),
+    ]
+}
+
+/// Permission options offered for reads outside the workspace and for
+/// writes; "Allow for this session" is remembered per path.
+fn session_permission_options() -> Vec<PermissionOption> {
+    vec![
+        PermissionOption::new(
+            "approved-for-session",
+            "Allow for this session",
+            PermissionOptionKind::AllowAlways,
+        ),
+        PermissionOption::new("approved", "Allow once", PermissionOptionKind::AllowOnce),
+        PermissionOption::new("abort", "Reject", PermissionOptionKind::RejectOnce),
+    ]
+}
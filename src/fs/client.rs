@@ -0,0 +1,471 @@
+//! Client side of the FS bridge, used by the `acp_fs` MCP server.
+//!
+//! All tool calls of one MCP server process share a single authenticated
+//! connection. Requests are tagged with a unique `id` and responses are routed
+//! back to their caller by that id, so a read does not wait behind a write that
+//! is blocked on a permission prompt. When the connection drops, pending
+//! requests fail and the next request reconnects.
+//!
+//! Responses may also name files the session has seen that changed outside
+//! it; those are collected until the server asks for them.
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    env,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+};
+
+use anyhow::{Context, Result, anyhow, bail};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWrite, AsyncWriteExt, BufReader, ReadHalf, WriteHalf},
+    sync::{Mutex, oneshot},
+    task::JoinHandle,
+    time::{self, Duration},
+};
+use tracing::{debug, warn};
+
+use crate::settings::{
+    DEFAULT_FS_CONNECT_TIMEOUT_SECS, DEFAULT_FS_READ_TIMEOUT_SECS, DEFAULT_FS_WRITE_TIMEOUT_SECS,
+};
+
+use super::{
+    binary::BinarySummary,
+    bridge::{BridgeArgs, BridgeHandshake, BridgeOp, BridgeRequest, BridgeResponse, ReadSource},
+    policy::FsAccess,
+};
+
+/// Connection attempts before a request gives up.
+const CONNECT_ATTEMPTS: u32 = 3;
+/// Delay before the first reconnection attempt; doubled on each retry.
+const RECONNECT_BACKOFF: Duration = Duration::from_millis(100);
+
+#[cfg(unix)]
+type BridgeStream = tokio::net::UnixStream;
+#[cfg(not(unix))]
+type BridgeStream = tokio::net::TcpStream;
+
+/// Where and as whom the MCP server talks to the FS bridge.
+#[derive(Debug, Clone)]
+pub(crate) struct BridgeEndpoint {
+    /// Unix socket path (or loopback `host:port` on other platforms).
+    pub address: String,
+    pub session_id: String,
+    pub token: String,
+}
+
+/// How long to wait for the bridge to connect and to answer each kind of
+/// request.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct BridgeTimeouts {
+    pub read: Duration,
+    pub write: Duration,
+    pub connect: Duration,
+}
+
+impl Default for BridgeTimeouts {
+    fn default() -> Self {
+        Self {
+            read: Duration::from_secs(DEFAULT_FS_READ_TIMEOUT_SECS),
+            write: Duration::from_secs(DEFAULT_FS_WRITE_TIMEOUT_SECS),
+            connect: Duration::from_secs(DEFAULT_FS_CONNECT_TIMEOUT_SECS),
+        }
+    }
+}
+
+impl BridgeTimeouts {
+    /// Read `ACP_FS_READ_TIMEOUT_SECS`, `ACP_FS_WRITE_TIMEOUT_SECS` and
+    /// `ACP_FS_CONNECT_TIMEOUT_SECS`, keeping the defaults for unset variables.
+    pub fn from_env() -> Result<Self> {
+        let defaults = Self::default();
+        Ok(Self {
+            read: timeout_from_env("ACP_FS_READ_TIMEOUT_SECS")?.unwrap_or(defaults.read),
+            write: timeout_from_env("ACP_FS_WRITE_TIMEOUT_SECS")?.unwrap_or(defaults.write),
+            connect: timeout_from_env("ACP_FS_CONNECT_TIMEOUT_SECS")?.unwrap_or(defaults.connect),
+        })
+    }
+
+    fn for_op(&self, op: BridgeOp) -> Duration {
+        match op.access() {
+            FsAccess::Read => self.read,
+            FsAccess::Write => self.write,
+        }
+    }
+}
+
+fn timeout_from_env(key: &str) -> Result<Option<Duration>> {
+    Ok(positive_from_env(key, "a whole number of seconds")?.map(Duration::from_secs))
+}
+
+/// A positive whole number from the variable `key` (described as `what` in
+/// errors), or `None` if it is unset.
+pub(super) fn positive_from_env(key: &str, what: &str) -> Result<Option<u64>> {
+    let Ok(raw) = env::var(key) else {
+        return Ok(None);
+    };
+    let value: u64 = raw
+        .trim()
+        .parse()
+        .with_context(|| format!("{key} must be {what} (got {raw:?})"))?;
+    if value == 0 {
+        bail!("{key} must be greater than 0");
+    }
+    Ok(Some(value))
+}
+
+type PendingMap = HashMap<u64, oneshot::Sender<BridgeResponse>>;
+
+/// Shared, lazily (re)connected connection to the FS bridge.
+#[derive(Clone)]
+pub(crate) struct BridgeClient {
+    endpoint: Arc<BridgeEndpoint>,
+    timeouts: BridgeTimeouts,
+    connection: Arc<Mutex<Option<Arc<Connection>>>>,
+    next_id: Arc<AtomicU64>,
+    /// Externally changed files reported by the bridge and not yet taken.
+    changed: Arc<Mutex<BTreeSet<String>>>,
+}
+
+impl BridgeClient {
+    pub fn new(endpoint: BridgeEndpoint, timeouts: BridgeTimeouts) -> Self {
+        Self {
+            endpoint: Arc::new(endpoint),
+            timeouts,
+            connection: Arc::new(Mutex::new(None)),
+            next_id: Arc::new(AtomicU64::new(1)),
+            changed: Arc::default(),
+        }
+    }
+
+    /// Send one request and wait for its response, returning the response
+    /// content and file version on success.
+    pub async fn request(&self, op: BridgeOp, args: BridgeArgs) -> Result<BridgeReply> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let payload = serde_json::to_string(&BridgeRequest {
+            id,
+            session_id: self.endpoint.session_id.clone(),
+            op,
+            args,
+        })?;
+
+        // A request that could not be written never reached the bridge, so it
+        // is safe to retry once on a fresh connection.
+        let mut retried = false;
+        let (connection, response_rx) = loop {
+            let connection = self.connection().await?;
+            let response_rx = connection.register(id).await;
+            match connection.send(&payload).await {
+                Ok(()) => break (connection, response_rx),
+                Err(err) => {
+                    connection.forget(id).await;
+                    connection.close();
+                    if retried {
+                        return Err(err.context("failed to send request to bridge"));
+                    }
+                    debug!(error = %err, "bridge connection lost; reconnecting");
+                    retried = true;
+                }
+            }
+        };
+
+        let limit = self.timeouts.for_op(op);
+        match time::timeout(limit, response_rx).await {
+            Ok(Ok(mut response)) => {
+                if !response.changed.is_empty() {
+                    let changed = std::mem::take(&mut response.changed);
+                    self.changed.lock().await.extend(changed);
+                }
+                response_result(response)
+            }
+            Ok(Err(_)) => Err(anyhow!("bridge connection closed before responding")),
+            Err(_) => {
+                connection.forget(id).await;
+                self.cancel(&connection, id).await;
+                Err(anyhow!(
+                    "bridge {} request timed out after {}s",
+                    op.as_str(),
+                    limit.as_secs()
+                ))
+            }
+        }
+    }
+
+    /// Tell the bridge to give up on request `id`, so a write the user
+    /// approves after the client stopped waiting does not land anyway. The
+    /// bridge's answer is not awaited.
+    async fn cancel(&self, connection: &Connection, id: u64) {
+        let request = BridgeRequest {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed),
+            session_id: self.endpoint.session_id.clone(),
+            op: BridgeOp::Cancel,
+            args: BridgeArgs {
+                request: Some(id),
+                ..BridgeArgs::new("")
+            },
+        };
+        let sent = match serde_json::to_string(&request) {
+            Ok(payload) => connection.send(&payload).await,
+            Err(err) => Err(err.into()),
+        };
+        if let Err(err) = sent {
+            debug!(error = %err, id, "failed to cancel timed out bridge request");
+        }
+    }
+
+    /// Files reported as changed outside the session since the last call.
+    pub async fn take_changed(&self) -> Vec<String> {
+        std::mem::take(&mut *self.changed.lock().await)
+            .into_iter()
+            .collect()
+    }
+
+    /// Return the live connection, reconnecting if it was closed.
+    async fn connection(&self) -> Result<Arc<Connection>> {
+        let mut slot = self.connection.lock().await;
+        if let Some(connection) = slot.as_ref().filter(|c| !c.is_closed()) {
+            return Ok(connection.clone());
+        }
+        *slot = None;
+
+        let mut backoff = RECONNECT_BACKOFF;
+        let mut attempt = 1;
+        let connection = loop {
+            match time::timeout(self.timeouts.connect, Connection::open(&self.endpoint)).await {
+                Ok(Ok(connection)) => break connection,
+                Ok(Err(err)) if attempt >= CONNECT_ATTEMPTS => return Err(err),
+                Err(_) if attempt >= CONNECT_ATTEMPTS => {
+                    bail!(
+                        "timed out connecting to bridge at {}",
+                        self.endpoint.address
+                    )
+                }
+                Ok(Err(err)) => {
+                    warn!(error = %err, attempt, "failed to connect to fs bridge; retrying");
+                }
+                Err(_) => warn!(attempt, "connecting to fs bridge timed out; retrying"),
+            }
+            time::sleep(backoff).await;
+            backoff *= 2;
+            attempt += 1;
+        };
+
+        let connection = Arc::new(connection);
+        *slot = Some(connection.clone());
+        Ok(connection)
+    }
+}
+
+/// One authenticated bridge connection and its response dispatcher.
+struct Connection {
+    writer: Mutex<WriteHalf<BridgeStream>>,
+    pending: Arc<Mutex<PendingMap>>,
+    closed: Arc<AtomicBool>,
+    reader_task: JoinHandle<()>,
+}
+
+impl Connection {
+    async fn open(endpoint: &BridgeEndpoint) -> Result<Self> {
+        let stream = BridgeStream::connect(&endpoint.address)
+            .await
+            .with_context(|| format!("failed to connect to bridge at {}", endpoint.address))?;
+        let (read_half, mut write_half) = tokio::io::split(stream);
+        let mut reader = BufReader::new(read_half);
+
+        let handshake = serde_json::to_string(&BridgeHandshake {
+            session_id: endpoint.session_id.clone(),
+            token: endpoint.token.clone(),
+        })?;
+        write_line(&mut write_half, &handshake).await?;
+        let mut ack = String::new();
+        if reader.read_line(&mut ack).await? == 0 {
+            bail!("bridge closed connection during handshake");
+        }
+        let ack: BridgeResponse = serde_json::from_str(&ack)?;
+        response_result(ack).context("bridge rejected handshake")?;
+
+        let pending = Arc::new(Mutex::new(PendingMap::new()));
+        let closed = Arc::new(AtomicBool::new(false));
+        let reader_task = tokio::spawn(dispatch_responses(reader, pending.clone(), closed.clone()));
+
+        Ok(Self {
+            writer: Mutex::new(write_half),
+            pending,
+            closed,
+            reader_task,
+        })
+    }
+
+    fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Acquire)
+    }
+
+    fn close(&self) {
+        self.closed.store(true, Ordering::Release);
+        self.reader_task.abort();
+    }
+
+    async fn register(&self, id: u64) -> oneshot::Receiver<BridgeResponse> {
+        let (tx, rx) = oneshot::channel();
+        self.pending.lock().await.insert(id, tx);
+        rx
+    }
+
+    async fn forget(&self, id: u64) {
+        self.pending.lock().await.remove(&id);
+    }
+
+    async fn send(&self, payload: &str) -> Result<()> {
+        if self.is_closed() {
+            bail!("bridge connection is closed");
+        }
+        let mut writer = self.writer.lock().await;
+        write_line(&mut *writer, payload).await
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        self.reader_task.abort();
+    }
+}
+
+/// Route responses to their waiting requests until the connection ends, then
+/// fail everything still pending.
+async fn dispatch_responses(
+    reader: BufReader<ReadHalf<BridgeStream>>,
+    pending: Arc<Mutex<PendingMap>>,
+    closed: Arc<AtomicBool>,
+) {
+    let mut lines = reader.lines();
+    loop {
+        match lines.next_line().await {
+            Ok(Some(line)) => {
+                let response: BridgeResponse = match serde_json::from_str(&line) {
+                    Ok(response) => response,
+                    Err(err) => {
+                        warn!(error = %err, "fs bridge sent malformed response");
+                        continue;
+                    }
+                };
+                // The sender is gone if the request already timed out, and
+                // `cancel` requests never have one.
+                if let Some(tx) = pending.lock().await.remove(&response.id) {
+                    let _ = tx.send(response);
+                }
+            }
+            Ok(None) => {
+                debug!("fs bridge closed the connection");
+                break;
+            }
+            Err(err) => {
+                warn!(error = %err, "fs bridge connection failed");
+                break;
+            }
+        }
+    }
+    closed.store(true, Ordering::Release);
+    pending.lock().await.clear();
+}
+
+async fn write_line<W>(writer: &mut W, line: &str) -> Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    writer.write_all(line.as_bytes()).await?;
+    writer.write_all(b"\n").await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+/// Successful bridge response.
+#[derive(Debug, Clone, Default)]
+pub struct BridgeReply {
+    pub content: String,
+    /// Content version of the file, for reads and writes.
+    pub version: Option<String>,
+    /// Set instead of `content` when a read hits a binary file.
+    pub binary: Option<BinarySummary>,
+    /// 1-based line a read's content starts at.
+    pub start_line: Option<u64>,
+    /// Lines in the whole file, for reads.
+    pub total_lines: Option<u64>,
+    /// Where a read's content came from.
+    pub source: Option<ReadSource>,
+    /// Why a read came from disk rather than the client.
+    pub fallback_reason: Option<String>,
+}
+
+fn response_result(response: BridgeResponse) -> Result<BridgeReply> {
+    if response.success {
+        Ok(BridgeReply {
+            content: response.content.unwrap_or_default(),
+            version: response.version,
+            binary: response.binary,
+            start_line: response.start_line,
+            total_lines: response.total_lines,
+            source: response.source,
+            fallback_reason: response.fallback_reason,
+        })
+    } else {
+        Err(anyhow!(
+            response.error.unwrap_or_else(|| "bridge error".to_string())
+        ))
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use serde_json::Value;
+    use tokio::{io::AsyncWriteExt, net::UnixListener};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn timed_out_requests_are_cancelled() {
+        let dir = tempfile::tempdir().unwrap();
+        let address = dir.path().join("bridge.sock");
+        let listener = UnixListener::bind(&address).unwrap();
+        // Answers the handshake, then nothing: returns the next two requests.
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (read_half, mut write_half) = stream.into_split();
+            let mut lines = BufReader::new(read_half).lines();
+            lines.next_line().await.unwrap();
+            write_half
+                .write_all(b"{\"id\":0,\"success\":true}\n")
+                .await
+                .unwrap();
+            let mut requests = Vec::new();
+            for _ in 0..2 {
+                let line = lines.next_line().await.unwrap().unwrap();
+                requests.push(serde_json::from_str::<Value>(&line).unwrap());
+            }
+            requests
+        });
+        let client = BridgeClient::new(
+            BridgeEndpoint {
+                address: address.display().to_string(),
+                session_id: "s1".to_string(),
+                token: "t".to_string(),
+            },
+            BridgeTimeouts {
+                write: Duration::from_millis(50),
+                ..BridgeTimeouts::default()
+            },
+        );
+
+        let args = BridgeArgs {
+            content: Some("new".to_string()),
+            ..BridgeArgs::new("a.txt")
+        };
+        let err = client.request(BridgeOp::Write, args).await.unwrap_err();
+        assert!(err.to_string().contains("timed out"), "{err}");
+
+        let requests = server.await.unwrap();
+        assert_eq!(requests[0]["op"], "write");
+        assert_eq!(requests[1]["op"], "cancel");
+        assert_eq!(requests[1]["request"], requests[0]["id"]);
+    }
+}
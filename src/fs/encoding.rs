@@ -0,0 +1,287 @@
+//! Text encoding and layout of files on disk.
+//!
+//! The bridge hands text to the model as UTF-8 strings. To write edits back
+//! without corrupting the file, it remembers how the file was stored: UTF-8
+//! or UTF-16 (detected by BOM), whether it starts with a BOM, its line
+//! endings and whether it ends with a newline. Files in any other encoding
+//! are refused rather than decoded lossily.
+
+use std::path::Path;
+
+const UTF8_BOM: &[u8] = &[0xEF, 0xBB, 0xBF];
+const UTF16_LE_BOM: &[u8] = &[0xFF, 0xFE];
+const UTF16_BE_BOM: &[u8] = &[0xFE, 0xFF];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum TextEncoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum LineEnding {
+    Lf,
+    Crlf,
+    /// Both kinds occur; left as they are.
+    Mixed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct TextFormat {
+    pub encoding: TextEncoding,
+    pub bom: bool,
+    /// `None` for files without line breaks.
+    pub line_ending: Option<LineEnding>,
+    pub final_newline: bool,
+}
+
+impl Default for TextFormat {
+    fn default() -> Self {
+        Self {
+            encoding: TextEncoding::Utf8,
+            bom: false,
+            line_ending: None,
+            final_newline: false,
+        }
+    }
+}
+
+/// File content decoded to a string, plus how it was stored.
+#[derive(Debug, Clone)]
+pub(crate) struct DecodedText {
+    /// Content without the BOM.
+    pub text: String,
+    pub format: TextFormat,
+}
+
+/// Decode a file read from disk. Fails for files that are neither UTF-8 nor
+/// UTF-16 with a BOM.
+pub(crate) fn decode(path: &Path, bytes: &[u8]) -> Result<DecodedText, String> {
+    let (text, encoding, bom) = if let Some(rest) = bytes.strip_prefix(UTF8_BOM) {
+        (utf8(path, rest)?, TextEncoding::Utf8, true)
+    } else if let Some(rest) = bytes.strip_prefix(UTF16_LE_BOM) {
+        (
+            utf16(path, rest, u16::from_le_bytes)?,
+            TextEncoding::Utf16Le,
+            true,
+        )
+    } else if let Some(rest) = bytes.strip_prefix(UTF16_BE_BOM) {
+        (
+            utf16(path, rest, u16::from_be_bytes)?,
+            TextEncoding::Utf16Be,
+            true,
+        )
+    } else {
+        (utf8(path, bytes)?, TextEncoding::Utf8, false)
+    };
+    let format = TextFormat {
+        encoding,
+        bom,
+        line_ending: detect_line_ending(&text),
+        final_newline: text.ends_with('\n'),
+    };
+    Ok(DecodedText { text, format })
+}
+
+/// Whether a file starting with `head` has a byte order mark, so the bridge
+/// reads and writes it itself.
+pub(crate) fn has_bom(head: &[u8]) -> bool {
+    [UTF8_BOM, UTF16_LE_BOM, UTF16_BE_BOM]
+        .iter()
+        .any(|bom| head.starts_with(bom))
+}
+
+/// Refuse a file whose first bytes are not UTF-8 without reading all of it.
+/// A character cut off at the end of `head` is not an error.
+pub(crate) fn check_utf8_head(path: &Path, head: &[u8]) -> Result<(), String> {
+    match std::str::from_utf8(head) {
+        Err(err) if err.error_len().is_some() => utf8(path, head).map(drop),
+        _ => Ok(()),
+    }
+}
+
+impl TextFormat {
+    /// Bring new content in line with the file's line endings, and restore a
+    /// final newline the file had.
+    pub fn normalize(&self, text: &str) -> String {
+        let mut text = match self.line_ending {
+            Some(LineEnding::Crlf) => text.replace("\r\n", "\n").replace('\n', "\r\n"),
+            Some(LineEnding::Lf) => text.replace("\r\n", "\n"),
+            Some(LineEnding::Mixed) | None => text.to_string(),
+        };
+        if self.final_newline && !text.is_empty() && !text.ends_with('\n') {
+            text.push_str(match self.line_ending {
+                Some(LineEnding::Crlf) => "\r\n",
+                _ => "\n",
+            });
+        }
+        text
+    }
+
+    /// Encode content for writing to disk, with the file's BOM.
+    pub fn encode(&self, text: &str) -> Vec<u8> {
+        match self.encoding {
+            TextEncoding::Utf8 => {
+                let mut bytes = Vec::with_capacity(text.len() + UTF8_BOM.len());
+                if self.bom {
+                    bytes.extend_from_slice(UTF8_BOM);
+                }
+                bytes.extend_from_slice(text.as_bytes());
+                bytes
+            }
+            TextEncoding::Utf16Le => encode_utf16(text, UTF16_LE_BOM, u16::to_le_bytes),
+            TextEncoding::Utf16Be => encode_utf16(text, UTF16_BE_BOM, u16::to_be_bytes),
+        }
+    }
+
+    /// Whether the file must be written by the bridge itself: clients write
+    /// UTF-8 and may drop the BOM.
+    pub fn needs_local_write(&self) -> bool {
+        self.encoding != TextEncoding::Utf8 || self.bom
+    }
+}
+
+fn utf8(path: &Path, bytes: &[u8]) -> Result<String, String> {
+    String::from_utf8(bytes.to_vec()).map_err(|err| {
+        let hint = if bytes.contains(&0) {
+            "it looks like UTF-16 without a byte order mark or a binary file"
+        } else {
+            "it may be Latin-1 or another legacy encoding"
+        };
+        format!(
+            "{} is not valid UTF-8 ({hint}; invalid byte at offset {}). Refusing to treat it as \
+             text to avoid corrupting it; convert it to UTF-8 first.",
+            path.display(),
+            err.utf8_error().valid_up_to()
+        )
+    })
+}
+
+fn utf16(path: &Path, bytes: &[u8], unit: fn([u8; 2]) -> u16) -> Result<String, String> {
+    if !bytes.len().is_multiple_of(2) {
+        return Err(format!(
+            "{} has a UTF-16 byte order mark but an odd number of bytes",
+            path.display()
+        ));
+    }
+    let units = bytes
+        .chunks_exact(2)
+        .map(|pair| unit([pair[0], pair[1]]))
+        .collect::<Vec<_>>();
+    String::from_utf16(&units)
+        .map_err(|err| format!("{} is not valid UTF-16: {err}", path.display()))
+}
+
+fn encode_utf16(text: &str, bom: &[u8], bytes: fn(u16) -> [u8; 2]) -> Vec<u8> {
+    let mut encoded = Vec::with_capacity(bom.len() + text.len() * 2);
+    encoded.extend_from_slice(bom);
+    for unit in text.encode_utf16() {
+        encoded.extend_from_slice(&bytes(unit));
+    }
+    encoded
+}
+
+fn detect_line_ending(text: &str) -> Option<LineEnding> {
+    let crlf = text.matches("\r\n").count();
+    let lf = text.matches('\n').count() - crlf;
+    match (crlf > 0, lf > 0) {
+        (true, false) => Some(LineEnding::Crlf),
+        (false, true) => Some(LineEnding::Lf),
+        (true, true) => Some(LineEnding::Mixed),
+        (false, false) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decoded(bytes: &[u8]) -> DecodedText {
+        decode(Path::new("f.txt"), bytes).unwrap()
+    }
+
+    #[test]
+    fn plain_utf8_is_detected_with_its_layout() {
+        let text = decoded(b"a\r\nb\r\n");
+        assert_eq!(text.text, "a\r\nb\r\n");
+        assert_eq!(
+            text.format,
+            TextFormat {
+                encoding: TextEncoding::Utf8,
+                bom: false,
+                line_ending: Some(LineEnding::Crlf),
+                final_newline: true,
+            }
+        );
+        assert_eq!(
+            decoded(b"a\nb\r\n").format.line_ending,
+            Some(LineEnding::Mixed)
+        );
+        assert_eq!(decoded(b"one line").format.line_ending, None);
+    }
+
+    #[test]
+    fn byte_order_marks_are_detected_and_stripped() {
+        let utf8 = decoded(b"\xEF\xBB\xBFhi\n");
+        assert_eq!(utf8.text, "hi\n");
+        assert!(utf8.format.bom);
+        assert!(utf8.format.needs_local_write());
+
+        let le = decoded(&[0xFF, 0xFE, b'h', 0, b'i', 0]);
+        assert_eq!(le.text, "hi");
+        assert_eq!(le.format.encoding, TextEncoding::Utf16Le);
+
+        let be = decoded(&[0xFE, 0xFF, 0, b'h', 0, b'i']);
+        assert_eq!(be.text, "hi");
+        assert_eq!(be.format.encoding, TextEncoding::Utf16Be);
+    }
+
+    #[test]
+    fn every_encoding_round_trips() {
+        for bytes in [
+            &b"plain\n"[..],
+            b"\xEF\xBB\xBFbom\r\n",
+            &[0xFF, 0xFE, b'l', 0, b'e', 0, b'\n', 0],
+            &[0xFE, 0xFF, 0, b'b', 0, b'e', 0, b'\n'],
+        ] {
+            let text = decoded(bytes);
+            assert_eq!(text.format.encode(&text.text), bytes);
+        }
+    }
+
+    #[test]
+    fn legacy_encodings_are_refused() {
+        let err = decode(Path::new("latin1.txt"), b"caf\xE9\n").unwrap_err();
+        assert!(err.contains("Latin-1"), "{err}");
+        assert!(err.contains("offset 3"), "{err}");
+
+        let err = decode(Path::new("odd.txt"), &[0xFF, 0xFE, b'x']).unwrap_err();
+        assert!(err.contains("odd number of bytes"), "{err}");
+    }
+
+    #[test]
+    fn file_heads_are_checked_without_the_rest() {
+        assert!(has_bom(b"\xEF\xBB\xBFtext"));
+        assert!(has_bom(&[0xFE, 0xFF, 0, b'a']));
+        assert!(!has_bom(b"text"));
+
+        let path = Path::new("f.txt");
+        // The head may end in the middle of a character.
+        assert!(check_utf8_head(path, "caf\u{e9}".as_bytes().split_last().unwrap().1).is_ok());
+        let err = check_utf8_head(path, b"caf\xE9 au lait").unwrap_err();
+        assert!(err.contains("offset 3"), "{err}");
+    }
+
+    #[test]
+    fn normalize_keeps_line_endings_and_final_newline() {
+        let crlf = decoded(b"a\r\nb\r\n").format;
+        assert_eq!(crlf.normalize("x\ny"), "x\r\ny\r\n");
+
+        let lf = decoded(b"a\nb").format;
+        assert_eq!(lf.normalize("x\r\ny"), "x\ny");
+
+        let mixed = decoded(b"a\nb\r\n").format;
+        assert_eq!(mixed.normalize("x\r\ny\n"), "x\r\ny\n");
+    }
+}
@@ -0,0 +1,292 @@
+//! Ranged reads of large files straight from disk.
+//!
+//! Files of at least [`LARGE_FILE_BYTES`] are not loaded whole. One streaming
+//! pass records the byte offset of every [`CHECKPOINT_STRIDE`]th line, the
+//! line count and the content hash; later reads seek to the nearest
+//! checkpoint and read only the requested lines. Indexes are cached per path
+//! until the file's size or modification time changes.
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufRead, BufReader, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+use sha2::{Digest, Sha256};
+
+use super::binary::{self, BINARY_SNIFF_BYTES, BinarySummary};
+
+/// Files at least this large are read by range instead of whole.
+pub(crate) const LARGE_FILE_BYTES: u64 = 8 * 1024 * 1024;
+/// Lines between index checkpoints.
+const CHECKPOINT_STRIDE: u64 = 1000;
+/// Indexes kept at once.
+const MAX_CACHED_INDEXES: usize = 16;
+const READ_BUFFER_BYTES: usize = 256 * 1024;
+
+const UTF8_BOM: &[u8] = &[0xEF, 0xBB, 0xBF];
+
+/// Line offsets and summary data for one version of a large file.
+pub(crate) struct LineIndex {
+    len: u64,
+    modified: Option<SystemTime>,
+    /// Byte offset of line `n * CHECKPOINT_STRIDE` (0-based) at index `n`.
+    checkpoints: Vec<u64>,
+    /// Lines as numbered by `read_text_file`; a final line without a newline
+    /// counts.
+    pub total_lines: u64,
+    /// Same value `content_version` gives for the decoded content.
+    pub version: String,
+    /// Set for binary files, which have no checkpoints.
+    pub binary: Option<BinarySummary>,
+}
+
+impl LineIndex {
+    /// Stream `path` once to build its index.
+    fn build(path: &Path) -> Result<Self, String> {
+        let fail = |err: std::io::Error| format!("failed to read {}: {err}", path.display());
+        let mut file = File::open(path).map_err(fail)?;
+        let metadata = file.metadata().map_err(fail)?;
+
+        let mut head = vec![0; BINARY_SNIFF_BYTES];
+        let head_len = read_up_to(&mut file, &mut head).map_err(fail)?;
+        head.truncate(head_len);
+        let is_binary = binary::is_binary(path, &head);
+        if !is_binary && (head.starts_with(&[0xFF, 0xFE]) || head.starts_with(&[0xFE, 0xFF])) {
+            return Err(format!(
+                "{} is a UTF-16 file larger than {} MB; ranged reads only support UTF-8",
+                path.display(),
+                LARGE_FILE_BYTES / (1024 * 1024)
+            ));
+        }
+        let start = if !is_binary && head.starts_with(UTF8_BOM) {
+            UTF8_BOM.len() as u64
+        } else {
+            0
+        };
+
+        file.seek(SeekFrom::Start(start)).map_err(fail)?;
+        let mut reader = BufReader::with_capacity(READ_BUFFER_BYTES, file);
+        let mut hasher = Sha256::new();
+        let mut checkpoints = vec![start];
+        let mut newlines = 0u64;
+        let mut offset = start;
+        let mut last_byte = None;
+        loop {
+            let chunk = reader.fill_buf().map_err(fail)?;
+            if chunk.is_empty() {
+                break;
+            }
+            hasher.update(chunk);
+            if !is_binary {
+                for (position, _) in chunk.iter().enumerate().filter(|(_, b)| **b == b'\n') {
+                    newlines += 1;
+                    if newlines.is_multiple_of(CHECKPOINT_STRIDE) {
+                        checkpoints.push(offset + position as u64 + 1);
+                    }
+                }
+            }
+            last_byte = chunk.last().copied();
+            let consumed = chunk.len();
+            offset += consumed as u64;
+            reader.consume(consumed);
+        }
+        let digest = hasher.finalize();
+
+        let binary = is_binary.then(|| binary::summarize_large(path, metadata.len(), &digest));
+        let trailing_line = last_byte.is_some_and(|byte| byte != b'\n');
+        Ok(Self {
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+            checkpoints: if is_binary { Vec::new() } else { checkpoints },
+            total_lines: newlines + u64::from(trailing_line),
+            version: digest[..8]
+                .iter()
+                .map(|byte| format!("{byte:02x}"))
+                .collect(),
+            binary,
+        })
+    }
+
+    /// Read `count` lines starting at 1-based `start_line`, keeping line
+    /// terminators.
+    pub fn read_lines(&self, path: &Path, start_line: u64, count: u64) -> Result<String, String> {
+        let fail = |err: std::io::Error| format!("failed to read {}: {err}", path.display());
+        let first = start_line.saturating_sub(1);
+        if first >= self.total_lines || count == 0 {
+            return Ok(String::new());
+        }
+        let checkpoint = ((first / CHECKPOINT_STRIDE) as usize).min(self.checkpoints.len() - 1);
+        let mut file = File::open(path).map_err(fail)?;
+        file.seek(SeekFrom::Start(self.checkpoints[checkpoint]))
+            .map_err(fail)?;
+        let mut reader = BufReader::with_capacity(READ_BUFFER_BYTES, file);
+
+        let mut scratch = Vec::new();
+        for _ in checkpoint as u64 * CHECKPOINT_STRIDE..first {
+            scratch.clear();
+            if reader.read_until(b'\n', &mut scratch).map_err(fail)? == 0 {
+                return Ok(String::new());
+            }
+        }
+        let mut window = Vec::new();
+        for _ in 0..count {
+            if reader.read_until(b'\n', &mut window).map_err(fail)? == 0 {
+                break;
+            }
+        }
+        String::from_utf8(window).map_err(|err| {
+            format!(
+                "{} is not valid UTF-8 near line {start_line} (invalid byte {} bytes into the \
+                 requested range)",
+                path.display(),
+                err.utf8_error().valid_up_to()
+            )
+        })
+    }
+
+    fn is_current(&self, metadata: &std::fs::Metadata) -> bool {
+        self.len == metadata.len() && self.modified == metadata.modified().ok()
+    }
+}
+
+/// Per-path cache of [`LineIndex`]es.
+#[derive(Default)]
+pub(crate) struct LineIndexCache {
+    entries: Mutex<HashMap<PathBuf, Arc<LineIndex>>>,
+}
+
+impl LineIndexCache {
+    /// The index for the current version of `path`, building it if needed.
+    /// Blocking; call from a blocking task.
+    pub fn get(&self, path: &Path) -> Result<Arc<LineIndex>, String> {
+        let metadata = std::fs::metadata(path)
+            .map_err(|err| format!("failed to read {}: {err}", path.display()))?;
+        if let Ok(entries) = self.entries.lock()
+            && let Some(index) = entries
+                .get(path)
+                .filter(|index| index.is_current(&metadata))
+        {
+            return Ok(index.clone());
+        }
+
+        let index = Arc::new(LineIndex::build(path)?);
+        if let Ok(mut entries) = self.entries.lock() {
+            if entries.len() >= MAX_CACHED_INDEXES
+                && !entries.contains_key(path)
+                && let Some(evicted) = entries.keys().next().cloned()
+            {
+                entries.remove(&evicted);
+            }
+            entries.insert(path.to_path_buf(), index.clone());
+        }
+        Ok(index)
+    }
+}
+
+fn read_up_to(file: &mut File, buf: &mut [u8]) -> std::io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match file.read(&mut buf[filled..])? {
+            0 => break,
+            read => filled += read,
+        }
+    }
+    Ok(filled)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+    use crate::fs::bridge::content_version;
+
+    /// A file of `count` numbered lines, the last one without a newline when
+    /// `final_newline` is false.
+    fn numbered(count: u64, final_newline: bool) -> tempfile::NamedTempFile {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        for n in 1..=count {
+            write!(file, "line {n}").unwrap();
+            if n < count || final_newline {
+                writeln!(file).unwrap();
+            }
+        }
+        file
+    }
+
+    #[test]
+    fn checkpoints_mark_every_strideth_line() {
+        let file = numbered(2500, true);
+        let index = LineIndex::build(file.path()).unwrap();
+        assert_eq!(index.total_lines, 2500);
+        assert_eq!(index.checkpoints.len(), 3);
+        let text = std::fs::read_to_string(file.path()).unwrap();
+        for (n, offset) in index.checkpoints.iter().enumerate() {
+            let line = n as u64 * CHECKPOINT_STRIDE + 1;
+            assert!(text[*offset as usize..].starts_with(&format!("line {line}\n")));
+        }
+    }
+
+    #[test]
+    fn ranges_are_read_across_checkpoints() {
+        let file = numbered(2500, true);
+        let index = LineIndex::build(file.path()).unwrap();
+        assert_eq!(
+            index.read_lines(file.path(), 999, 3).unwrap(),
+            "line 999\nline 1000\nline 1001\n"
+        );
+        assert_eq!(
+            index.read_lines(file.path(), 2001, 1).unwrap(),
+            "line 2001\n"
+        );
+        assert_eq!(index.read_lines(file.path(), 2501, 5).unwrap(), "");
+        assert_eq!(index.read_lines(file.path(), 1, 0).unwrap(), "");
+    }
+
+    #[test]
+    fn tail_windows_include_a_last_line_without_newline() {
+        let file = numbered(1500, false);
+        let index = LineIndex::build(file.path()).unwrap();
+        assert_eq!(index.total_lines, 1500);
+        // The bridge reads the last `n` lines from `total_lines - n + 1`.
+        let tail = index
+            .read_lines(file.path(), index.total_lines - 2 + 1, 2)
+            .unwrap();
+        assert_eq!(tail, "line 1499\nline 1500");
+        assert_eq!(
+            index
+                .read_lines(file.path(), 1400, u64::MAX)
+                .unwrap()
+                .lines()
+                .count(),
+            101
+        );
+    }
+
+    #[test]
+    fn byte_order_mark_is_skipped_and_version_matches_the_content() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(b"\xEF\xBB\xBFfirst\nsecond\n").unwrap();
+        let index = LineIndex::build(file.path()).unwrap();
+        assert_eq!(index.read_lines(file.path(), 1, 1).unwrap(), "first\n");
+
+        assert_eq!(index.version, content_version("first\nsecond\n"));
+    }
+
+    #[test]
+    fn cache_rebuilds_when_the_file_changes() {
+        let mut file = numbered(10, true);
+        let cache = LineIndexCache::default();
+        let first = cache.get(file.path()).unwrap();
+        assert!(Arc::ptr_eq(&first, &cache.get(file.path()).unwrap()));
+
+        writeln!(file, "line 11").unwrap();
+        let second = cache.get(file.path()).unwrap();
+        assert_eq!(second.total_lines, 11);
+        assert_ne!(first.version, second.version);
+    }
+}
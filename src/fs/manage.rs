@@ -0,0 +1,96 @@
+//! Disk operations behind the bridge's `delete`, `move` and
+//! `create_directory` ops. Callers have already checked the session's policy
+//! and approval for every path involved.
+
+use std::{io, path::Path};
+
+use tokio::fs;
+
+/// Delete a file, symlink or directory. Non-empty directories are only
+/// removed when `recursive` is set. Symlinks are removed, never followed.
+pub(crate) async fn delete_path(path: &Path, recursive: bool) -> Result<String, String> {
+    let metadata = fs::symlink_metadata(path)
+        .await
+        .map_err(|err| format!("failed to delete {}: {err}", path.display()))?;
+
+    let result = if metadata.is_dir() {
+        if recursive {
+            fs::remove_dir_all(path).await
+        } else {
+            fs::remove_dir(path).await.map_err(|err| {
+                if err.kind() == io::ErrorKind::DirectoryNotEmpty {
+                    io::Error::other("directory is not empty; pass recursive=true to delete it")
+                } else {
+                    err
+                }
+            })
+        }
+    } else {
+        fs::remove_file(path).await
+    };
+    result.map_err(|err| format!("failed to delete {}: {err}", path.display()))?;
+
+    Ok(if metadata.is_dir() {
+        format!("Deleted directory {}", path.display())
+    } else {
+        format!("Deleted {}", path.display())
+    })
+}
+
+/// Move or rename `source` to `destination`, creating missing parent
+/// directories. An existing destination is only replaced with `overwrite`.
+pub(crate) async fn move_path(
+    source: &Path,
+    destination: &Path,
+    overwrite: bool,
+) -> Result<String, String> {
+    if let Err(err) = fs::symlink_metadata(source).await {
+        return Err(format!("failed to move {}: {err}", source.display()));
+    }
+    if !overwrite && fs::symlink_metadata(destination).await.is_ok() {
+        return Err(format!(
+            "{} already exists; pass overwrite=true to replace it",
+            destination.display()
+        ));
+    }
+    if let Some(parent) = destination.parent() {
+        fs::create_dir_all(parent).await.map_err(|err| {
+            format!(
+                "failed to create parent directories {}: {err}",
+                parent.display()
+            )
+        })?;
+    }
+    fs::rename(source, destination).await.map_err(|err| {
+        format!(
+            "failed to move {} to {}: {err}",
+            source.display(),
+            destination.display()
+        )
+    })?;
+    Ok(format!(
+        "Moved {} to {}",
+        source.display(),
+        destination.display()
+    ))
+}
+
+/// Create `path` and any missing parents. Succeeds if it already exists.
+pub(crate) async fn create_directory(path: &Path) -> Result<String, String> {
+    match fs::metadata(path).await {
+        Ok(metadata) if metadata.is_dir() => {
+            return Ok(format!("{} already exists", path.display()));
+        }
+        Ok(_) => {
+            return Err(format!(
+                "{} already exists and is not a directory",
+                path.display()
+            ));
+        }
+        Err(_) => {}
+    }
+    fs::create_dir_all(path)
+        .await
+        .map_err(|err| format!("failed to create {}: {err}", path.display()))?;
+    Ok(format!("Created directory {}", path.display()))
+}
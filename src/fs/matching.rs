@@ -0,0 +1,500 @@
+//! Locating `old_string` for the edit tools.
+//!
+//! Matching is tiered: an exact match wins, then a line-by-line match that
+//! ignores line endings and trailing whitespace, then one that also ignores
+//! indentation, and finally a bounded fuzzy match over windows of the same
+//! number of lines. When nothing matches, the error shows the closest
+//! candidate with line numbers so the caller can fix its `old_string`.
+
+use std::ops::Range;
+
+/// Minimum average line similarity for a fuzzy match to be applied.
+const FUZZY_THRESHOLD: f64 = 0.9;
+/// Minimum similarity of every single line of a fuzzy match, so one
+/// unrelated line cannot hide behind an otherwise close window.
+const FUZZY_LINE_THRESHOLD: f64 = 0.8;
+/// Fuzzy matching is skipped for longer `old_string`s ...
+const MAX_FUZZY_NEEDLE_LINES: usize = 50;
+/// ... and for larger files, to keep the search bounded.
+const MAX_FUZZY_CONTENT_LINES: usize = 10_000;
+/// Windows whose first line is less similar than this are not scored.
+const FUZZY_PREFILTER: f64 = 0.6;
+/// Lines of the closest candidate shown in a "not found" error.
+const MAX_CANDIDATE_LINES: usize = 12;
+/// Matches listed in an ambiguity error.
+const MAX_AMBIGUOUS_LINES: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum MatchTier {
+    Exact,
+    /// Equal up to line endings and trailing whitespace.
+    Whitespace,
+    /// Equal up to leading and trailing whitespace on every line.
+    Indentation,
+    Fuzzy,
+}
+
+impl MatchTier {
+    /// How the match was relaxed, for reporting back to the model.
+    pub fn describe(self) -> Option<&'static str> {
+        match self {
+            MatchTier::Exact => None,
+            MatchTier::Whitespace => Some("ignoring line endings and trailing whitespace"),
+            MatchTier::Indentation => Some("ignoring indentation"),
+            MatchTier::Fuzzy => Some("approximately"),
+        }
+    }
+}
+
+/// Where an edit applies and how its replacement must be adapted.
+#[derive(Debug)]
+pub(crate) struct EditTarget {
+    /// Byte ranges to replace, in ascending order and non-overlapping.
+    pub ranges: Vec<Range<usize>>,
+    pub tier: MatchTier,
+    /// Indentation of `old_string` and of the matched text, when they differ.
+    reindent: Option<(String, String)>,
+    /// The matched text uses CRLF line endings.
+    crlf: bool,
+}
+
+impl EditTarget {
+    /// Adapt `new_text` to the matched text's indentation and line endings.
+    pub fn adapt_replacement(&self, new_text: &str) -> String {
+        let mut text = match &self.reindent {
+            Some((from, to)) => new_text
+                .split('\n')
+                .map(|line| match line.strip_prefix(from.as_str()) {
+                    Some(rest) => format!("{to}{rest}"),
+                    None => line.to_string(),
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+            None => new_text.to_string(),
+        };
+        if self.crlf && !text.contains("\r\n") {
+            text = text.replace('\n', "\r\n");
+        }
+        text
+    }
+}
+
+/// Find where `old_text` applies in `content`.
+///
+/// Without `replace_all`, more than one match at the first tier that finds
+/// any is an error. Fuzzy matches are never used with `replace_all`.
+pub(crate) fn find_edit_target(
+    content: &str,
+    old_text: &str,
+    replace_all: bool,
+) -> Result<EditTarget, String> {
+    let exact = content
+        .match_indices(old_text)
+        .map(|(start, found)| start..start + found.len())
+        .collect::<Vec<_>>();
+    if !exact.is_empty() {
+        return select(content, exact, replace_all, MatchTier::Exact, None);
+    }
+
+    let lines = split_lines(content);
+    let needle = old_text.lines().collect::<Vec<_>>();
+    if needle.is_empty() || needle.iter().all(|line| line.trim().is_empty()) {
+        return Err(not_found(content, &lines, &needle));
+    }
+    let include_terminator = old_text.ends_with('\n');
+
+    for (tier, normalize) in [
+        (MatchTier::Whitespace, str::trim_end as fn(&str) -> &str),
+        (MatchTier::Indentation, str::trim),
+    ] {
+        let starts = (0..=lines.len().saturating_sub(needle.len()))
+            .filter(|&start| {
+                start + needle.len() <= lines.len()
+                    && needle.iter().enumerate().all(|(offset, expected)| {
+                        normalize(lines[start + offset].text(content)) == normalize(expected)
+                    })
+            })
+            .collect::<Vec<_>>();
+        let Some(&first) = starts.first() else {
+            continue;
+        };
+        // Overlapping windows (e.g. repeated blank-ish lines) count once.
+        let mut ranges = Vec::new();
+        let mut next_free = 0;
+        for start in starts {
+            if start >= next_free {
+                ranges.push(window_range(
+                    &lines,
+                    start,
+                    needle.len(),
+                    include_terminator,
+                ));
+                next_free = start + needle.len();
+            }
+        }
+        let reindent = (tier == MatchTier::Indentation)
+            .then(|| indentation_change(content, &lines, first, &needle))
+            .flatten();
+        return select(content, ranges, replace_all, tier, reindent);
+    }
+
+    if !replace_all
+        && needle.len() <= MAX_FUZZY_NEEDLE_LINES
+        && lines.len() <= MAX_FUZZY_CONTENT_LINES
+    {
+        let scored = score_windows(content, &lines, &needle);
+        if let Some(&(best_start, best_score)) = scored
+            .iter()
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .filter(|(_, score)| *score >= FUZZY_THRESHOLD)
+        {
+            let rivals = scored
+                .iter()
+                .filter(|(start, score)| {
+                    start.abs_diff(best_start) >= needle.len()
+                        && *score >= FUZZY_THRESHOLD
+                        && best_score - score < 0.02
+                })
+                .count();
+            if rivals > 0 {
+                return Err(format!(
+                    "The provided `old_string` does not appear exactly in the file and is \
+                     approximately similar to {} places. Copy the exact text from the file. \
+                     No edits were applied.",
+                    rivals + 1
+                ));
+            }
+            let ranges = vec![window_range(
+                &lines,
+                best_start,
+                needle.len(),
+                include_terminator,
+            )];
+            let reindent = indentation_change(content, &lines, best_start, &needle);
+            return select(content, ranges, false, MatchTier::Fuzzy, reindent);
+        }
+    }
+
+    Err(not_found(content, &lines, &needle))
+}
+
+/// The lines of `content` most similar to `text`, rendered with line
+/// numbers.
+pub(crate) fn closest_match(content: &str, text: &str) -> Option<String> {
+    let needle = text.lines().collect::<Vec<_>>();
+    closest_candidate(content, &split_lines(content), &needle)
+}
+
+fn select(
+    content: &str,
+    ranges: Vec<Range<usize>>,
+    replace_all: bool,
+    tier: MatchTier,
+    reindent: Option<(String, String)>,
+) -> Result<EditTarget, String> {
+    if ranges.len() > 1 && !replace_all {
+        let mut at = ranges
+            .iter()
+            .take(MAX_AMBIGUOUS_LINES)
+            .map(|range| line_number(content, range.start).to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+        if ranges.len() > MAX_AMBIGUOUS_LINES {
+            at.push_str(", …");
+        }
+        return Err(format!(
+            "The provided `old_string` matches {} places (starting at lines {at}). Include more \
+             surrounding context to make it unique, or set replace_all=true. No edits were \
+             applied.",
+            ranges.len()
+        ));
+    }
+    let crlf = tier != MatchTier::Exact
+        && ranges
+            .first()
+            .is_some_and(|range| content[range.clone()].contains("\r\n"));
+    Ok(EditTarget {
+        ranges,
+        tier,
+        reindent,
+        crlf,
+    })
+}
+
+/// A line of `content`: `start..end` excludes the terminator, `next` is the
+/// start of the following line.
+struct Line {
+    start: usize,
+    end: usize,
+    next: usize,
+}
+
+impl Line {
+    fn text<'a>(&self, content: &'a str) -> &'a str {
+        &content[self.start..self.end]
+    }
+}
+
+fn split_lines(content: &str) -> Vec<Line> {
+    let mut lines = Vec::new();
+    let mut start = 0;
+    while start < content.len() {
+        let (end, next) = match content[start..].find('\n') {
+            Some(offset) => (start + offset, start + offset + 1),
+            None => (content.len(), content.len()),
+        };
+        let end = if content[start..end].ends_with('\r') {
+            end - 1
+        } else {
+            end
+        };
+        lines.push(Line { start, end, next });
+        start = next;
+    }
+    lines
+}
+
+fn window_range(
+    lines: &[Line],
+    start: usize,
+    count: usize,
+    include_terminator: bool,
+) -> Range<usize> {
+    let last = &lines[start + count - 1];
+    lines[start].start..if include_terminator {
+        last.next
+    } else {
+        last.end
+    }
+}
+
+/// Leading whitespace of `needle`'s first non-blank line and of the matching
+/// content line, when they differ.
+fn indentation_change(
+    content: &str,
+    lines: &[Line],
+    start: usize,
+    needle: &[&str],
+) -> Option<(String, String)> {
+    let offset = needle.iter().position(|line| !line.trim().is_empty())?;
+    let from = leading_whitespace(needle[offset]);
+    let to = leading_whitespace(lines.get(start + offset)?.text(content));
+    (from != to).then(|| (from.to_string(), to.to_string()))
+}
+
+fn leading_whitespace(line: &str) -> &str {
+    &line[..line.len() - line.trim_start().len()]
+}
+
+/// Similarity of each window the size of `needle` in which every line is
+/// close to the needle's, skipping windows whose first line is clearly
+/// different.
+fn score_windows(content: &str, lines: &[Line], needle: &[&str]) -> Vec<(usize, f64)> {
+    prefiltered_starts(content, lines, needle)
+        .filter_map(|start| {
+            let similarity = window_similarity(content, lines, start, needle);
+            (similarity.worst >= FUZZY_LINE_THRESHOLD).then_some((start, similarity.mean))
+        })
+        .collect()
+}
+
+/// Starts of the windows the size of `needle` whose first line is similar
+/// enough to be worth scoring.
+fn prefiltered_starts<'a>(
+    content: &'a str,
+    lines: &'a [Line],
+    needle: &'a [&str],
+) -> impl Iterator<Item = usize> + 'a {
+    let last = lines.len().checked_sub(needle.len());
+    last.into_iter().flat_map(move |last| {
+        (0..=last).filter(move |&start| {
+            strsim::normalized_levenshtein(lines[start].text(content).trim(), needle[0].trim())
+                >= FUZZY_PREFILTER
+        })
+    })
+}
+
+/// How similar a window is to `needle`, line by line.
+struct Similarity {
+    mean: f64,
+    worst: f64,
+}
+
+fn window_similarity(content: &str, lines: &[Line], start: usize, needle: &[&str]) -> Similarity {
+    let scores = needle.iter().enumerate().map(|(offset, expected)| {
+        strsim::normalized_levenshtein(lines[start + offset].text(content).trim(), expected.trim())
+    });
+    let (total, worst) = scores.fold((0.0, 1.0_f64), |(total, worst), score| {
+        (total + score, worst.min(score))
+    });
+    Similarity {
+        mean: total / needle.len() as f64,
+        worst,
+    }
+}
+
+fn not_found(content: &str, lines: &[Line], needle: &[&str]) -> String {
+    let mut message =
+        "The provided `old_string` does not appear in the file. No edits were applied.".to_string();
+    if let Some(candidate) = closest_candidate(content, lines, needle) {
+        message.push_str("\n\n");
+        message.push_str(&candidate);
+    }
+    message
+}
+
+/// The window most similar to `needle`, rendered with line numbers.
+fn closest_candidate(content: &str, lines: &[Line], needle: &[&str]) -> Option<String> {
+    if needle.is_empty() || lines.is_empty() || needle.len() > MAX_FUZZY_NEEDLE_LINES {
+        return None;
+    }
+    let count = needle.len().min(lines.len());
+    // Score whole windows only where the first line is close, as the fuzzy
+    // tier does; failing that, or in files too large to score, compare
+    // first lines only.
+    let scored = (lines.len() <= MAX_FUZZY_CONTENT_LINES)
+        .then(|| {
+            prefiltered_starts(content, lines, &needle[..count])
+                .map(|start| {
+                    let similarity = window_similarity(content, lines, start, &needle[..count]);
+                    (start, similarity.mean)
+                })
+                .max_by(|a, b| a.1.total_cmp(&b.1))
+        })
+        .flatten();
+    let (start, score) = match scored {
+        Some(best) => best,
+        None => (0..=lines.len() - count)
+            .map(|start| {
+                (
+                    start,
+                    strsim::normalized_levenshtein(
+                        lines[start].text(content).trim(),
+                        needle[0].trim(),
+                    ),
+                )
+            })
+            .max_by(|a, b| a.1.total_cmp(&b.1))?,
+    };
+    if score <= 0.0 {
+        return None;
+    }
+
+    let shown = count.min(MAX_CANDIDATE_LINES);
+    let width = (start + shown).to_string().len();
+    let mut snippet = format!(
+        "Closest match (lines {}-{}, {:.0}% similar):",
+        start + 1,
+        start + count,
+        score * 100.0
+    );
+    for (offset, line) in lines[start..start + shown].iter().enumerate() {
+        snippet.push_str(&format!(
+            "\n{:>width$} | {}",
+            start + offset + 1,
+            line.text(content)
+        ));
+    }
+    if shown < count {
+        snippet.push_str("\n…");
+    }
+    Some(snippet)
+}
+
+fn line_number(content: &str, byte: usize) -> usize {
+    content[..byte].matches('\n').count() + 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply(content: &str, old: &str, new: &str) -> Result<(String, MatchTier), String> {
+        let target = find_edit_target(content, old, false)?;
+        let mut result = content.to_string();
+        for range in target.ranges.iter().rev() {
+            result.replace_range(range.clone(), &target.adapt_replacement(new));
+        }
+        Ok((result, target.tier))
+    }
+
+    #[test]
+    fn exact_match_wins() {
+        let (result, tier) = apply("a\nb\nc\n", "b\n", "B\n").unwrap();
+        assert_eq!(result, "a\nB\nc\n");
+        assert_eq!(tier, MatchTier::Exact);
+    }
+
+    #[test]
+    fn trailing_whitespace_and_crlf_are_ignored() {
+        let (result, tier) = apply("one  \r\ntwo\r\nthree\r\n", "one\ntwo\n", "1\n2\n").unwrap();
+        assert_eq!(result, "1\r\n2\r\nthree\r\n");
+        assert_eq!(tier, MatchTier::Whitespace);
+    }
+
+    #[test]
+    fn indentation_is_adapted() {
+        let content = "fn main() {\n        let x = 1;\n        call(x);\n}\n";
+        let (result, tier) =
+            apply(content, "let x = 1;\ncall(x);", "let x = 2;\n    call(x);").unwrap();
+        assert_eq!(
+            result,
+            "fn main() {\n        let x = 2;\n            call(x);\n}\n"
+        );
+        assert_eq!(tier, MatchTier::Indentation);
+    }
+
+    #[test]
+    fn near_miss_matches_fuzzily() {
+        let content = "fn total(items: &[u32]) -> u32 {\n    items.iter().sum()\n}\n";
+        let old = "fn total(items: &[u32]) -> u32 {\n    item.iter().sum()\n}";
+        let (result, tier) = apply(content, old, "fn total() -> u32 {\n    0\n}").unwrap();
+        assert_eq!(result, "fn total() -> u32 {\n    0\n}\n");
+        assert_eq!(tier, MatchTier::Fuzzy);
+    }
+
+    #[test]
+    fn one_unrelated_line_blocks_a_fuzzy_match() {
+        let lines = (0..10)
+            .map(|i| format!("    let value_{i} = compute_value({i});"))
+            .collect::<Vec<_>>();
+        let content = lines.join("\n") + "\n";
+        let mut needle = lines.clone();
+        needle[4] = "    panic!(\"unreachable\");".to_string();
+
+        let err = find_edit_target(&content, &needle.join("\n"), false).unwrap_err();
+        assert!(err.contains("does not appear in the file"), "{err}");
+        assert!(err.contains("Closest match (lines 1-10"), "{err}");
+    }
+
+    #[test]
+    fn ambiguous_matches_are_refused_unless_replace_all() {
+        let content = "x = 1\ny = 2\nx = 1\n";
+        let err = find_edit_target(content, "x = 1", false).unwrap_err();
+        assert!(
+            err.contains("matches 2 places (starting at lines 1, 3)"),
+            "{err}"
+        );
+
+        let target = find_edit_target(content, "x = 1", true).unwrap();
+        assert_eq!(target.ranges, vec![0..5, 12..17]);
+    }
+
+    #[test]
+    fn missing_text_reports_the_closest_candidate() {
+        let content = "alpha\nbeta\ngamma\n";
+        let err = find_edit_target(content, "betx\ngamma", false).unwrap_err();
+        assert!(err.contains("Closest match (lines 2-3"), "{err}");
+        assert!(err.contains("2 | beta\n3 | gamma"), "{err}");
+    }
+
+    #[test]
+    fn closest_candidate_falls_back_to_first_lines() {
+        // No first line is close enough to score whole windows.
+        let snippet = closest_match("alpha\nbeta\ngamma\n", "alxxx\nbeta").unwrap();
+        assert!(
+            snippet.starts_with("Closest match (lines 1-2, 40% similar)"),
+            "{snippet}"
+        );
+    }
+}
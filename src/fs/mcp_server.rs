@@ -1,41 +1,70 @@
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     env,
-    sync::{
-        Arc,
-        atomic::{AtomicU64, Ordering},
-    },
+    sync::Arc,
 };
 
 use anyhow::{Context, Result, anyhow};
 use diffy::{PatchFormatter, create_patch};
 use rmcp::{
-    ErrorData as McpError, ServerHandler,
-    handler::server::{tool::ToolRouter, wrapper::Parameters},
+    ErrorData as McpError, RoleServer, ServerHandler,
+    handler::server::{
+        tool::{ToolCallContext, ToolRouter},
+        wrapper::Parameters,
+    },
     model::{
-        AnnotateAble, CallToolResult, Content, Implementation, Meta, ProtocolVersion, RawContent,
-        RawTextContent, ServerCapabilities, ServerInfo,
+        AnnotateAble, CallToolRequestParam, CallToolResult, Content, Implementation,
+        ListToolsResult, Meta, PaginatedRequestParam, ProtocolVersion, RawContent, RawTextContent,
+        ServerCapabilities, ServerInfo,
     },
-    service, tool, tool_handler, tool_router,
+    service::{self, RequestContext},
+    tool, tool_router,
     transport::io,
 };
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
-use tokio::{
-    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
-    net::TcpStream,
-    sync::Mutex,
-    time::{Duration, timeout},
-};
-use tracing::info;
+use tokio::sync::Mutex;
+use tracing::{debug, info};
+
+use crate::settings::{DEFAULT_FS_MAX_READ_BYTES, DEFAULT_FS_READ_LINE_LIMIT};
 
-use super::bridge;
+use super::{
+    binary::BinarySummary,
+    bridge::{
+        BridgeArgs, BridgeOp, DEFAULT_LIST_LIMIT, DEFAULT_SEARCH_LIMIT, ReadSource, SetChange,
+        VERSION_CONFLICT,
+    },
+    client::{BridgeClient, BridgeEndpoint, BridgeReply, BridgeTimeouts, positive_from_env},
+    matching::find_edit_target,
+    notebook::{self, DEFAULT_MAX_OUTPUT_CHARS, NotebookEdit},
+    patch::{FileChange, apply_hunks, parse_patch},
+    search::{SearchMatch, SearchPage},
+    walk::{EntryKind, ListingPage},
+};
 
-const DEFAULT_READ_LINE_LIMIT: u32 = 1000;
-const MAX_READ_BYTES: usize = 50 * 1024;
+/// How much of a file one `read_text_file` call returns.
+#[derive(Debug, Clone, Copy)]
+struct ReadLimits {
+    /// Lines returned when the model does not pass a limit.
+    lines: u32,
+    max_bytes: usize,
+}
 
-static NEXT_REQUEST_ID: AtomicU64 = AtomicU64::new(1);
+impl ReadLimits {
+    /// Read `ACP_FS_READ_LINE_LIMIT` / `ACP_FS_MAX_READ_BYTES`, keeping the
+    /// defaults for unset variables.
+    fn from_env() -> Result<Self> {
+        let lines = positive_from_env("ACP_FS_READ_LINE_LIMIT", "a whole number of lines")?
+            .map(|lines| u32::try_from(lines).unwrap_or(u32::MAX));
+        let max_bytes = positive_from_env("ACP_FS_MAX_READ_BYTES", "a whole number of bytes")?
+            .map(|bytes| usize::try_from(bytes).unwrap_or(usize::MAX));
+        Ok(Self {
+            lines: lines.unwrap_or(DEFAULT_FS_READ_LINE_LIMIT),
+            max_bytes: max_bytes.unwrap_or(DEFAULT_FS_MAX_READ_BYTES),
+        })
+    }
+}
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 struct LineRange {
@@ -61,9 +90,19 @@ pub async fn run() -> Result<()> {
         .context("ACP_FS_BRIDGE_ADDR environment variable is required")?;
     let session_id = env::var("ACP_FS_SESSION_ID")
         .context("ACP_FS_SESSION_ID environment variable is required")?;
+    let token = env::var("ACP_FS_BRIDGE_TOKEN")
+        .context("ACP_FS_BRIDGE_TOKEN environment variable is required")?;
+
+    let timeouts = BridgeTimeouts::from_env()?;
+    let limits = ReadLimits::from_env()?;
 
     // Build an rmcp server over stdio with our tools.
-    let server = FsTools::new(bridge_addr, session_id);
+    let endpoint = BridgeEndpoint {
+        address: bridge_addr,
+        session_id,
+        token,
+    };
+    let server = FsTools::new(BridgeClient::new(endpoint, timeouts), limits);
     let transport = io::stdio();
     // Serve and wait until the client closes the connection.
     let running = service::serve_server(server, transport).await?;
@@ -80,78 +119,241 @@ struct StagedEdits {
 #[derive(Clone)]
 struct StagedFile {
     content: String,
+    /// Version the bridge reported for `content`.
+    version: Option<String>,
 }
 
 impl StagedEdits {
-    async fn stage(&self, path: String, content: String) {
+    async fn stage(&self, path: String, content: String, version: Option<String>) {
         let mut map = self.entries.lock().await;
-        map.insert(path, StagedFile { content });
+        map.insert(path, StagedFile { content, version });
     }
     async fn get(&self, path: &str) -> Option<StagedFile> {
         let map = self.entries.lock().await;
         map.get(path).cloned()
     }
+    /// Forget staged content for `path` unless it is at `version`.
+    async fn invalidate_stale(&self, path: &str, version: Option<&str>) {
+        let mut map = self.entries.lock().await;
+        if map
+            .get(path)
+            .is_some_and(|staged| staged.version.as_deref() != version)
+        {
+            map.remove(path);
+        }
+    }
+    /// Forget staged content for `path` and anything below it.
+    async fn remove_tree(&self, path: &str) {
+        let prefix = format!("{}/", path.trim_end_matches('/'));
+        let mut map = self.entries.lock().await;
+        map.retain(|staged, _| staged != path && !staged.starts_with(&prefix));
+    }
 }
 
 #[derive(Clone)]
 struct FsTools {
-    bridge_addr: String,
-    session_id: String,
+    bridge: BridgeClient,
+    limits: ReadLimits,
     staged_edits: StagedEdits,
     tool_router: ToolRouter<Self>,
 }
 
 impl FsTools {
-    fn new(bridge_addr: String, session_id: String) -> Self {
+    fn new(bridge: BridgeClient, limits: ReadLimits) -> Self {
         Self {
-            bridge_addr,
-            session_id,
+            bridge,
+            limits,
             staged_edits: Default::default(),
             tool_router: Self::tool_router(),
         }
     }
+
+    /// Run a file-management op and return the bridge's summary of it.
+    async fn manage_via_bridge(&self, op: BridgeOp, args: BridgeArgs) -> Result<String, McpError> {
+        self.bridge
+            .request(op, args)
+            .await
+            .map(|reply| reply.content)
+            .map_err(|e| {
+                McpError::internal_error(
+                    format!("bridge {} failed", op.as_str()),
+                    Some(json!({"reason": e.to_string()})),
+                )
+            })
+    }
+
+    /// Run a listing op and render one page of entries, with a
+    /// `<list-info>` hint when more entries remain.
+    async fn list_via_bridge(
+        &self,
+        op: BridgeOp,
+        mut args: BridgeArgs,
+        offset: Option<u32>,
+        limit: Option<u32>,
+    ) -> Result<CallToolResult, McpError> {
+        let offset = offset.unwrap_or(0);
+        let limit = limit
+            .filter(|value| *value > 0)
+            .unwrap_or(DEFAULT_LIST_LIMIT);
+        args.offset = Some(offset);
+        args.limit = Some(limit);
+        let path = args.path.clone();
+        let pattern = args.pattern.clone();
+
+        let response = self.bridge.request(op, args).await.map_err(|e| {
+            McpError::internal_error(
+                format!("bridge {} failed", op.as_str()),
+                Some(json!({"reason": e.to_string()})),
+            )
+        })?;
+        let page: ListingPage = serde_json::from_str(&response.content).map_err(|e| {
+            McpError::internal_error(
+                "malformed listing from bridge",
+                Some(json!({"reason": e.to_string()})),
+            )
+        })?;
+
+        let mut text = page
+            .entries
+            .iter()
+            .map(|entry| {
+                let display = join_listing_path(&path, &entry.path);
+                match entry.kind {
+                    EntryKind::Directory => format!("{display}/"),
+                    EntryKind::Symlink => format!("{display} (symlink)"),
+                    EntryKind::File => display,
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        if page.entries.is_empty() {
+            text = match &pattern {
+                Some(pattern) => format!("No paths under {path} match {pattern}."),
+                None => format!("{path} is empty."),
+            };
+        }
+        if let Some(hint) = build_list_hint(&page, offset, limit) {
+            text.push_str("\n\n");
+            text.push_str(&hint);
+        }
+
+        let meta = json!({
+            "path": path,
+            "pattern": pattern,
+            "offset": offset,
+            "limit": limit,
+            "returned": page.entries.len(),
+            "total": page.total,
+            "next_offset": page.next_offset,
+            "walk_truncated": page.walk_truncated,
+        });
+        let mut meta_obj = Meta::new();
+        meta_obj.insert("codex_fs_list".to_string(), meta);
+        let content = RawContent::Text(RawTextContent {
+            text,
+            meta: Some(meta_obj),
+        })
+        .no_annotation();
+        Ok(CallToolResult::success(vec![content]))
+    }
 }
 
 #[tool_router]
 impl FsTools {
     /// Read workspace files via ACP bridge (paged to ~1000 lines/50KB; use line/limit to continue).
     #[tool(
-        description = "Read workspace files via ACP bridge (paged to ~1000 lines/50KB; use line/limit to continue)."
+        description = "Read workspace files via ACP bridge (paged, by default to ~1000 lines/50KB; use line/limit to continue). Pass tail=N for the last N lines of a log. Binary files are summarized and images are returned as image content."
     )]
     async fn read_text_file(
         &self,
-        Parameters(ReadTextFileArgs { path, line, limit }): Parameters<ReadTextFileArgs>,
-    ) -> Result<CallToolResult, McpError> {
-        let start_line = line.unwrap_or(1).max(1);
-        let requested_limit = limit
-            .filter(|value| *value > 0)
-            .unwrap_or(DEFAULT_READ_LINE_LIMIT);
-        let bridge_limit = requested_limit.saturating_add(1);
-        let response = perform_bridge_request(
-            &self.bridge_addr,
-            &self.session_id,
-            bridge::BridgeOp::Read,
-            &path,
+        Parameters(ReadTextFileArgs {
+            path,
             line,
-            Some(bridge_limit),
-            None,
-        )
-        .await
-        .map_err(|e| {
-            McpError::internal_error("bridge read failed", Some(json!({"reason": e.to_string()})))
-        })?;
+            limit,
+            tail,
+        }): Parameters<ReadTextFileArgs>,
+    ) -> Result<CallToolResult, McpError> {
+        let tail = tail.filter(|value| *value > 0);
+        let mut start_line = line.unwrap_or(1).max(1);
+        let requested_limit = tail.unwrap_or_else(|| {
+            limit
+                .filter(|value| *value > 0)
+                .unwrap_or(self.limits.lines)
+        });
+        // Ask for one extra line to learn whether more follow the window.
+        let args = match tail {
+            Some(tail) => BridgeArgs {
+                tail: Some(tail),
+                ..BridgeArgs::new(&path)
+            },
+            None => BridgeArgs {
+                line: Some(start_line),
+                limit: Some(requested_limit.saturating_add(1)),
+                ..BridgeArgs::new(&path)
+            },
+        };
+        let response = self
+            .bridge
+            .request(BridgeOp::Read, args)
+            .await
+            .map_err(|e| {
+                McpError::internal_error(
+                    "bridge read failed",
+                    Some(json!({"reason": e.to_string()})),
+                )
+            })?;
+
+        if let Some(summary) = response.binary {
+            return Ok(binary_read_result(&path, summary));
+        }
 
-        let mut snippet =
-            prepare_read_snippet(&response, start_line, requested_limit, MAX_READ_BYTES);
+        let version = response.version;
+        self.staged_edits
+            .invalidate_stale(&path, version.as_deref())
+            .await;
+
+        let total_lines = response.total_lines;
+        let mut window = response.content.as_str();
+        if tail.is_some() {
+            start_line = response
+                .start_line
+                .map_or(1, |line| u32::try_from(line).unwrap_or(u32::MAX));
+            // Keep the end of the file when the tail is over the byte cap.
+            let (kept, skipped) = trim_to_last_bytes(window, self.limits.max_bytes);
+            window = kept;
+            start_line = start_line.saturating_add(skipped);
+        }
 
-        if let Some(hint) =
-            build_file_read_hint(&snippet, start_line, requested_limit, MAX_READ_BYTES)
-        {
+        let mut snippet =
+            prepare_read_snippet(window, start_line, requested_limit, self.limits.max_bytes);
+
+        let hint = match (tail, total_lines) {
+            (Some(_), Some(total)) => Some(build_tail_hint(
+                &snippet,
+                start_line,
+                total,
+                self.limits.lines,
+            )),
+            _ => build_file_read_hint(&snippet, start_line, requested_limit, self.limits.max_bytes),
+        };
+        if let Some(hint) = hint {
             if !snippet.text.is_empty() {
                 snippet.text.push_str("\n\n");
             }
             snippet.text.push_str(&hint);
         }
+        if let Some(version) = &version {
+            if !snippet.text.is_empty() {
+                snippet.text.push_str("\n\n");
+            }
+            snippet
+                .text
+                .push_str(&format!("<file-version>{version}</file-version>"));
+        }
+        if let Some(tag) = file_source_tag(response.source, response.fallback_reason.as_deref()) {
+            snippet.text.push('\n');
+            snippet.text.push_str(&tag);
+        }
 
         let ReadSnippet {
             text,
@@ -175,6 +377,11 @@ impl FsTools {
             "truncated_by_line_limit": truncated_by_line_limit,
             "truncated_by_bytes": truncated_by_bytes,
             "additional_lines_available": additional_lines_available,
+            "total_lines": total_lines,
+            "tail": tail,
+            "version": version,
+            "source": response.source,
+            "fallback_reason": response.fallback_reason,
         });
 
         if truncated && let Some(obj) = meta.as_object_mut() {
@@ -182,7 +389,7 @@ impl FsTools {
         }
 
         if truncated_by_bytes && let Some(obj) = meta.as_object_mut() {
-            obj.insert("max_bytes".to_string(), json!(MAX_READ_BYTES));
+            obj.insert("max_bytes".to_string(), json!(self.limits.max_bytes));
         }
 
         let mut meta_obj = Meta::new();
@@ -196,10 +403,16 @@ impl FsTools {
     }
 
     /// Write workspace files via ACP bridge.
-    #[tool(description = "Write workspace files via ACP bridge.")]
+    #[tool(
+        description = "Write workspace files via ACP bridge. Pass expected_version from read_text_file to refuse the write if the file changed since."
+    )]
     async fn write_text_file(
         &self,
-        Parameters(WriteTextFileArgs { path, content }): Parameters<WriteTextFileArgs>,
+        Parameters(WriteTextFileArgs {
+            path,
+            content,
+            expected_version,
+        }): Parameters<WriteTextFileArgs>,
     ) -> Result<CallToolResult, McpError> {
         let mut final_content = content;
         let mut staged_applied = false;
@@ -213,30 +426,29 @@ impl FsTools {
             staged_applied = true;
         }
 
-        perform_bridge_request(
-            &self.bridge_addr,
-            &self.session_id,
-            bridge::BridgeOp::Write,
+        let reply = write_via_bridge(
+            &self.bridge,
             &path,
+            &final_content,
+            expected_version,
             None,
-            None,
-            Some(final_content.clone()),
+            false,
+            &self.staged_edits,
         )
-        .await
-        .map_err(|e| {
-            McpError::internal_error(
-                "bridge write failed",
-                Some(json!({"reason": e.to_string()})),
-            )
-        })?;
-
-        self.staged_edits.stage(path.clone(), final_content).await;
-
-        let response_text = if staged_applied {
-            "write completed (applied staged edits)"
+        .await?;
+        let version = reply.version.clone();
+        self.staged_edits
+            .stage(path.clone(), final_content, reply.version)
+            .await;
+
+        let mut response_text = if staged_applied {
+            "write completed (applied staged edits)".to_string()
         } else {
-            "write completed"
+            "write completed".to_string()
         };
+        if let Some(version) = version {
+            response_text.push_str(&format!("; new version {version}"));
+        }
         Ok(CallToolResult::success(vec![Content::text(response_text)]))
     }
 
@@ -248,6 +460,7 @@ impl FsTools {
             path,
             old_string,
             new_string,
+            expected_version,
         }): Parameters<EditTextFileArgs>,
     ) -> Result<CallToolResult, McpError> {
         let instructions = vec![EditInstruction {
@@ -256,10 +469,10 @@ impl FsTools {
             replace_all: false,
         }];
         stage_edits(
-            &self.bridge_addr,
-            &self.session_id,
+            &self.bridge,
             &path,
             instructions,
+            expected_version,
             &self.staged_edits,
         )
         .await
@@ -271,7 +484,11 @@ impl FsTools {
     )]
     async fn multi_edit_text_file(
         &self,
-        Parameters(MultiEditTextFileArgs { path, edits }): Parameters<MultiEditTextFileArgs>,
+        Parameters(MultiEditTextFileArgs {
+            path,
+            edits,
+            expected_version,
+        }): Parameters<MultiEditTextFileArgs>,
     ) -> Result<CallToolResult, McpError> {
         if edits.is_empty() {
             return Err(McpError::invalid_params(
@@ -289,18 +506,401 @@ impl FsTools {
             .collect::<Vec<_>>();
 
         stage_edits(
-            &self.bridge_addr,
-            &self.session_id,
+            &self.bridge,
             &path,
             instructions,
+            expected_version,
+            &self.staged_edits,
+        )
+        .await
+    }
+
+    /// Insert lines after a given line of a file and persist the result.
+    #[tool(
+        description = "Insert text after a given line of a file (line=0 inserts at the top) and persist the result. Line numbers are as shown by read_text_file."
+    )]
+    async fn insert_at_line(
+        &self,
+        Parameters(InsertAtLineArgs {
+            path,
+            line,
+            content,
+            expected_version,
+        }): Parameters<InsertAtLineArgs>,
+    ) -> Result<CallToolResult, McpError> {
+        stage_change(
+            &self.bridge,
+            &path,
+            expected_version,
+            &self.staged_edits,
+            |base| Ok((splice_lines(base, line as usize, 0, &content)?, Vec::new())),
+        )
+        .await
+    }
+
+    /// Replace an inclusive range of lines in a file and persist the result.
+    #[tool(
+        description = "Replace lines start_line through end_line (inclusive) of a file with new content and persist the result; empty content deletes the lines. Line numbers are as shown by read_text_file."
+    )]
+    async fn replace_line_range(
+        &self,
+        Parameters(ReplaceLineRangeArgs {
+            path,
+            start_line,
+            end_line,
+            content,
+            expected_version,
+        }): Parameters<ReplaceLineRangeArgs>,
+    ) -> Result<CallToolResult, McpError> {
+        if start_line == 0 || end_line < start_line {
+            return Err(McpError::invalid_params(
+                format!(
+                    "invalid line range {start_line}-{end_line}; lines are numbered from 1 and end_line must not be before start_line"
+                ),
+                None,
+            ));
+        }
+        let index = start_line as usize - 1;
+        let remove = (end_line - start_line) as usize + 1;
+        stage_change(
+            &self.bridge,
+            &path,
+            expected_version,
+            &self.staged_edits,
+            |base| Ok((splice_lines(base, index, remove, &content)?, Vec::new())),
+        )
+        .await
+    }
+
+    /// Apply edits to several files atomically and persist the result.
+    #[tool(
+        description = "Apply edits to several files as one change: every edit is checked against the current contents first, then all files are written or none are (already written files are rolled back on failure). Each file takes the same edits as multi_edit_text_file and an optional expected_version."
+    )]
+    async fn apply_edit_set(
+        &self,
+        Parameters(ApplyEditSetArgs { files }): Parameters<ApplyEditSetArgs>,
+    ) -> Result<CallToolResult, McpError> {
+        apply_edit_set(&self.bridge, files, &self.staged_edits).await
+    }
+
+    /// Apply a unified diff or Codex patch through the bridge.
+    #[tool(
+        description = "Apply a patch: a unified diff (one or more files, as from `diff -u` or `git diff`) or a Codex `*** Begin Patch` envelope. Files can be added, deleted, moved and updated. Hunks may be offset from their stated lines and tolerate small context differences; if any hunk does not apply, each failure is reported and no files are changed."
+    )]
+    async fn apply_patch(
+        &self,
+        Parameters(ApplyPatchArgs { patch }): Parameters<ApplyPatchArgs>,
+    ) -> Result<CallToolResult, McpError> {
+        apply_patch(&self.bridge, &patch, &self.staged_edits).await
+    }
+
+    /// Read a Jupyter notebook as a list of cells.
+    #[tool(
+        description = "Read a Jupyter notebook (.ipynb) as a list of cells with their index, type, id, source and outputs (outputs truncated to max_output_chars per cell, default 2000). Use this instead of read_text_file for notebooks; the result ends with a <file-version> for edit_notebook."
+    )]
+    async fn read_notebook(
+        &self,
+        Parameters(ReadNotebookArgs {
+            path,
+            max_output_chars,
+        }): Parameters<ReadNotebookArgs>,
+    ) -> Result<CallToolResult, McpError> {
+        let response = self
+            .bridge
+            .request(BridgeOp::Read, BridgeArgs::new(&path))
+            .await
+            .map_err(|e| {
+                McpError::internal_error(
+                    "bridge read failed",
+                    Some(json!({"reason": e.to_string()})),
+                )
+            })?;
+        if response.binary.is_some() {
+            return Err(McpError::invalid_params(
+                format!("{path} is a binary file, not a notebook"),
+                None,
+            ));
+        }
+        self.staged_edits
+            .invalidate_stale(&path, response.version.as_deref())
+            .await;
+
+        let max_output_chars =
+            max_output_chars.map_or(DEFAULT_MAX_OUTPUT_CHARS, |chars| chars as usize);
+        let mut text = notebook::render(&response.content, max_output_chars)
+            .map_err(|e| McpError::invalid_params(format!("{path}: {e:#}"), None))?;
+        if let Some(version) = &response.version {
+            text.push_str(&format!("\n<file-version>{version}</file-version>"));
+        }
+        if let Some(tag) = file_source_tag(response.source, response.fallback_reason.as_deref()) {
+            text.push_str(&format!("\n{tag}"));
+        }
+        Ok(CallToolResult::success(vec![Content::text(text)]))
+    }
+
+    /// Edit, insert, delete and move notebook cells and persist the result.
+    #[tool(
+        description = "Edit a Jupyter notebook by cell: each edit is {op: \"replace\", cell, source, cell_type?}, {op: \"insert\", at?, cell_type, source}, {op: \"delete\", cell} or {op: \"move\", cell, to}, where cell is an index or a cell id as shown by read_notebook. Edits apply in order; replacing a code cell's source clears its outputs. The notebook is written back as valid nbformat JSON."
+    )]
+    async fn edit_notebook(
+        &self,
+        Parameters(EditNotebookArgs {
+            path,
+            edits,
+            expected_version,
+        }): Parameters<EditNotebookArgs>,
+    ) -> Result<CallToolResult, McpError> {
+        if edits.is_empty() {
+            return Err(McpError::invalid_params(
+                "edits array must not be empty",
+                None,
+            ));
+        }
+        stage_change(
+            &self.bridge,
+            &path,
+            expected_version,
             &self.staged_edits,
+            |base| notebook::apply_edits(base, &edits),
         )
         .await
     }
+
+    /// List a workspace directory via ACP bridge.
+    #[tool(
+        description = "List a workspace directory via ACP bridge (honors .gitignore; depth defaults to 1; paged to ~500 entries, use offset/limit to continue)."
+    )]
+    async fn list_directory(
+        &self,
+        Parameters(ListDirectoryArgs {
+            path,
+            depth,
+            offset,
+            limit,
+        }): Parameters<ListDirectoryArgs>,
+    ) -> Result<CallToolResult, McpError> {
+        let path = path.unwrap_or_else(|| ".".to_string());
+        let args = BridgeArgs {
+            depth,
+            ..BridgeArgs::new(&path)
+        };
+        self.list_via_bridge(BridgeOp::ListDirectory, args, offset, limit)
+            .await
+    }
+
+    /// Find workspace paths matching a glob pattern via ACP bridge.
+    #[tool(
+        description = "Find workspace paths matching a glob pattern such as `src/**/*.rs`, relative to `path` (honors .gitignore; paged to ~500 entries, use offset/limit to continue)."
+    )]
+    async fn glob(
+        &self,
+        Parameters(GlobArgs {
+            pattern,
+            path,
+            offset,
+            limit,
+        }): Parameters<GlobArgs>,
+    ) -> Result<CallToolResult, McpError> {
+        let path = path.unwrap_or_else(|| ".".to_string());
+        let args = BridgeArgs {
+            pattern: Some(pattern),
+            ..BridgeArgs::new(&path)
+        };
+        self.list_via_bridge(BridgeOp::Glob, args, offset, limit)
+            .await
+    }
+
+    /// Delete a workspace file or directory via ACP bridge.
+    #[tool(
+        description = "Delete a workspace file or directory via ACP bridge (non-empty directories need recursive=true). May ask the user for approval."
+    )]
+    async fn delete_file(
+        &self,
+        Parameters(DeleteFileArgs { path, recursive }): Parameters<DeleteFileArgs>,
+    ) -> Result<CallToolResult, McpError> {
+        let args = BridgeArgs {
+            recursive,
+            ..BridgeArgs::new(&path)
+        };
+        let message = self.manage_via_bridge(BridgeOp::Delete, args).await?;
+        self.staged_edits.remove_tree(&path).await;
+        Ok(CallToolResult::success(vec![Content::text(message)]))
+    }
+
+    /// Move or rename a workspace file or directory via ACP bridge.
+    #[tool(
+        description = "Move or rename a workspace file or directory via ACP bridge (existing destinations need overwrite=true). May ask the user for approval."
+    )]
+    async fn move_file(
+        &self,
+        Parameters(MoveFileArgs {
+            source,
+            destination,
+            overwrite,
+        }): Parameters<MoveFileArgs>,
+    ) -> Result<CallToolResult, McpError> {
+        let args = BridgeArgs {
+            destination: Some(destination.clone()),
+            overwrite,
+            ..BridgeArgs::new(&source)
+        };
+        let message = self.manage_via_bridge(BridgeOp::Move, args).await?;
+        self.staged_edits.remove_tree(&source).await;
+        self.staged_edits.remove_tree(&destination).await;
+        Ok(CallToolResult::success(vec![Content::text(message)]))
+    }
+
+    /// Create a workspace directory (and missing parents) via ACP bridge.
+    #[tool(description = "Create a workspace directory and any missing parents via ACP bridge.")]
+    async fn create_directory(
+        &self,
+        Parameters(CreateDirectoryArgs { path }): Parameters<CreateDirectoryArgs>,
+    ) -> Result<CallToolResult, McpError> {
+        let message = self
+            .manage_via_bridge(BridgeOp::CreateDirectory, BridgeArgs::new(&path))
+            .await?;
+        Ok(CallToolResult::success(vec![Content::text(message)]))
+    }
+
+    /// Search file contents in the workspace via ACP bridge.
+    #[tool(
+        description = "Search file contents in the workspace via ACP bridge (regex by default, or literal=true; include/exclude filter files by glob; honors .gitignore; ~100 matches per page, pass the <search-info> continuation to get more)."
+    )]
+    async fn search_files(
+        &self,
+        Parameters(SearchFilesArgs {
+            pattern,
+            path,
+            literal,
+            case_insensitive,
+            include,
+            exclude,
+            context,
+            limit,
+            continuation,
+        }): Parameters<SearchFilesArgs>,
+    ) -> Result<CallToolResult, McpError> {
+        let path = path.unwrap_or_else(|| ".".to_string());
+        let offset = match continuation.as_deref() {
+            Some(token) => token.parse::<u32>().map_err(|_| {
+                McpError::invalid_params(format!("invalid continuation token: {token}"), None)
+            })?,
+            None => 0,
+        };
+        let limit = limit
+            .filter(|value| *value > 0)
+            .unwrap_or(DEFAULT_SEARCH_LIMIT);
+        let args = BridgeArgs {
+            pattern: Some(pattern.clone()),
+            literal,
+            case_insensitive,
+            include,
+            exclude,
+            context,
+            offset: Some(offset),
+            limit: Some(limit),
+            ..BridgeArgs::new(&path)
+        };
+
+        let response = self
+            .bridge
+            .request(BridgeOp::Search, args)
+            .await
+            .map_err(|e| {
+                McpError::internal_error(
+                    "bridge search failed",
+                    Some(json!({"reason": e.to_string()})),
+                )
+            })?;
+        let page: SearchPage = serde_json::from_str(&response.content).map_err(|e| {
+            McpError::internal_error(
+                "malformed search results from bridge",
+                Some(json!({"reason": e.to_string()})),
+            )
+        })?;
+
+        let mut text = if page.matches.is_empty() {
+            format!(
+                "No matches for {pattern} in {} files under {path}.",
+                page.files_searched
+            )
+        } else {
+            format_search_matches(&page.matches)
+        };
+        if let Some(hint) = build_search_hint(&page, offset) {
+            text.push_str("\n\n");
+            text.push_str(&hint);
+        }
+
+        let locations = page
+            .matches
+            .iter()
+            .map(|found| json!({"path": found.path, "line": found.line}))
+            .collect::<Vec<_>>();
+        let meta = json!({
+            "path": path,
+            "pattern": pattern,
+            "source": page.source,
+            "files_searched": page.files_searched,
+            "returned": page.matches.len(),
+            "next_continuation": page.next_offset.map(|next| next.to_string()),
+            "walk_truncated": page.walk_truncated,
+            "matches": locations,
+        });
+        let mut meta_obj = Meta::new();
+        meta_obj.insert("codex_fs_search".to_string(), meta);
+        let content = RawContent::Text(RawTextContent {
+            text,
+            meta: Some(meta_obj),
+        })
+        .no_annotation();
+        Ok(CallToolResult::success(vec![content]))
+    }
 }
 
-#[tool_handler]
 impl ServerHandler for FsTools {
+    async fn call_tool(
+        &self,
+        request: CallToolRequestParam,
+        context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        let context = ToolCallContext::new(self, request, context);
+        let result = self.tool_router.call(context).await;
+
+        // Staged copies of files changed by someone else are stale; drop them
+        // and tell the model, whatever the outcome of the call itself.
+        let changed = self.bridge.take_changed().await;
+        if changed.is_empty() {
+            return result;
+        }
+        for path in &changed {
+            self.staged_edits.remove_tree(path).await;
+        }
+        let note = format!(
+            "<files-changed>\nChanged outside this session since you last read or wrote them:\n{}\nRe-read them before editing.\n</files-changed>",
+            changed.join("\n")
+        );
+        match result {
+            Ok(mut result) => {
+                result.content.push(Content::text(note));
+                Ok(result)
+            }
+            Err(err) => Err(McpError::new(
+                err.code,
+                format!("{}\n\n{note}", err.message),
+                err.data,
+            )),
+        }
+    }
+
+    async fn list_tools(
+        &self,
+        _request: Option<PaginatedRequestParam>,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ListToolsResult, McpError> {
+        Ok(ListToolsResult::with_all_items(self.tool_router.list_all()))
+    }
+
     fn get_info(&self) -> ServerInfo {
         let caps = ServerCapabilities::builder()
             .enable_tools()
@@ -336,25 +936,180 @@ struct ReadTextFileArgs {
     line: Option<u32>,
     #[serde(default)]
     limit: Option<u32>,
+    /// Read the last this many lines instead (e.g. of a log); `line` and
+    /// `limit` are ignored.
+    #[serde(default)]
+    tail: Option<u32>,
 }
 
 #[derive(Deserialize, Serialize, JsonSchema)]
-struct WriteTextFileArgs {
-    path: String,
-    content: String,
+struct ListDirectoryArgs {
+    /// Directory to list; defaults to the workspace root.
+    #[serde(default)]
+    path: Option<String>,
+    /// Levels to descend (1 = immediate children, max 10).
+    #[serde(default)]
+    depth: Option<u32>,
+    #[serde(default)]
+    offset: Option<u32>,
+    #[serde(default)]
+    limit: Option<u32>,
 }
 
 #[derive(Deserialize, Serialize, JsonSchema)]
-struct EditTextFileArgs {
-    path: String,
-    old_string: String,
-    new_string: String,
+struct GlobArgs {
+    pattern: String,
+    /// Directory the pattern is relative to; defaults to the workspace root.
+    #[serde(default)]
+    path: Option<String>,
+    #[serde(default)]
+    offset: Option<u32>,
+    #[serde(default)]
+    limit: Option<u32>,
+}
+
+#[derive(Deserialize, Serialize, JsonSchema)]
+struct SearchFilesArgs {
+    /// Regular expression (or plain text with `literal`) matched per line.
+    pattern: String,
+    /// File or directory to search; defaults to the workspace root.
+    #[serde(default)]
+    path: Option<String>,
+    #[serde(default)]
+    literal: Option<bool>,
+    #[serde(default)]
+    case_insensitive: Option<bool>,
+    /// Only search files matching one of these globs, e.g. `*.rs` or `src/**/*.ts`.
+    #[serde(default)]
+    include: Option<Vec<String>>,
+    /// Skip files matching one of these globs.
+    #[serde(default)]
+    exclude: Option<Vec<String>>,
+    /// Lines of context before and after each match (max 10).
+    #[serde(default)]
+    context: Option<u32>,
+    #[serde(default)]
+    limit: Option<u32>,
+    /// Token from a previous result's <search-info> hint.
+    #[serde(default)]
+    continuation: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, JsonSchema)]
+struct DeleteFileArgs {
+    path: String,
+    /// Delete a non-empty directory and everything in it.
+    #[serde(default)]
+    recursive: Option<bool>,
+}
+
+#[derive(Deserialize, Serialize, JsonSchema)]
+struct MoveFileArgs {
+    source: String,
+    destination: String,
+    /// Replace the destination if it already exists.
+    #[serde(default)]
+    overwrite: Option<bool>,
+}
+
+#[derive(Deserialize, Serialize, JsonSchema)]
+struct CreateDirectoryArgs {
+    path: String,
+}
+
+#[derive(Deserialize, Serialize, JsonSchema)]
+struct WriteTextFileArgs {
+    path: String,
+    content: String,
+    /// Version from read_text_file's <file-version>; the write fails if the
+    /// file has changed since.
+    #[serde(default)]
+    expected_version: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, JsonSchema)]
+struct EditTextFileArgs {
+    path: String,
+    old_string: String,
+    new_string: String,
+    /// Version from read_text_file's <file-version>; the edit fails if the
+    /// file has changed since.
+    #[serde(default)]
+    expected_version: Option<String>,
 }
 
 #[derive(Deserialize, Serialize, JsonSchema)]
 struct MultiEditTextFileArgs {
     path: String,
     edits: Vec<EditEntry>,
+    /// Version from read_text_file's <file-version>; the edits fail if the
+    /// file has changed since.
+    #[serde(default)]
+    expected_version: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, JsonSchema)]
+struct ApplyEditSetArgs {
+    files: Vec<FileEdits>,
+}
+
+#[derive(Deserialize, Serialize, JsonSchema)]
+struct FileEdits {
+    path: String,
+    edits: Vec<EditEntry>,
+    /// Version from read_text_file's <file-version>; the whole set fails if
+    /// this file has changed since.
+    #[serde(default)]
+    expected_version: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, JsonSchema)]
+struct ApplyPatchArgs {
+    patch: String,
+}
+
+#[derive(Deserialize, Serialize, JsonSchema)]
+struct ReadNotebookArgs {
+    path: String,
+    #[serde(default)]
+    max_output_chars: Option<u32>,
+}
+
+#[derive(Deserialize, Serialize, JsonSchema)]
+struct EditNotebookArgs {
+    path: String,
+    edits: Vec<NotebookEdit>,
+    /// Version from read_notebook's <file-version>; the edits fail if the
+    /// notebook has changed since.
+    #[serde(default)]
+    expected_version: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, JsonSchema)]
+struct InsertAtLineArgs {
+    path: String,
+    /// Insert after this line; 0 inserts at the top of the file.
+    line: u32,
+    content: String,
+    /// Version from read_text_file's <file-version>; the insert fails if the
+    /// file has changed since.
+    #[serde(default)]
+    expected_version: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, JsonSchema)]
+struct ReplaceLineRangeArgs {
+    path: String,
+    /// First line to replace (1-based).
+    start_line: u32,
+    /// Last line to replace, inclusive.
+    end_line: u32,
+    /// Replacement text; empty deletes the lines.
+    content: String,
+    /// Version from read_text_file's <file-version>; the edit fails if the
+    /// file has changed since.
+    #[serde(default)]
+    expected_version: Option<String>,
 }
 
 struct EditInstruction {
@@ -364,43 +1119,34 @@ struct EditInstruction {
 }
 
 async fn stage_edits(
-    bridge_addr: &str,
-    session_id: &str,
+    bridge: &BridgeClient,
     path: &str,
     instructions: Vec<EditInstruction>,
+    expected_version: Option<String>,
     staged_edits: &StagedEdits,
 ) -> Result<CallToolResult, McpError> {
-    let base_content = if let Some(entry) = staged_edits.get(path).await {
-        entry.content.clone()
-    } else {
-        match perform_bridge_request(
-            bridge_addr,
-            session_id,
-            bridge::BridgeOp::Read,
-            path,
-            None,
-            None,
-            None,
-        )
-        .await
-        {
-            Ok(content) => content,
-            Err(err) => {
-                let message = err.to_string();
-                if is_missing_file_error(&message) {
-                    String::new()
-                } else {
-                    return Err(McpError::internal_error(
-                        "failed to read current file content",
-                        Some(json!({"reason": err.to_string()})),
-                    ));
-                }
-            }
-        }
-    };
+    stage_change(bridge, path, expected_version, staged_edits, |base| {
+        apply_edits(base, &instructions)
+    })
+    .await
+}
 
-    let new_content = apply_edits(&base_content, &instructions)
-        .map_err(|e| McpError::invalid_params(e.to_string(), None))?;
+/// Apply `change` to the current content of `path` (the staged copy when it
+/// is current), write the result through the bridge and return the diff.
+///
+/// `change` returns the new content and notes to append to the summary.
+async fn stage_change(
+    bridge: &BridgeClient,
+    path: &str,
+    expected_version: Option<String>,
+    staged_edits: &StagedEdits,
+    change: impl FnOnce(&str) -> Result<(String, Vec<String>)>,
+) -> Result<CallToolResult, McpError> {
+    let (base_content, base_version) =
+        load_edit_base(bridge, path, expected_version.as_deref(), staged_edits).await?;
+
+    let (new_content, notes) =
+        change(&base_content).map_err(|e| McpError::invalid_params(e.to_string(), None))?;
 
     if new_content == base_content {
         return Ok(CallToolResult::success(vec![Content::text(format!(
@@ -412,74 +1158,665 @@ async fn stage_edits(
 
     let write_content = new_content.clone();
     let staged_bytes = write_content.len();
-    let _ = perform_bridge_request(
-        bridge_addr,
-        session_id,
-        bridge::BridgeOp::Write,
+    // Writing against the base's version makes the bridge refuse the edit if
+    // the file changed after the base was read.
+    let reply = write_via_bridge(
+        bridge,
         path,
+        &write_content,
+        base_version,
         None,
-        None,
-        Some(write_content.clone()),
+        false,
+        staged_edits,
     )
-    .await
-    .map_err(|e| {
-        McpError::internal_error(
-            "bridge write failed",
-            Some(json!({"reason": e.to_string()})),
-        )
-    })?;
+    .await?;
+    let version = reply.version.clone();
 
-    staged_edits.stage(path.to_string(), write_content).await;
+    staged_edits
+        .stage(path.to_string(), write_content, reply.version)
+        .await;
     info!(file = %path, bytes = staged_bytes, "Staged edits committed");
 
+    let mut summary = match &version {
+        Some(version) => format!("Write completed for {path}; new version {version}."),
+        None => format!("Write completed for {path}."),
+    };
+    for note in notes {
+        summary.push(' ');
+        summary.push_str(&note);
+    }
+    Ok(CallToolResult::success(vec![
+        diff_content(path, diff_text, version.as_deref()),
+        Content::text(summary),
+    ]))
+}
+
+/// Content and version of `path` to base an edit on: the staged copy when it
+/// is the version the caller saw, otherwise the file as read through the
+/// bridge ("" with no version for a missing file). Fails with a version
+/// conflict if the file is not at `expected_version`.
+async fn load_edit_base(
+    bridge: &BridgeClient,
+    path: &str,
+    expected_version: Option<&str>,
+    staged_edits: &StagedEdits,
+) -> Result<(String, Option<String>), McpError> {
+    let staged = staged_edits
+        .get(path)
+        .await
+        .filter(|entry| expected_version.is_none() || entry.version.as_deref() == expected_version);
+    let (base_content, base_version) = if let Some(entry) = staged {
+        (entry.content, entry.version)
+    } else {
+        match bridge.request(BridgeOp::Read, BridgeArgs::new(path)).await {
+            Ok(reply) if reply.binary.is_some() => {
+                return Err(McpError::invalid_params(
+                    format!("{path} is a binary file and cannot be edited as text"),
+                    None,
+                ));
+            }
+            Ok(reply) => (reply.content, reply.version),
+            Err(err) => {
+                let message = err.to_string();
+                if is_missing_file_error(&message) {
+                    (String::new(), None)
+                } else {
+                    return Err(McpError::internal_error(
+                        "failed to read current file content",
+                        Some(json!({"path": path, "reason": message})),
+                    ));
+                }
+            }
+        }
+    };
+
+    if let Some(expected) = expected_version
+        && base_version.as_deref() != Some(expected)
+    {
+        staged_edits
+            .invalidate_stale(path, base_version.as_deref())
+            .await;
+        return Err(version_conflict(
+            path,
+            format!(
+                "expected version {expected}, found {}",
+                base_version.as_deref().unwrap_or("none (file is missing)")
+            ),
+        ));
+    }
+    Ok((base_content, base_version))
+}
+
+/// A file's unified diff, with `codex_fs_diff` line-range metadata.
+fn diff_content(path: &str, diff_text: String, version: Option<&str>) -> Content {
     let (new_ranges, old_ranges) = parse_diff_line_ranges(&diff_text);
     let diff_meta = json!({
         "path": path,
         "new_ranges": line_ranges_to_json(&new_ranges),
         "old_ranges": line_ranges_to_json(&old_ranges),
+        "version": version,
     });
 
     let mut meta_obj = Meta::new();
     meta_obj.insert("codex_fs_diff".to_string(), diff_meta);
-    let diff_content = RawContent::Text(RawTextContent {
+    RawContent::Text(RawTextContent {
         text: diff_text,
         meta: Some(meta_obj),
     })
-    .no_annotation();
-    Ok(CallToolResult::success(vec![
-        diff_content,
-        Content::text(format!("Write completed for {path}.")),
-    ]))
+    .no_annotation()
+}
+
+/// One file's part of an edit set or patch, computed before anything is
+/// written.
+struct PlannedWrite {
+    path: String,
+    before: String,
+    /// `None` when the file did not exist.
+    base_version: Option<String>,
+    /// `None` to delete the file.
+    after: Option<String>,
+    /// Write `after`'s final newline as it is instead of keeping the file's,
+    /// for patches that add or remove it.
+    exact_newline: bool,
+    notes: Vec<String>,
+}
+
+/// Validate every file's edits against its current content, then write all
+/// files, rolling back the ones already written if a later write fails.
+async fn apply_edit_set(
+    bridge: &BridgeClient,
+    files: Vec<FileEdits>,
+    staged_edits: &StagedEdits,
+) -> Result<CallToolResult, McpError> {
+    if files.is_empty() {
+        return Err(McpError::invalid_params(
+            "files array must not be empty",
+            None,
+        ));
+    }
+    let mut seen = HashSet::new();
+    if let Some(duplicate) = files.iter().find(|file| !seen.insert(file.path.as_str())) {
+        return Err(McpError::invalid_params(
+            format!(
+                "{} appears more than once; combine its edits into one entry",
+                duplicate.path
+            ),
+            None,
+        ));
+    }
+
+    let mut planned = Vec::new();
+    for file in files {
+        if file.edits.is_empty() {
+            return Err(McpError::invalid_params(
+                format!(
+                    "{}: edits array must not be empty. No files were changed.",
+                    file.path
+                ),
+                None,
+            ));
+        }
+        let (before, base_version) = load_edit_base(
+            bridge,
+            &file.path,
+            file.expected_version.as_deref(),
+            staged_edits,
+        )
+        .await?;
+        let instructions = file
+            .edits
+            .into_iter()
+            .map(|edit| EditInstruction {
+                old_text: edit.old_string,
+                new_text: edit.new_string,
+                replace_all: edit.replace_all,
+            })
+            .collect::<Vec<_>>();
+        let (after, notes) = apply_edits(&before, &instructions).map_err(|e| {
+            McpError::invalid_params(format!("{}: {e} No files were changed.", file.path), None)
+        })?;
+        if after != before {
+            planned.push(PlannedWrite {
+                path: file.path,
+                before,
+                base_version,
+                after: Some(after),
+                exact_newline: false,
+                notes,
+            });
+        }
+    }
+    commit_planned(bridge, planned, staged_edits).await
+}
+
+/// Parse `patch` and check that every hunk applies, then write all files,
+/// rolling back the ones already written if a later write fails.
+async fn apply_patch(
+    bridge: &BridgeClient,
+    patch: &str,
+    staged_edits: &StagedEdits,
+) -> Result<CallToolResult, McpError> {
+    let changes = parse_patch(patch)
+        .map_err(|e| McpError::invalid_params(format!("Could not parse the patch: {e}"), None))?;
+
+    let mut planned = Vec::new();
+    let mut failures = Vec::new();
+    for change in changes {
+        let path = change.path().to_string();
+        let (before, base_version) = match load_edit_base(bridge, &path, None, staged_edits).await {
+            Ok(base) => base,
+            Err(err) => {
+                failures.push(format!("{path}: {}", error_reason(&err)));
+                continue;
+            }
+        };
+        match change {
+            FileChange::Add { content, .. } => {
+                if base_version.is_some() {
+                    failures.push(format!("{path}: already exists, so it cannot be added"));
+                    continue;
+                }
+                planned.push(PlannedWrite {
+                    path,
+                    before,
+                    base_version,
+                    after: Some(content),
+                    exact_newline: true,
+                    notes: Vec::new(),
+                });
+            }
+            FileChange::Delete { .. } => {
+                if base_version.is_none() {
+                    failures.push(format!("{path}: does not exist, so it cannot be deleted"));
+                    continue;
+                }
+                planned.push(PlannedWrite {
+                    path,
+                    before,
+                    base_version,
+                    after: None,
+                    exact_newline: false,
+                    notes: Vec::new(),
+                });
+            }
+            FileChange::Update { move_to, hunks, .. } => {
+                if base_version.is_none() {
+                    failures.push(format!("{path}: does not exist, so it cannot be updated"));
+                    continue;
+                }
+                let applied = match apply_hunks(&before, &hunks) {
+                    Ok(applied) => applied,
+                    Err(hunk_failures) => {
+                        failures.extend(
+                            hunk_failures
+                                .into_iter()
+                                .map(|failure| format!("{path}: {failure}")),
+                        );
+                        continue;
+                    }
+                };
+                let Some(destination) = move_to else {
+                    if applied.content != before {
+                        planned.push(PlannedWrite {
+                            path,
+                            before,
+                            base_version,
+                            after: Some(applied.content),
+                            exact_newline: true,
+                            notes: applied.notes,
+                        });
+                    }
+                    continue;
+                };
+                match load_edit_base(bridge, &destination, None, staged_edits).await {
+                    Ok((_, Some(_))) => failures.push(format!(
+                        "{path}: cannot be moved to {destination}, which already exists"
+                    )),
+                    Ok((destination_before, None)) => {
+                        let mut notes = applied.notes;
+                        notes.push(format!("Moved from {path}."));
+                        planned.push(PlannedWrite {
+                            path: destination,
+                            before: destination_before,
+                            base_version: None,
+                            after: Some(applied.content),
+                            exact_newline: true,
+                            notes,
+                        });
+                        planned.push(PlannedWrite {
+                            path,
+                            before,
+                            base_version,
+                            after: None,
+                            exact_newline: false,
+                            notes: Vec::new(),
+                        });
+                    }
+                    Err(err) => failures.push(format!("{destination}: {}", error_reason(&err))),
+                }
+            }
+        }
+    }
+    let mut seen = HashSet::new();
+    if let Some(duplicate) = planned.iter().find(|plan| !seen.insert(plan.path.as_str())) {
+        failures.push(format!(
+            "{}: changed more than once; combine its changes into one file section",
+            duplicate.path
+        ));
+    }
+    if !failures.is_empty() {
+        return Err(McpError::invalid_params(
+            format!(
+                "The patch does not apply; no files were changed.\n\n{}",
+                failures.join("\n\n")
+            ),
+            None,
+        ));
+    }
+    commit_planned(bridge, planned, staged_edits).await
+}
+
+/// Write (or delete) every planned file in order. If one fails, the files
+/// already written are rolled back so the set is applied whole or not at all.
+///
+/// The user approves the whole set once up front; the writes, deletes and any
+/// rollback then run under that approval, so a rejected or unanswered prompt
+/// cannot leave the set half applied.
+async fn commit_planned(
+    bridge: &BridgeClient,
+    planned: Vec<PlannedWrite>,
+    staged_edits: &StagedEdits,
+) -> Result<CallToolResult, McpError> {
+    if planned.is_empty() {
+        return Ok(CallToolResult::success(vec![Content::text(
+            "No changes detected.",
+        )]));
+    }
+
+    let grant = approve_set(bridge, &planned).await?;
+    let result = commit_approved(bridge, planned, &grant, staged_edits).await;
+    let release = BridgeArgs {
+        grant: Some(grant),
+        ..BridgeArgs::new("")
+    };
+    if let Err(err) = bridge.request(BridgeOp::EndSet, release).await {
+        debug!(error = %err, "failed to release edit set approval");
+    }
+    result
+}
+
+/// Ask the bridge to check and approve every planned change at once.
+async fn approve_set(bridge: &BridgeClient, planned: &[PlannedWrite]) -> Result<String, McpError> {
+    let args = BridgeArgs {
+        changes: Some(
+            planned
+                .iter()
+                .map(|plan| SetChange {
+                    path: plan.path.clone(),
+                    content: plan.after.clone(),
+                })
+                .collect(),
+        ),
+        ..BridgeArgs::new("")
+    };
+    match bridge.request(BridgeOp::ApproveSet, args).await {
+        Ok(reply) => Ok(reply.content),
+        Err(err) => Err(McpError::internal_error(
+            "edit set was not approved; no files were changed",
+            Some(json!({"reason": err.to_string()})),
+        )),
+    }
+}
+
+/// Write the approved set in order, rolling back on the first failure.
+async fn commit_approved(
+    bridge: &BridgeClient,
+    planned: Vec<PlannedWrite>,
+    grant: &str,
+    staged_edits: &StagedEdits,
+) -> Result<CallToolResult, McpError> {
+    let mut versions = Vec::with_capacity(planned.len());
+    for plan in &planned {
+        let result = match &plan.after {
+            Some(after) => write_via_bridge(
+                bridge,
+                &plan.path,
+                after,
+                plan.base_version.clone(),
+                Some(grant),
+                plan.exact_newline,
+                staged_edits,
+            )
+            .await
+            .map(|reply| reply.version),
+            None => delete_via_bridge(bridge, &plan.path, Some(grant), staged_edits)
+                .await
+                .map(|()| None),
+        };
+        match result {
+            Ok(version) => versions.push(version),
+            Err(err) => {
+                let written = &planned[..versions.len()];
+                let failures = roll_back(bridge, written, &versions, grant, staged_edits).await;
+                let mut message = format!("Writing {} failed: {}. ", plan.path, error_reason(&err));
+                if failures.is_empty() {
+                    message.push_str(&format!(
+                        "Rolled back {} already written file(s); no files were changed.",
+                        written.len()
+                    ));
+                } else {
+                    message.push_str(&format!(
+                        "Rolling back failed for: {}. Re-read these files before retrying.",
+                        failures.join("; ")
+                    ));
+                }
+                return Err(McpError::new(err.code, message, err.data));
+            }
+        }
+    }
+
+    let mut contents = Vec::with_capacity(planned.len() + 1);
+    let mut written = Vec::with_capacity(planned.len());
+    let mut notes = Vec::new();
+    for (plan, version) in planned.into_iter().zip(versions) {
+        let after = plan.after.as_deref().unwrap_or_default();
+        let diff_text = format_diff_for_path(&plan.path, &plan.before, after);
+        contents.push(diff_content(&plan.path, diff_text, version.as_deref()));
+        written.push(match (&plan.after, &version) {
+            (None, _) => format!("{} (deleted)", plan.path),
+            (Some(_), Some(version)) => format!("{} (version {version})", plan.path),
+            (Some(_), None) => plan.path.clone(),
+        });
+        notes.extend(
+            plan.notes
+                .iter()
+                .map(|note| format!("{}: {note}", plan.path)),
+        );
+        match plan.after {
+            Some(after) => staged_edits.stage(plan.path, after, version).await,
+            None => staged_edits.remove_tree(&plan.path).await,
+        }
+    }
+    let mut summary = format!("Write completed for {}.", written.join(", "));
+    for note in notes {
+        summary.push(' ');
+        summary.push_str(&note);
+    }
+    contents.push(Content::text(summary));
+    Ok(CallToolResult::success(contents))
 }
 
-fn apply_edits(base: &str, edits: &[EditInstruction]) -> Result<String> {
+/// Restore files written by a failed edit set, newest first: rewrite their
+/// previous content, or delete them if they were created. Returns a
+/// description of each file that could not be restored.
+async fn roll_back(
+    bridge: &BridgeClient,
+    written: &[PlannedWrite],
+    versions: &[Option<String>],
+    grant: &str,
+    staged_edits: &StagedEdits,
+) -> Vec<String> {
+    let mut failures = Vec::new();
+    for (plan, version) in written.iter().zip(versions).rev() {
+        let result = if plan.base_version.is_none() {
+            delete_via_bridge(bridge, &plan.path, Some(grant), staged_edits)
+                .await
+                .map_err(|err| error_reason(&err))
+        } else {
+            // A deleted file is recreated; a written one must still be at the
+            // version this set wrote. Either way it gets back exactly the
+            // content it had.
+            let expected_version = plan.after.as_ref().and(version.clone());
+            match write_via_bridge(
+                bridge,
+                &plan.path,
+                &plan.before,
+                expected_version,
+                Some(grant),
+                true,
+                staged_edits,
+            )
+            .await
+            {
+                Ok(reply) => {
+                    staged_edits
+                        .stage(plan.path.clone(), plan.before.clone(), reply.version)
+                        .await;
+                    Ok(())
+                }
+                Err(err) => Err(error_reason(&err)),
+            }
+        };
+        if let Err(err) = result {
+            failures.push(format!("{} ({err})", plan.path));
+        }
+    }
+    failures
+}
+
+/// Write `content` through the bridge, refusing it unless the file is still
+/// at `expected_version`. A conflict drops the stale staged copy of the file.
+/// `grant` is the approval of the edit set the write belongs to, and
+/// `exact_newline` keeps the bridge from restoring a final newline `content`
+/// leaves out.
+async fn write_via_bridge(
+    bridge: &BridgeClient,
+    path: &str,
+    content: &str,
+    expected_version: Option<String>,
+    grant: Option<&str>,
+    exact_newline: bool,
+    staged_edits: &StagedEdits,
+) -> Result<BridgeReply, McpError> {
+    let args = BridgeArgs {
+        content: Some(content.to_string()),
+        expected_version,
+        grant: grant.map(str::to_string),
+        exact_newline: exact_newline.then_some(true),
+        ..BridgeArgs::new(path)
+    };
+    match bridge.request(BridgeOp::Write, args).await {
+        Ok(reply) => Ok(reply),
+        Err(err) => {
+            let reason = err.to_string();
+            if reason.contains(VERSION_CONFLICT) {
+                staged_edits.remove_tree(path).await;
+                Err(version_conflict(path, reason))
+            } else {
+                Err(McpError::internal_error(
+                    "bridge write failed",
+                    Some(json!({"reason": reason})),
+                ))
+            }
+        }
+    }
+}
+
+/// Delete `path` through the bridge under the edit set's `grant` and drop
+/// its staged copy.
+async fn delete_via_bridge(
+    bridge: &BridgeClient,
+    path: &str,
+    grant: Option<&str>,
+    staged_edits: &StagedEdits,
+) -> Result<(), McpError> {
+    let args = BridgeArgs {
+        grant: grant.map(str::to_string),
+        ..BridgeArgs::new(path)
+    };
+    match bridge.request(BridgeOp::Delete, args).await {
+        Ok(_) => {
+            staged_edits.remove_tree(path).await;
+            Ok(())
+        }
+        Err(err) => Err(McpError::internal_error(
+            "bridge delete failed",
+            Some(json!({"reason": err.to_string()})),
+        )),
+    }
+}
+
+/// An error's message, with the underlying reason when it carries one.
+fn error_reason(err: &McpError) -> String {
+    match err
+        .data
+        .as_ref()
+        .and_then(|data| data.get("reason"))
+        .and_then(|reason| reason.as_str())
+    {
+        Some(reason) => format!("{} ({reason})", err.message),
+        None => err.message.to_string(),
+    }
+}
+
+fn version_conflict(path: &str, reason: String) -> McpError {
+    McpError::invalid_params(
+        format!(
+            "{path} changed since it was read; nothing was written. Re-read it with read_text_file and retry with the new version."
+        ),
+        Some(json!({"reason": reason})),
+    )
+}
+
+/// Apply `edits` in order. Also returns a note for each edit whose
+/// `old_string` only matched after relaxing whitespace or exactness.
+fn apply_edits(base: &str, edits: &[EditInstruction]) -> Result<(String, Vec<String>)> {
     let mut content = base.to_string();
-    for edit in edits {
+    let mut notes = Vec::new();
+    for (index, edit) in edits.iter().enumerate() {
         if edit.old_text.is_empty() {
             return Err(anyhow!(
                 "the provided `old_string` is empty. No edits were applied."
             ));
         }
 
-        if edit.replace_all {
-            let replaced = content.replace(&edit.old_text, &edit.new_text);
-            if replaced == content {
-                return Err(anyhow!(
-                    "The provided `old_string` does not appear in the file. No edits were applied."
-                ));
-            }
-            content = replaced;
-        } else {
-            let Some(index) = content.find(&edit.old_text) else {
-                return Err(anyhow!(
-                    "The provided `old_string` does not appear in the file. No edits were applied."
-                ));
-            };
-            let end = index + edit.old_text.len();
-            content.replace_range(index..end, &edit.new_text);
+        let target =
+            find_edit_target(&content, &edit.old_text, edit.replace_all).map_err(|err| {
+                if edits.len() > 1 {
+                    anyhow!("edit {}: {err}", index + 1)
+                } else {
+                    anyhow!(err)
+                }
+            })?;
+        let replacement = target.adapt_replacement(&edit.new_text);
+        for range in target.ranges.iter().rev() {
+            content.replace_range(range.clone(), &replacement);
+        }
+        if let Some(how) = target.tier.describe() {
+            notes.push(if edits.len() > 1 {
+                format!("Edit {} matched {how}.", index + 1)
+            } else {
+                format!("`old_string` matched {how}.")
+            });
+        }
+    }
+    Ok((content, notes))
+}
+
+/// Replace `remove` lines starting at 0-based line `index` with `text`,
+/// using the same line numbering as `read_text_file`.
+///
+/// Inserted text is completed to whole lines with the file's line ending; a
+/// missing final newline is preserved when the edit touches the end of file.
+fn splice_lines(base: &str, index: usize, remove: usize, text: &str) -> Result<String> {
+    let mut lines = base.split_inclusive('\n').collect::<Vec<_>>();
+    let total = lines.len();
+    if index > total || index + remove > total {
+        return Err(anyhow!(
+            "line {} is past the end of the file ({total} lines). No edits were applied.",
+            index + remove.max(1)
+        ));
+    }
+
+    let eol = if base.contains("\r\n") { "\r\n" } else { "\n" };
+    let at_end = index + remove == total;
+    let missing_final_newline = !base.is_empty() && !base.ends_with('\n');
+
+    let mut inserted = text.to_string();
+    if !inserted.is_empty() && !inserted.ends_with('\n') && !(at_end && missing_final_newline) {
+        inserted.push_str(eol);
+    }
+
+    let mut result = String::with_capacity(base.len() + inserted.len());
+    for line in lines.drain(..index) {
+        result.push_str(line);
+    }
+    if at_end && missing_final_newline && index > 0 {
+        if remove == 0 && !inserted.is_empty() {
+            // Appending after a last line that has no newline.
+            result.push_str(eol);
+        } else if inserted.is_empty() {
+            // Deleting the last lines: the new last line keeps the file's
+            // missing final newline.
+            let trimmed = result.len() - if result.ends_with("\r\n") { 2 } else { 1 };
+            result.truncate(trimmed);
         }
     }
-    Ok(content)
+    result.push_str(&inserted);
+    for line in lines.into_iter().skip(remove) {
+        result.push_str(line);
+    }
+    Ok(result)
 }
 
 fn format_diff_for_path(path: &str, before: &str, after: &str) -> String {
@@ -493,6 +1830,162 @@ fn format_diff_for_path(path: &str, before: &str, after: &str) -> String {
     }
 }
 
+/// Whether a read saw the client's buffer or the file as saved on disk.
+fn file_source_tag(source: Option<ReadSource>, fallback_reason: Option<&str>) -> Option<String> {
+    let text = match (source?, fallback_reason) {
+        (ReadSource::Client, _) => {
+            "client (the editor's buffer, including unsaved changes)".to_string()
+        }
+        (ReadSource::Disk, Some(reason)) => {
+            format!("disk ({reason}); unsaved editor changes are not included")
+        }
+        (ReadSource::Disk, None) => "disk; unsaved editor changes are not included".to_string(),
+    };
+    Some(format!("<file-source>{text}</file-source>"))
+}
+
+/// Describe a binary file instead of returning its bytes; images are
+/// attached as image content.
+fn binary_read_result(path: &str, summary: BinarySummary) -> CallToolResult {
+    let BinarySummary {
+        size,
+        mime_type,
+        sha256,
+        image,
+    } = summary;
+    let text = if image.is_some() {
+        format!("{path} is an image ({mime_type}, {size} bytes); it is attached below.")
+    } else {
+        format!(
+            "{path} is a binary file ({mime_type}, {size} bytes, sha256 {sha256}); its content cannot be shown as text."
+        )
+    };
+    let meta = json!({
+        "path": path,
+        "binary": true,
+        "mime_type": mime_type,
+        "size": size,
+        "sha256": sha256,
+        "image": image.is_some(),
+    });
+    let mut meta_obj = Meta::new();
+    meta_obj.insert("codex_fs_read".to_string(), meta);
+    let mut content = vec![
+        RawContent::Text(RawTextContent {
+            text,
+            meta: Some(meta_obj),
+        })
+        .no_annotation(),
+    ];
+    if let Some(data) = image {
+        content.push(Content::image(data, mime_type));
+    }
+    CallToolResult::success(content)
+}
+
+/// Prefix a listing entry with the listed directory so it can be passed
+/// straight to the other tools.
+fn join_listing_path(base: &str, entry: &str) -> String {
+    let base = base.trim_end_matches('/');
+    if base.is_empty() || base == "." {
+        entry.to_string()
+    } else {
+        format!("{base}/{entry}")
+    }
+}
+
+fn build_list_hint(page: &ListingPage, offset: u32, limit: u32) -> Option<String> {
+    if page.next_offset.is_none() && !page.walk_truncated {
+        return None;
+    }
+
+    let first = offset as usize + 1;
+    let last = offset as usize + page.entries.len();
+    let mut hint = format!(
+        "<list-info>Showing entries {first}-{last} of {}.",
+        page.total
+    );
+    if let Some(next_offset) = page.next_offset {
+        hint.push_str(&format!(
+            " Continue with offset={next_offset} limit={limit}."
+        ));
+    }
+    if page.walk_truncated {
+        hint.push_str(" The walk stopped early; narrow the path or pattern to see everything.");
+    }
+    hint.push_str("</list-info>");
+    Some(hint)
+}
+
+/// Render matches grep-style: `path:line:text` for matches, `path-line-text`
+/// for context, with `--` between non-adjacent groups.
+fn format_search_matches(matches: &[SearchMatch]) -> String {
+    let mut lines: Vec<String> = Vec::new();
+    // Path and last line printed so far, to avoid repeating shared context.
+    let mut printed: Option<(&str, u32)> = None;
+    for (index, found) in matches.iter().enumerate() {
+        let first_line = found.line - found.before.len() as u32;
+        let last_printed = printed
+            .filter(|(path, _)| *path == found.path)
+            .map(|(_, line)| line);
+        match last_printed {
+            Some(last) if last.saturating_add(1) >= first_line => {}
+            _ if printed.is_some() => lines.push("--".to_string()),
+            _ => {}
+        }
+
+        for (offset, context) in found.before.iter().enumerate() {
+            let line = first_line + offset as u32;
+            if last_printed.is_none_or(|last| line > last) {
+                lines.push(format!("{}-{line}-{context}", found.path));
+            }
+        }
+        lines.push(format!("{}:{}:{}", found.path, found.line, found.text));
+
+        // Stop trailing context where the next match in the file begins.
+        let next_match = matches
+            .get(index + 1)
+            .filter(|next| next.path == found.path)
+            .map(|next| next.line);
+        let mut last = found.line;
+        for context in &found.after {
+            let line = last + 1;
+            if next_match.is_some_and(|next| line >= next) {
+                break;
+            }
+            lines.push(format!("{}-{line}-{context}", found.path));
+            last = line;
+        }
+        printed = Some((&found.path, last));
+    }
+    lines.join("\n")
+}
+
+fn build_search_hint(page: &SearchPage, offset: u32) -> Option<String> {
+    if page.next_offset.is_none() && !page.walk_truncated {
+        return None;
+    }
+
+    let first = offset as usize + 1;
+    let last = offset as usize + page.matches.len();
+    let mut hint = format!(
+        "<search-info>Showing matches {first}-{last} ({} files searched).",
+        page.files_searched
+    );
+    if let Some(next_offset) = page.next_offset {
+        hint.push_str(&format!(
+            " More matches remain; continue with continuation=\"{next_offset}\"."
+        ));
+    }
+    if page.walk_truncated {
+        hint.push_str(
+            " The walk stopped early; narrow the path or include globs to search everything.",
+        );
+    }
+    hint.push_str("</search-info>");
+    Some(hint)
+}
+
 fn is_missing_file_error(message: &str) -> bool {
     let lower = message.to_ascii_lowercase();
     lower.contains("no such file") || lower.contains("not found")
@@ -620,6 +2113,45 @@ fn build_file_read_hint(
     Some(hint)
 }
 
+/// The longest suffix of `text` made of whole lines that fits in
+/// `max_bytes`, and how many lines were dropped from the front.
+fn trim_to_last_bytes(text: &str, max_bytes: usize) -> (&str, u32) {
+    if text.len() <= max_bytes {
+        return (text, 0);
+    }
+    let mut start = 0;
+    let mut skipped = 0u32;
+    for segment in text.split_inclusive('\n') {
+        if text.len() - start <= max_bytes {
+            break;
+        }
+        start += segment.len();
+        skipped += 1;
+    }
+    (&text[start..], skipped)
+}
+
+fn build_tail_hint(
+    snippet: &ReadSnippet,
+    start_line: u32,
+    total_lines: u64,
+    line_limit: u32,
+) -> String {
+    let mut hint = format!(
+        "<file-read-info>Showing lines {start_line}-{} of {total_lines}.",
+        snippet.end_line
+    );
+    if start_line > 1 {
+        let earlier = line_limit.min(start_line - 1);
+        hint.push_str(&format!(
+            " Earlier lines: continue with line={} limit={earlier}.",
+            start_line - earlier
+        ));
+    }
+    hint.push_str("</file-read-info>");
+    hint
+}
+
 fn parse_diff_line_ranges(diff_text: &str) -> (Vec<LineRange>, Vec<LineRange>) {
     let mut new_ranges = Vec::new();
     let mut old_ranges = Vec::new();
@@ -676,60 +2208,213 @@ fn line_ranges_to_json(ranges: &[LineRange]) -> Vec<serde_json::Value> {
         .collect()
 }
 
-async fn perform_bridge_request(
-    bridge_addr: &str,
-    session_id: &str,
-    op: bridge::BridgeOp,
-    path: &str,
-    line: Option<u32>,
-    limit: Option<u32>,
-    content: Option<String>,
-) -> Result<String> {
-    let request_id = NEXT_REQUEST_ID.fetch_add(1, Ordering::Relaxed);
-    let mut stream = TcpStream::connect(bridge_addr)
-        .await
-        .with_context(|| format!("failed to connect to bridge at {bridge_addr}"))?;
-    let (reader_half, mut writer_half) = stream.split();
-    let mut reader = BufReader::new(reader_half).lines();
-
-    let payload = serde_json::to_string(&json!({
-        "id": request_id,
-        "session_id": session_id,
-        "op": match op {
-            bridge::BridgeOp::Read => "read",
-            bridge::BridgeOp::Write => "write",
-        },
-        "path": path,
-        "line": line,
-        "limit": limit,
-        "content": content,
-    }))?;
+#[cfg(all(test, unix))]
+mod tests {
+    use serde_json::Value;
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+        net::UnixListener,
+        task::JoinHandle,
+    };
 
-    writer_half.write_all(payload.as_bytes()).await?;
-    writer_half.write_all(b"\n").await?;
-    writer_half.flush().await?;
+    use super::*;
+
+    /// A bridge that approves every edit set with grant `g1` and fails writes
+    /// to `fail_path`. The task returns the requests it got after the
+    /// handshake.
+    fn stub_bridge(
+        fail_path: &'static str,
+    ) -> (tempfile::TempDir, BridgeClient, JoinHandle<Vec<Value>>) {
+        let dir = tempfile::tempdir().unwrap();
+        let address = dir.path().join("bridge.sock");
+        let listener = UnixListener::bind(&address).unwrap();
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (read_half, mut write_half) = stream.into_split();
+            let mut lines = BufReader::new(read_half).lines();
+            lines.next_line().await.unwrap();
+            write_half
+                .write_all(b"{\"id\":0,\"success\":true}\n")
+                .await
+                .unwrap();
+
+            let mut requests = Vec::new();
+            while let Some(line) = lines.next_line().await.unwrap() {
+                let request: Value = serde_json::from_str(&line).unwrap();
+                let id = request["id"].as_u64().unwrap();
+                let response = match (request["op"].as_str().unwrap(), request["path"].as_str()) {
+                    ("approve_set", _) => json!({"id": id, "success": true, "content": "g1"}),
+                    ("write", Some(path)) if path == fail_path => {
+                        json!({"id": id, "success": false, "error": "disk full"})
+                    }
+                    ("write", _) => json!({"id": id, "success": true, "version": "new"}),
+                    _ => json!({"id": id, "success": true}),
+                };
+                let done = request["op"] == "end_set";
+                requests.push(request);
+                let mut line = response.to_string();
+                line.push('\n');
+                write_half.write_all(line.as_bytes()).await.unwrap();
+                if done {
+                    break;
+                }
+            }
+            requests
+        });
+        let client = BridgeClient::new(
+            BridgeEndpoint {
+                address: address.display().to_string(),
+                session_id: "s1".to_string(),
+                token: "t".to_string(),
+            },
+            BridgeTimeouts::default(),
+        );
+        (dir, client, server)
+    }
 
-    let line = timeout(Duration::from_secs(5), reader.next_line())
+    fn plan(path: &str, before: &str, base_version: Option<&str>, after: &str) -> PlannedWrite {
+        PlannedWrite {
+            path: path.to_string(),
+            before: before.to_string(),
+            base_version: base_version.map(str::to_string),
+            after: Some(after.to_string()),
+            exact_newline: false,
+            notes: Vec::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn edit_set_rolls_back_under_its_approval_when_a_write_fails() {
+        let (_dir, bridge, server) = stub_bridge("b.txt");
+        let planned = vec![
+            plan("a.txt", "a0", Some("va"), "a1"),
+            plan("new.txt", "", None, "n1"),
+            plan("b.txt", "b0", Some("vb"), "b1"),
+            plan("c.txt", "c0", Some("vc"), "c1"),
+        ];
+
+        let err = commit_planned(&bridge, planned, &StagedEdits::default())
+            .await
+            .unwrap_err();
+        assert!(
+            err.message.contains("Writing b.txt failed"),
+            "{}",
+            err.message
+        );
+        assert!(err.message.contains("Rolled back 2"), "{}", err.message);
+
+        let requests = server.await.unwrap();
+        let summary = requests
+            .iter()
+            .map(|request| {
+                format!(
+                    "{} {} {} {}",
+                    request["op"].as_str().unwrap(),
+                    request["path"].as_str().unwrap_or_default(),
+                    request["content"].as_str().unwrap_or("-"),
+                    request["grant"].as_str().unwrap_or("-"),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            [
+                "approve_set  - -",
+                "write a.txt a1 g1",
+                "write new.txt n1 g1",
+                "write b.txt b1 g1",
+                "delete new.txt - g1",
+                "write a.txt a0 g1",
+                "end_set  - g1",
+            ]
+        );
+        assert_eq!(requests[0]["changes"].as_array().unwrap().len(), 4);
+        // The rollback only restores a.txt if it is still what the set wrote.
+        assert_eq!(requests[5]["expected_version"], "new");
+    }
+
+    #[tokio::test]
+    async fn rejected_edit_set_writes_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        let address = dir.path().join("bridge.sock");
+        let listener = UnixListener::bind(&address).unwrap();
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (read_half, mut write_half) = stream.into_split();
+            let mut lines = BufReader::new(read_half).lines();
+            lines.next_line().await.unwrap();
+            write_half
+                .write_all(b"{\"id\":0,\"success\":true}\n")
+                .await
+                .unwrap();
+            let request: Value =
+                serde_json::from_str(&lines.next_line().await.unwrap().unwrap()).unwrap();
+            let response = json!({
+                "id": request["id"],
+                "success": false,
+                "error": "the edit set was rejected by the user",
+            });
+            write_half
+                .write_all(format!("{response}\n").as_bytes())
+                .await
+                .unwrap();
+            let rest = lines.next_line().await.unwrap();
+            (request["op"].clone(), rest)
+        });
+        let bridge = BridgeClient::new(
+            BridgeEndpoint {
+                address: address.display().to_string(),
+                session_id: "s1".to_string(),
+                token: "t".to_string(),
+            },
+            BridgeTimeouts::default(),
+        );
+
+        let err = commit_planned(
+            &bridge,
+            vec![plan("a.txt", "a0", Some("va"), "a1")],
+            &StagedEdits::default(),
+        )
         .await
-        .map_err(|_| anyhow!("bridge request timed out"))??
-        .ok_or_else(|| anyhow!("bridge closed connection"))?;
-
-    let response: serde_json::Value = serde_json::from_str(&line)?;
-    let success = response
-        .get("success")
-        .and_then(|s| s.as_bool())
-        .unwrap_or(false);
-    if success {
-        Ok(response
-            .get("content")
-            .and_then(|c| c.as_str())
-            .unwrap_or_default()
-            .to_string())
-    } else {
-        let message = response
-            .get("error")
-            .and_then(|e| e.as_str())
-            .unwrap_or("bridge error");
-        Err(anyhow!(message.to_string()))
+        .unwrap_err();
+        assert!(err.message.contains("no files were changed"));
+        drop(bridge);
+
+        let (op, rest) = server.await.unwrap();
+        assert_eq!(op, "approve_set");
+        assert_eq!(rest, None);
+    }
+
+    #[test]
+    fn splice_replaces_inserts_and_deletes_lines() {
+        assert_eq!(splice_lines("a\nb\nc\n", 1, 1, "B").unwrap(), "a\nB\nc\n");
+        assert_eq!(splice_lines("a\nc\n", 1, 0, "b\n").unwrap(), "a\nb\nc\n");
+        assert_eq!(splice_lines("a\nb\nc\n", 0, 2, "").unwrap(), "c\n");
+        assert_eq!(splice_lines("", 0, 0, "first").unwrap(), "first\n");
+    }
+
+    #[test]
+    fn splice_uses_the_files_line_endings() {
+        assert_eq!(
+            splice_lines("a\r\nb\r\n", 1, 0, "x").unwrap(),
+            "a\r\nx\r\nb\r\n"
+        );
+    }
+
+    #[test]
+    fn splice_keeps_a_missing_final_newline() {
+        assert_eq!(splice_lines("a\nb", 1, 1, "B").unwrap(), "a\nB");
+        assert_eq!(splice_lines("a\nb", 2, 0, "c").unwrap(), "a\nb\nc");
+        assert_eq!(splice_lines("a\nb", 1, 1, "").unwrap(), "a");
+    }
+
+    #[test]
+    fn splice_past_the_end_is_refused() {
+        let err = splice_lines("a\nb\n", 1, 2, "x").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "line 3 is past the end of the file (2 lines). No edits were applied."
+        );
+        assert!(splice_lines("a\n", 2, 0, "x").is_err());
     }
 }
@@ -1,5 +1,18 @@
+mod binary;
 pub mod bridge;
+mod client;
+mod encoding;
+mod line_index;
+mod manage;
+mod matching;
 pub mod mcp_server;
+mod notebook;
+mod patch;
+pub mod policy;
+mod search;
+mod walk;
+mod watch;
 
-pub use bridge::FsBridge;
+pub use bridge::{FsBridge, FsSessionMode};
 pub use mcp_server::run as run_mcp_server;
+pub use policy::FsPolicy;
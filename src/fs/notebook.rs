@@ -0,0 +1,362 @@
+//! Jupyter notebooks for the notebook tools.
+//!
+//! Notebooks are handled as nbformat 4 JSON values, so fields the tools don't
+//! know about survive an edit. Cells are addressed by index or by `id`. As in
+//! Jupyter, changing a code cell's source clears its outputs and execution
+//! count, and the file is written back with one-space indentation and a
+//! trailing newline.
+
+use anyhow::{Context, Result, anyhow, bail};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value, json};
+use uuid::Uuid;
+
+/// Characters of output shown per cell by default.
+pub(crate) const DEFAULT_MAX_OUTPUT_CHARS: usize = 2000;
+/// Lines of an error traceback shown.
+const MAX_TRACEBACK_LINES: usize = 10;
+
+/// A cell, by position or by its `id`.
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(untagged)]
+pub(crate) enum CellRef {
+    Index(usize),
+    Id(String),
+}
+
+impl std::fmt::Display for CellRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CellRef::Index(index) => write!(f, "{index}"),
+            CellRef::Id(id) => write!(f, "{id:?}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum CellType {
+    Code,
+    Markdown,
+    Raw,
+}
+
+impl CellType {
+    fn as_str(self) -> &'static str {
+        match self {
+            CellType::Code => "code",
+            CellType::Markdown => "markdown",
+            CellType::Raw => "raw",
+        }
+    }
+}
+
+/// One change to a notebook. Indexes refer to the notebook as left by the
+/// previous edits.
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub(crate) enum NotebookEdit {
+    /// Replace a cell's source, optionally changing its type.
+    Replace {
+        cell: CellRef,
+        source: String,
+        #[serde(default)]
+        cell_type: Option<CellType>,
+    },
+    /// Insert a new cell at index `at`, or at the end when omitted.
+    Insert {
+        #[serde(default)]
+        at: Option<usize>,
+        cell_type: CellType,
+        source: String,
+    },
+    Delete {
+        cell: CellRef,
+    },
+    /// Move a cell so that it ends up at index `to`.
+    Move {
+        cell: CellRef,
+        to: usize,
+    },
+}
+
+/// Render a notebook's cells for the model: type, id, execution count,
+/// source and outputs, with each cell's outputs cut at `max_output_chars`.
+pub(crate) fn render(text: &str, max_output_chars: usize) -> Result<String> {
+    let notebook = parse(text)?;
+    let cells = cells(&notebook)?;
+    let kernel = notebook
+        .pointer("/metadata/kernelspec/display_name")
+        .or_else(|| notebook.pointer("/metadata/kernelspec/name"))
+        .and_then(Value::as_str);
+    let mut out = format!(
+        "nbformat {}.{}, {} cells",
+        notebook["nbformat"],
+        notebook["nbformat_minor"].as_u64().unwrap_or(0),
+        cells.len()
+    );
+    if let Some(kernel) = kernel {
+        out.push_str(&format!(", kernel {kernel}"));
+    }
+    out.push('\n');
+
+    for (index, cell) in cells.iter().enumerate() {
+        let cell_type = cell["cell_type"].as_str().unwrap_or("unknown");
+        out.push_str(&format!("\n## cell {index} [{cell_type}]"));
+        if let Some(id) = cell["id"].as_str() {
+            out.push_str(&format!(" id={id}"));
+        }
+        if let Some(count) = cell["execution_count"].as_u64() {
+            out.push_str(&format!(" execution_count={count}"));
+        }
+        out.push('\n');
+        let source = multiline(&cell["source"]);
+        out.push_str(&source);
+        if !source.is_empty() && !source.ends_with('\n') {
+            out.push('\n');
+        }
+
+        let outputs = cell["outputs"].as_array().map(Vec::as_slice).unwrap_or(&[]);
+        if !outputs.is_empty() {
+            let rendered = outputs
+                .iter()
+                .map(render_output)
+                .collect::<Vec<_>>()
+                .join("\n");
+            out.push_str("### outputs\n");
+            out.push_str(&truncate_chars(&rendered, max_output_chars));
+            if !out.ends_with('\n') {
+                out.push('\n');
+            }
+        }
+    }
+    Ok(out)
+}
+
+/// Apply `edits` in order and serialize the result. Returns notes on what
+/// each edit did.
+pub(crate) fn apply_edits(text: &str, edits: &[NotebookEdit]) -> Result<(String, Vec<String>)> {
+    let mut notebook = parse(text)?;
+    cells(&notebook)?;
+    let with_ids = needs_cell_ids(&notebook);
+    let cells = notebook["cells"]
+        .as_array_mut()
+        .ok_or_else(|| anyhow!("notebook has no cells array"))?;
+    let mut notes = Vec::new();
+
+    for (number, edit) in edits.iter().enumerate().map(|(i, edit)| (i + 1, edit)) {
+        match edit {
+            NotebookEdit::Replace {
+                cell,
+                source,
+                cell_type,
+            } => {
+                let index = resolve(cells, cell).with_context(|| format!("edit {number}"))?;
+                let target = cells[index]
+                    .as_object_mut()
+                    .ok_or_else(|| anyhow!("edit {number}: cell {index} is not an object"))?;
+                if let Some(cell_type) = cell_type {
+                    target.insert("cell_type".to_string(), json!(cell_type.as_str()));
+                }
+                target.insert("source".to_string(), source_lines(source));
+                if target.get("cell_type").and_then(Value::as_str) == Some("code") {
+                    target.insert("outputs".to_string(), json!([]));
+                    target.insert("execution_count".to_string(), Value::Null);
+                } else {
+                    target.remove("outputs");
+                    target.remove("execution_count");
+                }
+                notes.push(format!("Replaced cell {index}."));
+            }
+            NotebookEdit::Insert {
+                at,
+                cell_type,
+                source,
+            } => {
+                let index = at.unwrap_or(cells.len());
+                if index > cells.len() {
+                    bail!(
+                        "edit {number}: cannot insert at {index}; the notebook has {} cells",
+                        cells.len()
+                    );
+                }
+                // Keys in the sorted order Jupyter writes them.
+                let mut cell = Map::new();
+                cell.insert("cell_type".to_string(), json!(cell_type.as_str()));
+                if *cell_type == CellType::Code {
+                    cell.insert("execution_count".to_string(), Value::Null);
+                }
+                let id = with_ids.then(|| Uuid::new_v4().simple().to_string()[..8].to_string());
+                if let Some(id) = &id {
+                    cell.insert("id".to_string(), json!(id));
+                }
+                cell.insert("metadata".to_string(), json!({}));
+                if *cell_type == CellType::Code {
+                    cell.insert("outputs".to_string(), json!([]));
+                }
+                cell.insert("source".to_string(), source_lines(source));
+                cells.insert(index, Value::Object(cell));
+                notes.push(match id {
+                    Some(id) => format!("Inserted cell {index} (id {id})."),
+                    None => format!("Inserted cell {index}."),
+                });
+            }
+            NotebookEdit::Delete { cell } => {
+                let index = resolve(cells, cell).with_context(|| format!("edit {number}"))?;
+                cells.remove(index);
+                notes.push(format!("Deleted cell {index}."));
+            }
+            NotebookEdit::Move { cell, to } => {
+                let index = resolve(cells, cell).with_context(|| format!("edit {number}"))?;
+                if *to >= cells.len() {
+                    bail!(
+                        "edit {number}: cannot move to {to}; the notebook has {} cells",
+                        cells.len()
+                    );
+                }
+                let moved = cells.remove(index);
+                cells.insert(*to, moved);
+                notes.push(format!("Moved cell {index} to {to}."));
+            }
+        }
+    }
+    Ok((serialize(&notebook)?, notes))
+}
+
+fn parse(text: &str) -> Result<Value> {
+    let notebook: Value =
+        serde_json::from_str(text).context("not a valid notebook: the file is not JSON")?;
+    match notebook["nbformat"].as_u64() {
+        Some(4) => Ok(notebook),
+        Some(version) => bail!("nbformat {version} notebooks are not supported; only nbformat 4"),
+        None => bail!("not a valid notebook: missing nbformat"),
+    }
+}
+
+fn cells(notebook: &Value) -> Result<&Vec<Value>> {
+    notebook["cells"]
+        .as_array()
+        .ok_or_else(|| anyhow!("not a valid notebook: missing cells array"))
+}
+
+/// nbformat 4.5 and later require cell ids; older notebooks get them only if
+/// they already use them.
+fn needs_cell_ids(notebook: &Value) -> bool {
+    notebook["nbformat_minor"].as_u64().unwrap_or(0) >= 5
+        || notebook["cells"]
+            .as_array()
+            .is_some_and(|cells| cells.iter().any(|cell| cell.get("id").is_some()))
+}
+
+fn resolve(cells: &[Value], cell: &CellRef) -> Result<usize> {
+    let found = match cell {
+        CellRef::Index(index) => (*index < cells.len()).then_some(*index),
+        CellRef::Id(id) => cells
+            .iter()
+            .position(|cell| cell["id"].as_str() == Some(id.as_str())),
+    };
+    found.ok_or_else(|| {
+        anyhow!(
+            "cell {cell} not found; the notebook has {} cells",
+            cells.len()
+        )
+    })
+}
+
+/// nbformat stores text as a list of lines, each keeping its newline.
+fn source_lines(source: &str) -> Value {
+    json!(source.split_inclusive('\n').collect::<Vec<_>>())
+}
+
+/// Text stored either as a string or as a list of lines.
+fn multiline(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        Value::Array(lines) => lines.iter().filter_map(Value::as_str).collect(),
+        _ => String::new(),
+    }
+}
+
+fn render_output(output: &Value) -> String {
+    match output["output_type"].as_str() {
+        Some("stream") => format!(
+            "[{}] {}",
+            output["name"].as_str().unwrap_or("stream"),
+            multiline(&output["text"])
+        ),
+        Some(kind @ ("execute_result" | "display_data")) => {
+            let data = output["data"].as_object();
+            match data.and_then(|data| data.get("text/plain")) {
+                Some(text) => format!("[{kind}] {}", multiline(text)),
+                None => {
+                    let types = data
+                        .map(|data| data.keys().cloned().collect::<Vec<_>>().join(", "))
+                        .unwrap_or_default();
+                    format!("[{kind}: {types}]")
+                }
+            }
+        }
+        Some("error") => {
+            let traceback = output["traceback"]
+                .as_array()
+                .map(|lines| {
+                    lines
+                        .iter()
+                        .rev()
+                        .take(MAX_TRACEBACK_LINES)
+                        .rev()
+                        .filter_map(Value::as_str)
+                        .map(strip_ansi)
+                        .collect::<Vec<_>>()
+                        .join("\n")
+                })
+                .unwrap_or_default();
+            format!(
+                "[error] {}: {}\n{traceback}",
+                output["ename"].as_str().unwrap_or("Error"),
+                output["evalue"].as_str().unwrap_or_default()
+            )
+        }
+        other => format!("[{}]", other.unwrap_or("output")),
+    }
+}
+
+/// Drop the terminal color codes Jupyter puts in tracebacks.
+fn strip_ansi(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\u{1b}' {
+            for c in chars.by_ref() {
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+fn truncate_chars(text: &str, max_chars: usize) -> String {
+    match text.char_indices().nth(max_chars) {
+        Some((cut, _)) => format!(
+            "{}\n… ({} more characters of output)",
+            &text[..cut],
+            text[cut..].chars().count()
+        ),
+        None => text.to_string(),
+    }
+}
+
+/// Serialize the way Jupyter does: one-space indent, trailing newline.
+fn serialize(notebook: &Value) -> Result<String> {
+    let mut bytes = Vec::new();
+    let formatter = serde_json::ser::PrettyFormatter::with_indent(b" ");
+    let mut serializer = serde_json::Serializer::with_formatter(&mut bytes, formatter);
+    notebook.serialize(&mut serializer)?;
+    bytes.push(b'\n');
+    Ok(String::from_utf8(bytes)?)
+}
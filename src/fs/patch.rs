@@ -0,0 +1,830 @@
+//! Patches for the `apply_patch` tool.
+//!
+//! Two formats are accepted: unified diffs covering one or more files (as
+//! written by `diff -u` or `git diff`), whose per-file sections are parsed
+//! with `diffy`, and the Codex `*** Begin Patch` envelope. Both are turned
+//! into [`FileChange`]s. Hunks are then placed the way `patch` does it: at
+//! the matching position nearest to where the hunk says it starts, trying an
+//! exact match, then ignoring trailing whitespace, then ignoring indentation,
+//! and finally dropping up to [`MAX_FUZZ`] context lines from either end.
+
+use diffy::{Line, Patch};
+
+use super::matching::{MatchTier, closest_match};
+
+/// Context lines that may be dropped from each end of a hunk.
+const MAX_FUZZ: usize = 2;
+/// Lines of a hunk's expected text shown when it cannot be placed.
+const MAX_EXPECTED_LINES: usize = 6;
+
+const BEGIN_PATCH: &str = "*** Begin Patch";
+const END_PATCH: &str = "*** End Patch";
+const ADD_FILE: &str = "*** Add File: ";
+const DELETE_FILE: &str = "*** Delete File: ";
+const UPDATE_FILE: &str = "*** Update File: ";
+const MOVE_TO: &str = "*** Move to: ";
+const END_OF_FILE: &str = "*** End of File";
+const DEV_NULL: &str = "/dev/null";
+
+/// One file's part of a patch.
+#[derive(Debug)]
+pub(crate) enum FileChange {
+    Add {
+        path: String,
+        content: String,
+    },
+    Delete {
+        path: String,
+    },
+    Update {
+        path: String,
+        move_to: Option<String>,
+        hunks: Vec<PatchHunk>,
+    },
+}
+
+impl FileChange {
+    /// The file the change reads from or creates.
+    pub fn path(&self) -> &str {
+        match self {
+            FileChange::Add { path, .. }
+            | FileChange::Delete { path }
+            | FileChange::Update { path, .. } => path,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct PatchHunk {
+    /// `@@` line as written in the patch, for failure reports.
+    header: Option<String>,
+    /// 1-based line the hunk's old text starts at, when the format gives it.
+    old_start: Option<usize>,
+    /// Line the hunk comes after (the Codex `@@ <context>` line).
+    anchor: Option<String>,
+    /// The hunk ends at the end of the file.
+    at_eof: bool,
+    /// Whether the file ends with a newline after the hunk, when a
+    /// `\ No newline at end of file` marker says so.
+    final_newline: Option<bool>,
+    lines: Vec<HunkLine>,
+}
+
+/// A hunk line without its terminator.
+#[derive(Debug)]
+enum HunkLine {
+    Context(String),
+    Delete(String),
+    Insert(String),
+}
+
+impl HunkLine {
+    fn old_text(&self) -> Option<&str> {
+        match self {
+            HunkLine::Context(text) | HunkLine::Delete(text) => Some(text),
+            HunkLine::Insert(_) => None,
+        }
+    }
+
+    fn new_text(&self) -> Option<&str> {
+        match self {
+            HunkLine::Context(text) | HunkLine::Insert(text) => Some(text),
+            HunkLine::Delete(_) => None,
+        }
+    }
+}
+
+/// Parse a unified diff or a Codex patch envelope.
+pub(crate) fn parse_patch(input: &str) -> Result<Vec<FileChange>, String> {
+    let changes = if input.trim_start().starts_with(BEGIN_PATCH) {
+        parse_envelope(input)?
+    } else {
+        parse_unified(input)?
+    };
+    if changes.is_empty() {
+        return Err("the patch does not change any files".to_string());
+    }
+    Ok(changes)
+}
+
+fn parse_envelope(input: &str) -> Result<Vec<FileChange>, String> {
+    let lines = input
+        .lines()
+        .map(|line| line.strip_suffix('\r').unwrap_or(line))
+        .collect::<Vec<_>>();
+    let mut index = lines
+        .iter()
+        .position(|line| line.trim() == BEGIN_PATCH)
+        .map_or(0, |begin| begin + 1);
+    let mut changes = Vec::new();
+    let mut ended = false;
+
+    while index < lines.len() {
+        let line = lines[index];
+        let line_number = index + 1;
+        index += 1;
+        if line.trim() == END_PATCH {
+            ended = true;
+            break;
+        }
+        if line.trim().is_empty() {
+            continue;
+        }
+        if let Some(path) = line.strip_prefix(ADD_FILE) {
+            let mut content = String::new();
+            while index < lines.len() && !lines[index].starts_with("*** ") {
+                let Some(text) = lines[index].strip_prefix('+') else {
+                    return Err(format!(
+                        "line {}: lines of an added file must start with '+'",
+                        index + 1
+                    ));
+                };
+                content.push_str(text);
+                content.push('\n');
+                index += 1;
+            }
+            changes.push(FileChange::Add {
+                path: path.trim().to_string(),
+                content,
+            });
+        } else if let Some(path) = line.strip_prefix(DELETE_FILE) {
+            changes.push(FileChange::Delete {
+                path: path.trim().to_string(),
+            });
+        } else if let Some(path) = line.strip_prefix(UPDATE_FILE) {
+            let move_to = match lines.get(index).and_then(|line| line.strip_prefix(MOVE_TO)) {
+                Some(target) => {
+                    index += 1;
+                    Some(target.trim().to_string())
+                }
+                None => None,
+            };
+            let mut hunks: Vec<PatchHunk> = Vec::new();
+            while index < lines.len() {
+                let line = lines[index];
+                if line == END_OF_FILE {
+                    if let Some(hunk) = hunks.last_mut() {
+                        hunk.at_eof = true;
+                    }
+                } else if line.starts_with("*** ") {
+                    break;
+                } else if let Some(anchor) = line.strip_prefix("@@") {
+                    let anchor = anchor.trim();
+                    hunks.push(PatchHunk {
+                        header: Some(line.to_string()),
+                        old_start: None,
+                        anchor: (!anchor.is_empty()).then(|| anchor.to_string()),
+                        at_eof: false,
+                        final_newline: None,
+                        lines: Vec::new(),
+                    });
+                } else {
+                    let hunk_line = match line.chars().next() {
+                        Some(' ') => HunkLine::Context(line[1..].to_string()),
+                        Some('-') => HunkLine::Delete(line[1..].to_string()),
+                        Some('+') => HunkLine::Insert(line[1..].to_string()),
+                        // Blank context lines often lose their leading space.
+                        None => HunkLine::Context(String::new()),
+                        Some(_) => {
+                            return Err(format!(
+                                "line {}: expected a line starting with ' ', '-', '+' or '@@', \
+                                 found {line:?}",
+                                index + 1
+                            ));
+                        }
+                    };
+                    if hunks.is_empty() {
+                        hunks.push(PatchHunk {
+                            header: None,
+                            old_start: None,
+                            anchor: None,
+                            at_eof: false,
+                            final_newline: None,
+                            lines: Vec::new(),
+                        });
+                    }
+                    if let Some(hunk) = hunks.last_mut() {
+                        hunk.lines.push(hunk_line);
+                    }
+                }
+                index += 1;
+            }
+            hunks.retain(|hunk| !hunk.lines.is_empty());
+            if hunks.is_empty() && move_to.is_none() {
+                return Err(format!("line {line_number}: update of {path} has no hunks"));
+            }
+            changes.push(FileChange::Update {
+                path: path.trim().to_string(),
+                move_to,
+                hunks,
+            });
+        } else {
+            return Err(format!(
+                "line {line_number}: expected '{ADD_FILE}', '{DELETE_FILE}', '{UPDATE_FILE}' or \
+                 '{END_PATCH}', found {line:?}"
+            ));
+        }
+    }
+    if !ended {
+        return Err(format!("the patch is missing its '{END_PATCH}' line"));
+    }
+    Ok(changes)
+}
+
+fn parse_unified(input: &str) -> Result<Vec<FileChange>, String> {
+    split_unified(input)
+        .into_iter()
+        .filter_map(|section| parse_unified_file(&section).transpose())
+        .collect()
+}
+
+/// Split a multi-file diff at `diff --git` lines, or at `---`/`+++` header
+/// pairs when there are none.
+fn split_unified(input: &str) -> Vec<String> {
+    let lines = input.split_inclusive('\n').collect::<Vec<_>>();
+    let mut sections = Vec::new();
+    let mut current = String::new();
+    let mut seen_header = false;
+    for (index, line) in lines.iter().enumerate() {
+        let git_header = line.starts_with("diff --git ");
+        let file_header = line.starts_with("--- ")
+            && lines
+                .get(index + 1)
+                .is_some_and(|next| next.starts_with("+++ "));
+        if (git_header || (file_header && seen_header)) && !current.trim().is_empty() {
+            sections.push(std::mem::take(&mut current));
+            seen_header = false;
+        }
+        seen_header |= file_header;
+        current.push_str(line);
+    }
+    if !current.trim().is_empty() {
+        sections.push(current);
+    }
+    sections
+}
+
+fn parse_unified_file(section: &str) -> Result<Option<FileChange>, String> {
+    let git_path = |prefix: &str| {
+        section
+            .lines()
+            .find_map(|line| line.strip_prefix(prefix))
+            .map(|path| path.trim().to_string())
+    };
+    let rename = git_path("rename from ").zip(git_path("rename to "));
+    let has_hunks = section.lines().any(|line| line.starts_with("@@"));
+    if !has_hunks {
+        // Renames and new or deleted empty files have no hunks in git diffs.
+        let git_paths = section
+            .lines()
+            .find_map(|line| line.strip_prefix("diff --git "))
+            .and_then(|paths| paths.split_once(" b/"))
+            .map(|(old, new)| (strip_git_prefix(old.trim()), new.trim().to_string()));
+        return Ok(match (rename, git_paths) {
+            (Some((from, to)), _) => Some(FileChange::Update {
+                path: from,
+                move_to: Some(to),
+                hunks: Vec::new(),
+            }),
+            (None, Some((_, path))) if section.contains("\nnew file mode") => {
+                Some(FileChange::Add {
+                    path,
+                    content: String::new(),
+                })
+            }
+            (None, Some((path, _))) if section.contains("\ndeleted file mode") => {
+                Some(FileChange::Delete { path })
+            }
+            _ => None,
+        });
+    }
+
+    let patch = Patch::from_str(section).map_err(|err| {
+        let file = section
+            .lines()
+            .find_map(|line| line.strip_prefix("+++ "))
+            .unwrap_or("the patch");
+        format!(
+            "{} in {}; check that each @@ header's line counts match its hunk",
+            err,
+            strip_git_prefix(file.trim())
+        )
+    })?;
+    let original = patch.original().map(strip_git_prefix);
+    let modified = patch.modified().map(strip_git_prefix);
+    let hunk_headers = section.lines().filter(|line| line.starts_with("@@"));
+    let hunks = patch
+        .hunks()
+        .iter()
+        .zip(hunk_headers)
+        .map(|(hunk, header)| {
+            // diffy keeps the newline on every line but one followed by a
+            // `\ No newline at end of file` marker, which is the last line
+            // of its side of the diff.
+            let (mut old_missing, mut new_missing) = (false, false);
+            for line in hunk.lines() {
+                match line {
+                    Line::Context(text) => {
+                        old_missing |= !text.ends_with('\n');
+                        new_missing |= !text.ends_with('\n');
+                    }
+                    Line::Delete(text) => old_missing |= !text.ends_with('\n'),
+                    Line::Insert(text) => new_missing |= !text.ends_with('\n'),
+                }
+            }
+            let marked = old_missing || new_missing;
+            PatchHunk {
+                header: Some(header.to_string()),
+                old_start: Some(if hunk.old_range().is_empty() {
+                    // An insertion-only hunk names the line it follows.
+                    hunk.old_range().start() + 1
+                } else {
+                    hunk.old_range().start()
+                }),
+                anchor: None,
+                at_eof: marked,
+                final_newline: marked.then_some(!new_missing),
+                lines: hunk
+                    .lines()
+                    .iter()
+                    .map(|line| match line {
+                        Line::Context(text) => HunkLine::Context(without_eol(text)),
+                        Line::Delete(text) => HunkLine::Delete(without_eol(text)),
+                        Line::Insert(text) => HunkLine::Insert(without_eol(text)),
+                    })
+                    .collect(),
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let change = match (original.as_deref(), modified.as_deref()) {
+        (Some(DEV_NULL), new) => {
+            let path = new
+                .filter(|path| *path != DEV_NULL)
+                .ok_or("a diff that creates a file must name it on its +++ line")?;
+            let mut content = String::new();
+            for line in hunks.iter().flat_map(|hunk| &hunk.lines) {
+                if let Some(text) = line.new_text() {
+                    content.push_str(text);
+                    content.push('\n');
+                }
+            }
+            if hunks.last().and_then(|hunk| hunk.final_newline) == Some(false) {
+                content.pop();
+            }
+            FileChange::Add {
+                path: path.to_string(),
+                content,
+            }
+        }
+        (Some(old), Some(DEV_NULL)) => FileChange::Delete {
+            path: old.to_string(),
+        },
+        (old, new) => {
+            let path = old
+                .or(new)
+                .ok_or("the diff has hunks but no ---/+++ file names")?
+                .to_string();
+            let move_to = new
+                .filter(|new| *new != path)
+                .map(str::to_string)
+                .or(rename.map(|(_, to)| to));
+            FileChange::Update {
+                path,
+                move_to,
+                hunks,
+            }
+        }
+    };
+    Ok(Some(change))
+}
+
+fn strip_git_prefix(path: &str) -> String {
+    path.strip_prefix("a/")
+        .or_else(|| path.strip_prefix("b/"))
+        .unwrap_or(path)
+        .to_string()
+}
+
+fn without_eol(text: &str) -> String {
+    let text = text.strip_suffix('\n').unwrap_or(text);
+    text.strip_suffix('\r').unwrap_or(text).to_string()
+}
+
+/// Result of applying a file's hunks.
+#[derive(Debug)]
+pub(crate) struct AppliedHunks {
+    pub content: String,
+    /// Hunks that needed an offset, relaxed matching or fuzz.
+    pub notes: Vec<String>,
+}
+
+/// Apply `hunks` to `content` in order. Fails with one message per hunk
+/// that could not be placed; the other hunks are still checked so every
+/// failure is reported at once.
+pub(crate) fn apply_hunks(content: &str, hunks: &[PatchHunk]) -> Result<AppliedHunks, Vec<String>> {
+    let mut lines = content.lines().map(str::to_string).collect::<Vec<_>>();
+    let mut final_newline = content.is_empty() || content.ends_with('\n');
+    let mut cursor = 0;
+    let mut shift = 0isize;
+    let mut notes = Vec::new();
+    let mut failures = Vec::new();
+
+    for (number, hunk) in hunks.iter().enumerate().map(|(i, hunk)| (i + 1, hunk)) {
+        let label = match &hunk.header {
+            Some(header) => format!("hunk {number} ({header})"),
+            None => format!("hunk {number}"),
+        };
+        let mut from = cursor;
+        if let Some(anchor) = &hunk.anchor {
+            match (cursor..lines.len()).find(|&i| lines[i].trim() == anchor.trim()) {
+                Some(found) => from = found + 1,
+                None => {
+                    failures.push(format!(
+                        "{label}: the line {anchor:?} it follows was not found{}",
+                        if cursor > 0 {
+                            format!(" after line {cursor}")
+                        } else {
+                            String::new()
+                        }
+                    ));
+                    continue;
+                }
+            }
+        }
+        let target = match hunk.old_start {
+            Some(start) => ((start as isize - 1 + shift).max(0) as usize).max(from),
+            None => from,
+        };
+
+        let Some(placement) = place_hunk(&lines, hunk, from, target) else {
+            failures.push(hunk_failure(&label, &lines, hunk, target));
+            continue;
+        };
+        let old_len = placement.old.len();
+        if let Some(newline) = hunk.final_newline {
+            // The marker only makes sense where the hunk ends the file, which
+            // fuzz dropping its trailing context would no longer show.
+            if placement.at + old_len != lines.len() {
+                failures.push(format!(
+                    "{label}: it changes whether the file ends with a newline, but it does not \
+                     apply at the end of the file"
+                ));
+                continue;
+            }
+            if newline != final_newline {
+                notes.push(format!(
+                    "{label} {} the final newline.",
+                    if newline { "added" } else { "removed" }
+                ));
+            }
+            final_newline = newline;
+        }
+        let new = placement.new.iter().map(|line| line.to_string());
+        lines.splice(placement.at..placement.at + old_len, new);
+
+        let mut how = Vec::new();
+        if let Some(start) = hunk.old_start {
+            let expected = (start as isize - 1 + shift).max(0) as usize + placement.lead;
+            if placement.at != expected {
+                how.push(format!(
+                    "at line {} (offset {:+} lines)",
+                    placement.at + 1,
+                    placement.at as isize - expected as isize
+                ));
+            }
+        }
+        if let Some(relaxed) = placement.tier.describe() {
+            how.push(relaxed.to_string());
+        }
+        if placement.fuzz > 0 {
+            how.push(format!("with fuzz {}", placement.fuzz));
+        }
+        if !how.is_empty() {
+            notes.push(format!("{label} applied {}.", how.join(", ")));
+        }
+
+        let delta = placement.new.len() as isize - old_len as isize;
+        if let Some(start) = hunk.old_start {
+            shift = (placement.at - placement.lead) as isize - (start as isize - 1) + delta;
+        }
+        cursor = placement.at + placement.new.len();
+    }
+
+    if !failures.is_empty() {
+        return Err(failures);
+    }
+    let eol = if content.contains("\r\n") {
+        "\r\n"
+    } else {
+        "\n"
+    };
+    let mut content = lines.join(eol);
+    if final_newline && !content.is_empty() {
+        content.push_str(eol);
+    }
+    Ok(AppliedHunks { content, notes })
+}
+
+struct Placement<'a> {
+    /// Index in the file's lines where `old` starts.
+    at: usize,
+    /// Leading context lines dropped by fuzz.
+    lead: usize,
+    fuzz: usize,
+    tier: MatchTier,
+    old: Vec<&'a str>,
+    new: Vec<&'a str>,
+}
+
+fn place_hunk<'a>(
+    lines: &[String],
+    hunk: &'a PatchHunk,
+    from: usize,
+    target: usize,
+) -> Option<Placement<'a>> {
+    let leading_context = hunk
+        .lines
+        .iter()
+        .take_while(|line| matches!(line, HunkLine::Context(_)))
+        .count();
+    let trailing_context = hunk
+        .lines
+        .iter()
+        .rev()
+        .take_while(|line| matches!(line, HunkLine::Context(_)))
+        .count()
+        .min(hunk.lines.len() - leading_context);
+
+    for fuzz in 0..=MAX_FUZZ {
+        let lead = fuzz.min(leading_context);
+        let trail = fuzz.min(trailing_context);
+        if fuzz > 0 && fuzz > leading_context.max(trailing_context) {
+            break;
+        }
+        let body = &hunk.lines[lead..hunk.lines.len() - trail];
+        let old = body
+            .iter()
+            .filter_map(HunkLine::old_text)
+            .collect::<Vec<_>>();
+        let new = body
+            .iter()
+            .filter_map(HunkLine::new_text)
+            .collect::<Vec<_>>();
+
+        if old.is_empty() {
+            if fuzz > 0 {
+                break;
+            }
+            let at = if hunk.at_eof {
+                lines.len()
+            } else {
+                target.min(lines.len())
+            };
+            return Some(Placement {
+                at,
+                lead,
+                fuzz,
+                tier: MatchTier::Exact,
+                old,
+                new,
+            });
+        }
+        if old.len() > lines.len() || from > lines.len() - old.len() {
+            continue;
+        }
+        let candidates = if hunk.at_eof && trail == 0 {
+            lines.len() - old.len()..lines.len() - old.len() + 1
+        } else {
+            from..lines.len() - old.len() + 1
+        };
+        let wanted = target + lead;
+        for (tier, normalize) in [
+            (MatchTier::Exact, exact as fn(&str) -> &str),
+            (MatchTier::Whitespace, str::trim_end),
+            (MatchTier::Indentation, str::trim),
+        ] {
+            let found = candidates
+                .clone()
+                .filter(|&at| {
+                    old.iter()
+                        .zip(&lines[at..])
+                        .all(|(expected, actual)| normalize(expected) == normalize(actual))
+                })
+                .min_by_key(|&at| at.abs_diff(wanted));
+            if let Some(at) = found {
+                return Some(Placement {
+                    at,
+                    lead,
+                    fuzz,
+                    tier,
+                    old,
+                    new,
+                });
+            }
+        }
+    }
+    None
+}
+
+fn exact(line: &str) -> &str {
+    line
+}
+
+fn hunk_failure(label: &str, lines: &[String], hunk: &PatchHunk, target: usize) -> String {
+    let old = hunk
+        .lines
+        .iter()
+        .filter_map(HunkLine::old_text)
+        .collect::<Vec<_>>();
+    let mut message = format!(
+        "{label}: the lines it changes were not found near line {}. Expected:",
+        target + 1
+    );
+    for line in old.iter().take(MAX_EXPECTED_LINES) {
+        message.push_str(&format!("\n  {line}"));
+    }
+    if old.len() > MAX_EXPECTED_LINES {
+        message.push_str("\n  …");
+    }
+    let mut current = lines.join("\n");
+    current.push('\n');
+    if let Some(candidate) = closest_match(&current, &old.join("\n")) {
+        message.push('\n');
+        message.push_str(&candidate);
+    }
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn update(patch: &str) -> Vec<PatchHunk> {
+        match parse_patch(patch).unwrap().pop() {
+            Some(FileChange::Update { hunks, .. }) => hunks,
+            other => panic!("expected an update, got {other:?}"),
+        }
+    }
+
+    fn apply(content: &str, patch: &str) -> Result<AppliedHunks, Vec<String>> {
+        apply_hunks(content, &update(patch))
+    }
+
+    #[test]
+    fn envelope_parses_every_kind_of_change() {
+        let changes = parse_patch(
+            "*** Begin Patch\n\
+             *** Add File: new.txt\n\
+             +hello\n\
+             +world\n\
+             *** Delete File: old.txt\n\
+             *** Update File: src/lib.rs\n\
+             *** Move to: src/main.rs\n\
+             @@ fn main() {\n\
+             -    one();\n\
+             +    two();\n\
+             *** End of File\n\
+             *** End Patch\n",
+        )
+        .unwrap();
+        assert_eq!(changes.len(), 3);
+        assert!(
+            matches!(&changes[0], FileChange::Add { path, content } if path == "new.txt" && content == "hello\nworld\n")
+        );
+        assert!(matches!(&changes[1], FileChange::Delete { path } if path == "old.txt"));
+        let FileChange::Update {
+            path,
+            move_to,
+            hunks,
+        } = &changes[2]
+        else {
+            panic!("expected an update");
+        };
+        assert_eq!(path, "src/lib.rs");
+        assert_eq!(move_to.as_deref(), Some("src/main.rs"));
+        assert_eq!(hunks[0].anchor.as_deref(), Some("fn main() {"));
+        assert!(hunks[0].at_eof);
+    }
+
+    #[test]
+    fn unified_diff_parses_several_files() {
+        let changes = parse_patch(
+            "diff --git a/a.txt b/a.txt\n\
+             --- a/a.txt\n\
+             +++ b/a.txt\n\
+             @@ -1,2 +1,2 @@\n\
+             -one\n\
+             +uno\n \
+             two\n\
+             --- /dev/null\n\
+             +++ b/new.txt\n\
+             @@ -0,0 +1 @@\n\
+             +fresh\n\
+             --- a/gone.txt\n\
+             +++ /dev/null\n\
+             @@ -1 +0,0 @@\n\
+             -bye\n",
+        )
+        .unwrap();
+        assert_eq!(
+            changes.iter().map(FileChange::path).collect::<Vec<_>>(),
+            ["a.txt", "new.txt", "gone.txt"]
+        );
+        assert!(matches!(&changes[1], FileChange::Add { content, .. } if content == "fresh\n"));
+        assert!(matches!(&changes[2], FileChange::Delete { .. }));
+    }
+
+    #[test]
+    fn empty_patch_is_rejected() {
+        assert!(parse_patch("*** Begin Patch\n*** End Patch\n").is_err());
+    }
+
+    #[test]
+    fn hunks_apply_at_an_offset_and_report_it() {
+        let applied = apply(
+            "zero\none\ntwo\nthree\n",
+            "--- a/f\n+++ b/f\n@@ -1,2 +1,2 @@\n one\n-two\n+TWO\n",
+        )
+        .unwrap();
+        assert_eq!(applied.content, "zero\none\nTWO\nthree\n");
+        assert_eq!(applied.notes.len(), 1);
+        assert!(
+            applied.notes[0].contains("offset +1 lines"),
+            "{:?}",
+            applied.notes
+        );
+    }
+
+    #[test]
+    fn whitespace_differences_are_tolerated() {
+        let applied = apply(
+            "a\r\nb  \r\nc\r\n",
+            "*** Begin Patch\n*** Update File: f\n@@\n a\n-b\n+B\n c\n*** End Patch\n",
+        )
+        .unwrap();
+        assert_eq!(applied.content, "a\r\nB\r\nc\r\n");
+    }
+
+    #[test]
+    fn stale_context_is_fuzzed_away() {
+        let applied = apply(
+            "first\nsecond\nthird\nfourth\n",
+            "--- a/f\n+++ b/f\n@@ -1,4 +1,4 @@\n changed\n second\n-third\n+THIRD\n fourth\n",
+        )
+        .unwrap();
+        assert_eq!(applied.content, "first\nsecond\nTHIRD\nfourth\n");
+        assert!(applied.notes[0].contains("fuzz 1"), "{:?}", applied.notes);
+    }
+
+    #[test]
+    fn unmatched_hunks_are_all_reported() {
+        let failures = apply(
+            "a\nb\nc\n",
+            "--- a/f\n+++ b/f\n@@ -1 +1 @@\n-x\n+X\n@@ -3 +3 @@\n-y\n+Y\n",
+        )
+        .unwrap_err();
+        assert_eq!(failures.len(), 2);
+        assert!(
+            failures[0].starts_with("hunk 1 (@@ -1 +1 @@)"),
+            "{failures:?}"
+        );
+    }
+
+    #[test]
+    fn missing_newline_markers_are_honoured() {
+        let removed = apply(
+            "a\nb\n",
+            "--- a/f\n+++ b/f\n@@ -1,2 +1,2 @@\n a\n-b\n+b\n\\ No newline at end of file\n",
+        )
+        .unwrap();
+        assert_eq!(removed.content, "a\nb");
+        assert_eq!(
+            removed.notes,
+            ["hunk 1 (@@ -1,2 +1,2 @@) removed the final newline."]
+        );
+
+        let added = apply(
+            "a\nb",
+            "--- a/f\n+++ b/f\n@@ -1,2 +1,2 @@\n a\n-b\n\\ No newline at end of file\n+b\n",
+        )
+        .unwrap();
+        assert_eq!(added.content, "a\nb\n");
+
+        let created = parse_patch(
+            "--- /dev/null\n+++ b/f\n@@ -0,0 +1 @@\n+only\n\\ No newline at end of file\n",
+        )
+        .unwrap();
+        assert!(matches!(&created[0], FileChange::Add { content, .. } if content == "only"));
+    }
+
+    #[test]
+    fn missing_newline_marker_away_from_the_end_is_rejected() {
+        let failures = apply(
+            "a\nb\nc\n",
+            "--- a/f\n+++ b/f\n@@ -1,3 +1,3 @@\n a\n-b\n+B\n x\n\\ No newline at end of file\n",
+        )
+        .unwrap_err();
+        assert!(failures[0].contains("end of the file"), "{failures:?}");
+    }
+}
@@ -0,0 +1,417 @@
+//! Workspace confinement for the FS bridge.
+//!
+//! Every bridge request is checked against the policy registered for its
+//! session, which is derived from the session's Codex `SandboxPolicy`:
+//! - full disk write access (`danger-full-access`): no restrictions;
+//! - otherwise writes are limited to the sandbox's writable roots (the
+//!   workspace root plus configured `writable_roots`), minus their read-only
+//!   subpaths such as `.git`; a read-only sandbox has no writable roots;
+//! - reads are limited to the workspace root, the writable roots and the
+//!   `[acp.fs] read_allow` list. Other reads need the user's approval.
+//!
+//! Paths are canonicalized before the check so symlinks cannot be used to
+//! escape the allowed roots, including dangling symlinks that a write would
+//! create the target of. The bridge then operates on the canonical path the
+//! check approved.
+
+use std::{
+    fs, io,
+    path::{Component, Path, PathBuf},
+};
+
+use codex_core::protocol::SandboxPolicy;
+
+/// Kind of access requested for a path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FsAccess {
+    Read,
+    Write,
+}
+
+/// Result of checking a path against an [`FsPolicy`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PolicyDecision {
+    Allowed {
+        canonical: PathBuf,
+    },
+    /// The read is outside the allowed roots; the user may still approve it.
+    NeedsApproval {
+        canonical: PathBuf,
+    },
+    Denied(String),
+}
+
+/// A root the session may write to, excluding its read-only subpaths.
+#[derive(Debug, Clone)]
+struct WriteRoot {
+    root: PathBuf,
+    read_only_subpaths: Vec<PathBuf>,
+}
+
+impl WriteRoot {
+    fn allows(&self, path: &Path) -> bool {
+        path.starts_with(&self.root)
+            && !self
+                .read_only_subpaths
+                .iter()
+                .any(|subpath| path.starts_with(subpath))
+    }
+}
+
+/// Most symlinks followed while resolving one path, as in Linux's `MAXSYMLINKS`.
+const MAX_SYMLINK_HOPS: u32 = 40;
+
+/// Per-session confinement rules enforced by the FS bridge.
+#[derive(Debug, Clone)]
+pub struct FsPolicy {
+    workspace_root: PathBuf,
+    /// `None` means reads are unrestricted.
+    read_roots: Option<Vec<PathBuf>>,
+    /// `None` means writes are unrestricted.
+    write_roots: Option<Vec<WriteRoot>>,
+}
+
+impl FsPolicy {
+    /// Derive the policy from a session's sandbox, its workspace root and the
+    /// configured read allow-list (relative entries resolve against the root).
+    pub fn from_sandbox(
+        sandbox: &SandboxPolicy,
+        workspace_root: &Path,
+        read_allow: &[PathBuf],
+    ) -> Self {
+        let workspace_root =
+            canonicalize_lenient(workspace_root).unwrap_or_else(|_| workspace_root.to_path_buf());
+
+        if sandbox.has_full_disk_write_access() {
+            return Self {
+                workspace_root,
+                read_roots: None,
+                write_roots: None,
+            };
+        }
+
+        let write_roots: Vec<WriteRoot> = sandbox
+            .get_writable_roots_with_cwd(&workspace_root)
+            .into_iter()
+            .map(|writable| WriteRoot {
+                root: canonicalize_or_keep(&writable.root),
+                read_only_subpaths: writable
+                    .read_only_subpaths
+                    .iter()
+                    .map(|subpath| canonicalize_or_keep(subpath))
+                    .collect(),
+            })
+            .collect();
+
+        let mut read_roots = vec![workspace_root.clone()];
+        read_roots.extend(write_roots.iter().map(|root| root.root.clone()));
+        read_roots.extend(
+            read_allow
+                .iter()
+                .map(|entry| canonicalize_or_keep(&workspace_root.join(entry))),
+        );
+
+        Self {
+            workspace_root,
+            read_roots: Some(read_roots),
+            write_roots: Some(write_roots),
+        }
+    }
+
+    /// Canonical workspace root the policy was built for.
+    pub fn workspace_root(&self) -> &Path {
+        &self.workspace_root
+    }
+
+    /// Check `path` (absolute, lexically normalized) for the requested access,
+    /// following symlinks all the way.
+    pub fn check(&self, path: &Path, access: FsAccess) -> PolicyDecision {
+        self.decide(path, canonicalize_lenient(path), access)
+    }
+
+    /// Like [`FsPolicy::check`], but for ops on the directory entry itself
+    /// (deleting or moving it): a symlink in the last component is not
+    /// followed.
+    pub fn check_entry(&self, path: &Path, access: FsAccess) -> PolicyDecision {
+        self.decide(path, canonicalize_entry(path), access)
+    }
+
+    fn decide(
+        &self,
+        path: &Path,
+        canonical: io::Result<PathBuf>,
+        access: FsAccess,
+    ) -> PolicyDecision {
+        let canonical = match canonical {
+            Ok(canonical) => canonical,
+            Err(err) => {
+                return PolicyDecision::Denied(format!(
+                    "failed to resolve {}: {err}",
+                    path.display()
+                ));
+            }
+        };
+
+        match access {
+            FsAccess::Read => match &self.read_roots {
+                None => PolicyDecision::Allowed { canonical },
+                Some(roots) if roots.iter().any(|root| canonical.starts_with(root)) => {
+                    PolicyDecision::Allowed { canonical }
+                }
+                Some(_) => PolicyDecision::NeedsApproval { canonical },
+            },
+            FsAccess::Write => match &self.write_roots {
+                None => PolicyDecision::Allowed { canonical },
+                Some(roots) if roots.iter().any(|root| root.allows(&canonical)) => {
+                    PolicyDecision::Allowed { canonical }
+                }
+                Some(roots) if roots.is_empty() => PolicyDecision::Denied(
+                    "writes are disabled by the session's read-only sandbox".to_string(),
+                ),
+                Some(_) => PolicyDecision::Denied(format!(
+                    "{} is outside the session's writable roots",
+                    describe(path, &canonical)
+                )),
+            },
+        }
+    }
+}
+
+/// Lexically resolve `path` against `root`, collapsing `.` and `..`.
+///
+/// Symlinks are not followed here; [`FsPolicy::check`] canonicalizes the result.
+pub fn normalize_path(root: &Path, path: &str) -> Result<PathBuf, String> {
+    let candidate = Path::new(path);
+    let mut resolved = if candidate.is_absolute() {
+        PathBuf::new()
+    } else {
+        root.to_path_buf()
+    };
+
+    for component in candidate.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !resolved.pop() {
+                    return Err(format!("path escapes the filesystem root: {path}"));
+                }
+            }
+            Component::Normal(part) => resolved.push(part),
+            Component::RootDir => resolved.push(Component::RootDir),
+            Component::Prefix(prefix) => resolved.push(prefix.as_os_str()),
+        }
+    }
+
+    Ok(resolved)
+}
+
+/// Canonicalize `path`, resolving symlinks in the longest existing prefix so
+/// that files which do not exist yet can still be checked.
+///
+/// A dangling symlink is resolved to where it points, since writing through
+/// it would create its target.
+pub fn canonicalize_lenient(path: &Path) -> io::Result<PathBuf> {
+    resolve_lenient(path, MAX_SYMLINK_HOPS)
+}
+
+/// Canonicalize the parent of `path` and keep its last component as is, so a
+/// symlink there names the link rather than its target.
+pub fn canonicalize_entry(path: &Path) -> io::Result<PathBuf> {
+    match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) => Ok(canonicalize_lenient(parent)?.join(name)),
+        _ => canonicalize_lenient(path),
+    }
+}
+
+fn resolve_lenient(path: &Path, hops: u32) -> io::Result<PathBuf> {
+    let mut existing = path;
+    let mut missing = Vec::new();
+    loop {
+        match fs::canonicalize(existing) {
+            Ok(mut canonical) => {
+                canonical.extend(missing.iter().rev());
+                return Ok(canonical);
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                if fs::symlink_metadata(existing).is_ok_and(|meta| meta.file_type().is_symlink()) {
+                    if hops == 0 {
+                        return Err(io::Error::other("too many levels of symbolic links"));
+                    }
+                    let target = fs::read_link(existing)?;
+                    let target = match existing.parent() {
+                        Some(parent) => parent.join(target),
+                        None => target,
+                    };
+                    let mut canonical = resolve_lenient(&target, hops - 1)?;
+                    canonical.extend(missing.iter().rev());
+                    return Ok(canonical);
+                }
+                let (Some(parent), Some(name)) = (existing.parent(), existing.file_name()) else {
+                    return Err(err);
+                };
+                missing.push(name.to_os_string());
+                existing = parent;
+            }
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+fn canonicalize_or_keep(path: &Path) -> PathBuf {
+    canonicalize_lenient(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+fn describe(path: &Path, canonical: &Path) -> String {
+    if path == canonical {
+        path.display().to_string()
+    } else {
+        format!("{} (resolves to {})", path.display(), canonical.display())
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::os::unix::fs::symlink;
+
+    use tempfile::TempDir;
+
+    use super::*;
+
+    /// A workspace-write policy over a fresh workspace directory, plus a
+    /// directory outside it.
+    fn workspace() -> (TempDir, PathBuf, PathBuf, FsPolicy) {
+        let dir = TempDir::new().unwrap();
+        let root = fs::canonicalize(dir.path()).unwrap();
+        let ws = root.join("ws");
+        let outside = root.join("outside");
+        fs::create_dir_all(ws.join(".git")).unwrap();
+        fs::create_dir(&outside).unwrap();
+        let policy = FsPolicy {
+            workspace_root: ws.clone(),
+            read_roots: Some(vec![ws.clone()]),
+            write_roots: Some(vec![WriteRoot {
+                root: ws.clone(),
+                read_only_subpaths: vec![ws.join(".git")],
+            }]),
+        };
+        (dir, ws, outside, policy)
+    }
+
+    fn allowed(path: impl Into<PathBuf>) -> PolicyDecision {
+        PolicyDecision::Allowed {
+            canonical: path.into(),
+        }
+    }
+
+    #[test]
+    fn allows_workspace_paths_and_new_files() {
+        let (_dir, ws, _, policy) = workspace();
+        fs::write(ws.join("a.txt"), "a").unwrap();
+
+        assert_eq!(
+            policy.check(&ws.join("a.txt"), FsAccess::Write),
+            allowed(ws.join("a.txt"))
+        );
+        assert_eq!(
+            policy.check(&ws.join("new/dir/b.txt"), FsAccess::Write),
+            allowed(ws.join("new/dir/b.txt"))
+        );
+    }
+
+    #[test]
+    fn denies_read_only_subpaths_and_outside_writes() {
+        let (_dir, ws, outside, policy) = workspace();
+
+        assert!(matches!(
+            policy.check(&ws.join(".git/config"), FsAccess::Write),
+            PolicyDecision::Denied(_)
+        ));
+        assert!(matches!(
+            policy.check(&outside.join("x"), FsAccess::Write),
+            PolicyDecision::Denied(_)
+        ));
+        assert_eq!(
+            policy.check(&outside.join("x"), FsAccess::Read),
+            PolicyDecision::NeedsApproval {
+                canonical: outside.join("x")
+            }
+        );
+    }
+
+    #[test]
+    fn parent_components_cannot_escape() {
+        let (_dir, ws, outside, policy) = workspace();
+        let path = normalize_path(&ws, "sub/../../outside/x").unwrap();
+
+        assert_eq!(path, outside.join("x"));
+        assert!(matches!(
+            policy.check(&path, FsAccess::Write),
+            PolicyDecision::Denied(_)
+        ));
+        assert!(normalize_path(Path::new("/"), "../etc").is_err());
+    }
+
+    #[test]
+    fn symlink_out_of_the_workspace_is_denied() {
+        let (_dir, ws, outside, policy) = workspace();
+        fs::write(outside.join("secret"), "s").unwrap();
+        symlink(&outside, ws.join("link")).unwrap();
+
+        assert!(matches!(
+            policy.check(&ws.join("link/secret"), FsAccess::Write),
+            PolicyDecision::Denied(_)
+        ));
+        assert!(matches!(
+            policy.check(&ws.join("link/new"), FsAccess::Write),
+            PolicyDecision::Denied(_)
+        ));
+    }
+
+    #[test]
+    fn dangling_symlink_resolves_to_its_target() {
+        let (_dir, ws, outside, policy) = workspace();
+        symlink(outside.join("newfile"), ws.join("escape")).unwrap();
+        symlink("inside.txt", ws.join("local")).unwrap();
+        symlink(outside.join("missing/dir"), ws.join("dir")).unwrap();
+
+        assert!(matches!(
+            policy.check(&ws.join("escape"), FsAccess::Write),
+            PolicyDecision::Denied(_)
+        ));
+        assert!(matches!(
+            policy.check(&ws.join("dir/file"), FsAccess::Write),
+            PolicyDecision::Denied(_)
+        ));
+        assert_eq!(
+            policy.check(&ws.join("local"), FsAccess::Write),
+            allowed(ws.join("inside.txt"))
+        );
+    }
+
+    #[test]
+    fn entry_checks_do_not_follow_the_last_component() {
+        let (_dir, ws, outside, policy) = workspace();
+        symlink(&outside, ws.join("link")).unwrap();
+
+        assert_eq!(
+            policy.check_entry(&ws.join("link"), FsAccess::Write),
+            allowed(ws.join("link"))
+        );
+        assert!(matches!(
+            policy.check_entry(&ws.join("link/x"), FsAccess::Write),
+            PolicyDecision::Denied(_)
+        ));
+    }
+
+    #[test]
+    fn symlink_loops_are_refused() {
+        let (_dir, ws, _, policy) = workspace();
+        symlink(ws.join("b"), ws.join("a")).unwrap();
+        symlink(ws.join("a"), ws.join("b")).unwrap();
+
+        assert!(matches!(
+            policy.check(&ws.join("a"), FsAccess::Write),
+            PolicyDecision::Denied(_)
+        ));
+    }
+}
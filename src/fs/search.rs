@@ -0,0 +1,431 @@
+//! Content search for the bridge's `search_files` operation.
+//!
+//! Candidate files come from the same ignore-aware walk as the listings. The
+//! [`Search`] collector is fed one file at a time so the bridge can take file
+//! contents from the client's buffers or from disk.
+
+use std::path::{Path, PathBuf};
+
+use globset::{GlobSet, GlobSetBuilder};
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+
+use super::walk::{self, MAX_WALK_ENTRIES};
+
+/// Cap on the text of one page of results.
+const MAX_SEARCH_BYTES: usize = 50 * 1024;
+/// Longer matched or context lines are cut to this many characters.
+const MAX_LINE_CHARS: usize = 300;
+/// Files larger than this are skipped.
+const MAX_SEARCH_FILE_BYTES: u64 = 4 * 1024 * 1024;
+/// Bytes inspected for NUL when deciding whether a file is binary.
+const BINARY_SNIFF_BYTES: usize = 8 * 1024;
+
+/// Where searched file contents were read from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ContentSource {
+    /// The client's view of the files, including unsaved buffers.
+    Client,
+    Disk,
+}
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct SearchOptions {
+    pub pattern: String,
+    /// Treat `pattern` as plain text instead of a regular expression.
+    pub literal: bool,
+    pub case_insensitive: bool,
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    /// Lines of context before and after each match.
+    pub context: usize,
+    /// Matches to skip (from the continuation token).
+    pub offset: usize,
+    pub limit: usize,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub(crate) struct SearchMatch {
+    /// Workspace-relative path when inside the workspace, absolute otherwise.
+    pub path: String,
+    /// 1-based line number.
+    pub line: u32,
+    pub text: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub before: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub after: Vec<String>,
+}
+
+/// One page of search results, as sent back over the bridge.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub(crate) struct SearchPage {
+    pub matches: Vec<SearchMatch>,
+    /// Offset of the first match of the next page, if there is one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_offset: Option<usize>,
+    pub files_searched: usize,
+    pub source: ContentSource,
+    /// The walk stopped at the entry cap; some files were not searched.
+    #[serde(default)]
+    pub walk_truncated: bool,
+}
+
+/// A file to search: its absolute path and the path reported in matches.
+#[derive(Debug, Clone)]
+pub(crate) struct Candidate {
+    pub path: PathBuf,
+    pub display: String,
+}
+
+/// Files under `root` (or `root` itself when it is a file) that pass the
+/// include/exclude globs, sorted by path.
+///
+/// Globs without a `/` match file names at any depth; others match the path
+/// relative to `root`. Also reports whether the walk stopped at the entry cap.
+pub(crate) fn candidate_files(
+    root: &Path,
+    workspace_root: &Path,
+    include: &[String],
+    exclude: &[String],
+) -> Result<(Vec<Candidate>, bool), String> {
+    let metadata = std::fs::metadata(root)
+        .map_err(|err| format!("failed to read {}: {err}", root.display()))?;
+    if metadata.is_file() {
+        return Ok((
+            vec![Candidate {
+                path: root.to_path_buf(),
+                display: display_path(root, workspace_root),
+            }],
+            false,
+        ));
+    }
+
+    let include = FileFilter::new(include)?;
+    let exclude = FileFilter::new(exclude)?;
+    let mut candidates = Vec::new();
+    let mut truncated = false;
+    for (visited, entry) in walk::walker(root, None).flatten().enumerate() {
+        if visited >= MAX_WALK_ENTRIES {
+            truncated = true;
+            break;
+        }
+        if !entry
+            .file_type()
+            .is_some_and(|file_type| file_type.is_file())
+        {
+            continue;
+        }
+        let Some(relative) = walk::relative_path(root, &entry) else {
+            continue;
+        };
+        if include
+            .as_ref()
+            .is_some_and(|filter| !filter.matches(&relative))
+            || exclude
+                .as_ref()
+                .is_some_and(|filter| filter.matches(&relative))
+        {
+            continue;
+        }
+        candidates.push(Candidate {
+            display: display_path(entry.path(), workspace_root),
+            path: entry.into_path(),
+        });
+    }
+    candidates.sort_by(|a, b| a.display.cmp(&b.display));
+    Ok((candidates, truncated))
+}
+
+/// Read a file for searching; `None` for large, binary or non-UTF-8 files.
+pub(crate) fn read_searchable(path: &Path) -> Option<String> {
+    let metadata = std::fs::metadata(path).ok()?;
+    if metadata.len() > MAX_SEARCH_FILE_BYTES {
+        return None;
+    }
+    let bytes = std::fs::read(path).ok()?;
+    if bytes[..bytes.len().min(BINARY_SNIFF_BYTES)].contains(&0) {
+        return None;
+    }
+    String::from_utf8(bytes).ok()
+}
+
+/// Collects one page of matches across the files it is fed.
+pub(crate) struct Search {
+    regex: Regex,
+    context: usize,
+    offset: usize,
+    limit: usize,
+    seen: usize,
+    bytes: usize,
+    matches: Vec<SearchMatch>,
+    next_offset: Option<usize>,
+    files_searched: usize,
+}
+
+impl Search {
+    pub fn new(options: &SearchOptions) -> Result<Self, String> {
+        let pattern = if options.literal {
+            regex::escape(&options.pattern)
+        } else {
+            options.pattern.clone()
+        };
+        let regex = RegexBuilder::new(&pattern)
+            .case_insensitive(options.case_insensitive)
+            .build()
+            .map_err(|err| format!("invalid search pattern: {err}"))?;
+        Ok(Self {
+            regex,
+            context: options.context,
+            offset: options.offset,
+            limit: options.limit.max(1),
+            seen: 0,
+            bytes: 0,
+            matches: Vec::new(),
+            next_offset: None,
+            files_searched: 0,
+        })
+    }
+
+    /// Whether the page is full and later files need not be searched.
+    pub fn is_done(&self) -> bool {
+        self.next_offset.is_some()
+    }
+
+    pub fn scan(&mut self, display: &str, content: &str) {
+        if self.is_done() {
+            return;
+        }
+        self.files_searched += 1;
+        let lines: Vec<&str> = content.lines().collect();
+        for (index, line) in lines.iter().enumerate() {
+            if !self.regex.is_match(line) {
+                continue;
+            }
+            self.seen += 1;
+            if self.seen <= self.offset {
+                continue;
+            }
+            if self.matches.len() >= self.limit || self.bytes >= MAX_SEARCH_BYTES {
+                self.next_offset = Some(self.seen - 1);
+                return;
+            }
+
+            let before = lines[index.saturating_sub(self.context)..index]
+                .iter()
+                .map(|line| clip_line(line))
+                .collect::<Vec<_>>();
+            let after_end = (index + 1 + self.context).min(lines.len());
+            let after = lines[index + 1..after_end]
+                .iter()
+                .map(|line| clip_line(line))
+                .collect::<Vec<_>>();
+            let found = SearchMatch {
+                path: display.to_string(),
+                line: index as u32 + 1,
+                text: clip_line(line),
+                before,
+                after,
+            };
+            self.bytes += found.path.len()
+                + found.text.len()
+                + found
+                    .before
+                    .iter()
+                    .chain(&found.after)
+                    .map(String::len)
+                    .sum::<usize>();
+            self.matches.push(found);
+        }
+    }
+
+    pub fn finish(self, source: ContentSource, walk_truncated: bool) -> SearchPage {
+        SearchPage {
+            matches: self.matches,
+            next_offset: self.next_offset,
+            files_searched: self.files_searched,
+            source,
+            walk_truncated,
+        }
+    }
+}
+
+/// Include/exclude glob list, split by whether globs match names or paths.
+struct FileFilter {
+    names: GlobSet,
+    paths: GlobSet,
+}
+
+impl FileFilter {
+    fn new(globs: &[String]) -> Result<Option<Self>, String> {
+        if globs.is_empty() {
+            return Ok(None);
+        }
+        let mut names = GlobSetBuilder::new();
+        let mut paths = GlobSetBuilder::new();
+        for glob in globs {
+            let parsed = walk::build_glob(glob)?;
+            if glob.contains('/') {
+                paths.add(parsed);
+            } else {
+                names.add(parsed);
+            }
+        }
+        let build = |builder: GlobSetBuilder| {
+            builder
+                .build()
+                .map_err(|err| format!("invalid glob list: {err}"))
+        };
+        Ok(Some(Self {
+            names: build(names)?,
+            paths: build(paths)?,
+        }))
+    }
+
+    fn matches(&self, relative: &str) -> bool {
+        let name = relative.rsplit('/').next().unwrap_or(relative);
+        self.names.is_match(name) || self.paths.is_match(relative)
+    }
+}
+
+fn display_path(path: &Path, workspace_root: &Path) -> String {
+    match path.strip_prefix(workspace_root) {
+        Ok(relative) if !relative.as_os_str().is_empty() => relative.display().to_string(),
+        _ => path.display().to_string(),
+    }
+}
+
+fn clip_line(line: &str) -> String {
+    match line.char_indices().nth(MAX_LINE_CHARS) {
+        Some((cut, _)) => format!("{}…", &line[..cut]),
+        None => line.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    const FILES: [(&str, &str); 2] = [
+        ("a.txt", "todo one\nskip\ntodo two\n"),
+        ("b.txt", "TODO three\n"),
+    ];
+
+    fn page(options: SearchOptions) -> SearchPage {
+        let mut search = Search::new(&options).unwrap();
+        for (display, content) in FILES {
+            if search.is_done() {
+                break;
+            }
+            search.scan(display, content);
+        }
+        search.finish(ContentSource::Disk, false)
+    }
+
+    fn found(page: &SearchPage) -> Vec<(&str, u32)> {
+        page.matches
+            .iter()
+            .map(|found| (found.path.as_str(), found.line))
+            .collect()
+    }
+
+    #[test]
+    fn pages_continue_across_files() {
+        let options = SearchOptions {
+            pattern: "todo".to_string(),
+            case_insensitive: true,
+            limit: 2,
+            ..Default::default()
+        };
+        let first = page(options.clone());
+        assert_eq!(found(&first), [("a.txt", 1), ("a.txt", 3)]);
+        assert_eq!(first.next_offset, Some(2));
+        assert_eq!(first.files_searched, 2);
+
+        let second = page(SearchOptions {
+            offset: 2,
+            ..options
+        });
+        assert_eq!(found(&second), [("b.txt", 1)]);
+        assert_eq!(second.next_offset, None);
+    }
+
+    #[test]
+    fn a_full_last_page_has_no_continuation() {
+        let last = page(SearchOptions {
+            pattern: "todo".to_string(),
+            limit: 2,
+            ..Default::default()
+        });
+        assert_eq!(found(&last), [("a.txt", 1), ("a.txt", 3)]);
+        assert_eq!(last.next_offset, None);
+    }
+
+    #[test]
+    fn matches_carry_context_lines() {
+        let result = page(SearchOptions {
+            pattern: "skip".to_string(),
+            context: 1,
+            limit: 10,
+            ..Default::default()
+        });
+        assert_eq!(result.matches[0].before, ["todo one"]);
+        assert_eq!(result.matches[0].after, ["todo two"]);
+    }
+
+    #[test]
+    fn literal_patterns_are_escaped() {
+        let mut search = Search::new(&SearchOptions {
+            pattern: "a.b(".to_string(),
+            literal: true,
+            limit: 10,
+            ..Default::default()
+        })
+        .unwrap();
+        search.scan("f", "a.b(\naxb(\n");
+        assert_eq!(search.finish(ContentSource::Client, false).matches.len(), 1);
+
+        let err = Search::new(&SearchOptions {
+            pattern: "a.b(".to_string(),
+            ..Default::default()
+        })
+        .err()
+        .unwrap();
+        assert!(err.starts_with("invalid search pattern"), "{err}");
+    }
+
+    #[test]
+    fn candidates_follow_include_and_exclude_globs() {
+        let dir = tempfile::tempdir().unwrap();
+        for file in ["main.rs", "src/lib.rs", "src/gen/out.rs", "notes.md"] {
+            let path = dir.path().join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "x").unwrap();
+        }
+        let include = ["*.rs".to_string()];
+        let exclude = ["src/gen/**".to_string()];
+        let (candidates, truncated) =
+            candidate_files(dir.path(), dir.path(), &include, &exclude).unwrap();
+        let displays = candidates
+            .iter()
+            .map(|candidate| candidate.display.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(displays, ["main.rs", "src/lib.rs"]);
+        assert!(!truncated);
+    }
+
+    #[test]
+    fn binary_files_and_long_lines_are_handled() {
+        let dir = tempfile::tempdir().unwrap();
+        let binary = dir.path().join("blob");
+        fs::write(&binary, b"text\0more").unwrap();
+        assert_eq!(read_searchable(&binary), None);
+
+        let clipped = clip_line(&"x".repeat(MAX_LINE_CHARS + 5));
+        assert_eq!(clipped.chars().count(), MAX_LINE_CHARS + 1);
+        assert!(clipped.ends_with('…'));
+    }
+}
@@ -0,0 +1,263 @@
+//! Directory walking for the bridge's `list_directory` and `glob` operations.
+//!
+//! Walks honor `.gitignore`, `.ignore` and global git excludes (even outside a
+//! git repository), never descend into `.git`, and do not follow symlinks, so
+//! a listing cannot leave the directory the policy check approved.
+
+use std::path::Path;
+
+use globset::{Glob, GlobBuilder};
+use ignore::{DirEntry, WalkBuilder};
+use serde::{Deserialize, Serialize};
+
+/// Upper bound on entries visited by one walk, to keep huge trees responsive.
+pub(crate) const MAX_WALK_ENTRIES: usize = 100_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum EntryKind {
+    File,
+    Directory,
+    Symlink,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub(crate) struct ListEntry {
+    /// Path relative to the walked directory, `/`-separated.
+    pub path: String,
+    pub kind: EntryKind,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+}
+
+/// One page of a listing, as sent back over the bridge.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub(crate) struct ListingPage {
+    pub entries: Vec<ListEntry>,
+    /// Number of matching entries across all pages.
+    pub total: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_offset: Option<usize>,
+    /// The walk stopped at the entry cap; `total` is a lower bound.
+    #[serde(default)]
+    pub walk_truncated: bool,
+}
+
+/// List `dir` up to `depth` levels deep (1 = immediate children).
+///
+/// Also reports whether the walk stopped at the entry cap.
+pub(crate) fn list_directory(dir: &Path, depth: usize) -> Result<(Vec<ListEntry>, bool), String> {
+    ensure_directory(dir)?;
+    Ok(walk(dir, Some(depth), |_| true))
+}
+
+/// Find entries under `dir` whose relative path matches `pattern`.
+///
+/// `*` and `?` do not cross `/`; `**` matches any number of directories.
+pub(crate) fn glob(dir: &Path, pattern: &str) -> Result<(Vec<ListEntry>, bool), String> {
+    ensure_directory(dir)?;
+    let matcher = build_glob(pattern)?.compile_matcher();
+    Ok(walk(dir, None, |relative| matcher.is_match(relative)))
+}
+
+/// Slice a sorted listing into one page.
+pub(crate) fn paginate(
+    entries: Vec<ListEntry>,
+    offset: usize,
+    limit: usize,
+    walk_truncated: bool,
+) -> ListingPage {
+    let total = entries.len();
+    let end = offset.saturating_add(limit).min(total);
+    let next_offset = (end < total).then_some(end);
+    let entries = entries
+        .into_iter()
+        .skip(offset)
+        .take(end.saturating_sub(offset))
+        .collect();
+    ListingPage {
+        entries,
+        total,
+        next_offset,
+        walk_truncated,
+    }
+}
+
+/// Parse a glob in which `*` and `?` do not match `/`.
+pub(crate) fn build_glob(pattern: &str) -> Result<Glob, String> {
+    GlobBuilder::new(pattern)
+        .literal_separator(true)
+        .build()
+        .map_err(|err| format!("invalid glob pattern {pattern:?}: {err}"))
+}
+
+/// A walker over `dir` with the bridge's ignore rules applied.
+pub(crate) fn walker(dir: &Path, max_depth: Option<usize>) -> ignore::Walk {
+    WalkBuilder::new(dir)
+        .hidden(false)
+        .git_ignore(true)
+        .git_global(true)
+        .git_exclude(true)
+        .ignore(true)
+        .parents(true)
+        .require_git(false)
+        .follow_links(false)
+        .max_depth(max_depth)
+        .filter_entry(|entry| entry.file_name() != ".git")
+        .build()
+}
+
+/// Relative, `/`-separated path of `entry` below `dir`.
+pub(crate) fn relative_path(dir: &Path, entry: &DirEntry) -> Option<String> {
+    let relative = entry.path().strip_prefix(dir).ok()?;
+    if relative.as_os_str().is_empty() {
+        return None;
+    }
+    Some(
+        relative
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/"),
+    )
+}
+
+fn walk(
+    dir: &Path,
+    max_depth: Option<usize>,
+    include: impl Fn(&str) -> bool,
+) -> (Vec<ListEntry>, bool) {
+    let mut entries = Vec::new();
+    let mut truncated = false;
+    for (visited, entry) in walker(dir, max_depth).flatten().enumerate() {
+        if visited >= MAX_WALK_ENTRIES {
+            truncated = true;
+            break;
+        }
+        let Some(relative) = relative_path(dir, &entry) else {
+            continue;
+        };
+        if !include(&relative) {
+            continue;
+        }
+        let Some(file_type) = entry.file_type() else {
+            continue;
+        };
+        let (kind, size) = if file_type.is_symlink() {
+            (EntryKind::Symlink, None)
+        } else if file_type.is_dir() {
+            (EntryKind::Directory, None)
+        } else {
+            (
+                EntryKind::File,
+                entry.metadata().ok().map(|metadata| metadata.len()),
+            )
+        };
+        entries.push(ListEntry {
+            path: relative,
+            kind,
+            size,
+        });
+    }
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    (entries, truncated)
+}
+
+fn ensure_directory(dir: &Path) -> Result<(), String> {
+    match std::fs::metadata(dir) {
+        Ok(metadata) if metadata.is_dir() => Ok(()),
+        Ok(_) => Err(format!("{} is not a directory", dir.display())),
+        Err(err) => Err(format!("failed to read {}: {err}", dir.display())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    fn tree() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        for file in [
+            "a.rs",
+            "src/b.rs",
+            "src/deep/c.rs",
+            "target/out.rs",
+            ".git/HEAD",
+        ] {
+            let path = dir.path().join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "x").unwrap();
+        }
+        fs::write(dir.path().join(".gitignore"), "target/\n").unwrap();
+        dir
+    }
+
+    fn paths(entries: &[ListEntry]) -> Vec<&str> {
+        entries.iter().map(|entry| entry.path.as_str()).collect()
+    }
+
+    #[test]
+    fn listings_honor_depth_and_ignore_rules() {
+        let dir = tree();
+        let (entries, truncated) = list_directory(dir.path(), 1).unwrap();
+        assert_eq!(paths(&entries), [".gitignore", "a.rs", "src"]);
+        assert!(!truncated);
+        assert_eq!(entries[1].kind, EntryKind::File);
+        assert_eq!(entries[1].size, Some(1));
+        assert_eq!(entries[2].kind, EntryKind::Directory);
+
+        let (entries, _) = list_directory(dir.path(), 3).unwrap();
+        assert_eq!(
+            paths(&entries),
+            [
+                ".gitignore",
+                "a.rs",
+                "src",
+                "src/b.rs",
+                "src/deep",
+                "src/deep/c.rs"
+            ]
+        );
+    }
+
+    #[test]
+    fn globs_keep_single_stars_within_a_directory() {
+        let dir = tree();
+        let (entries, _) = glob(dir.path(), "*.rs").unwrap();
+        assert_eq!(paths(&entries), ["a.rs"]);
+        let (entries, _) = glob(dir.path(), "**/*.rs").unwrap();
+        assert_eq!(paths(&entries), ["a.rs", "src/b.rs", "src/deep/c.rs"]);
+        assert!(glob(dir.path(), "src/[").is_err());
+    }
+
+    #[test]
+    fn walking_a_file_is_refused() {
+        let dir = tree();
+        let err = list_directory(&dir.path().join("a.rs"), 1).unwrap_err();
+        assert!(err.ends_with("is not a directory"), "{err}");
+    }
+
+    #[test]
+    fn pages_cover_the_listing_in_order() {
+        let dir = tree();
+        let (entries, _) = list_directory(dir.path(), 3).unwrap();
+
+        let first = paginate(entries.clone(), 0, 4, false);
+        assert_eq!(
+            paths(&first.entries),
+            [".gitignore", "a.rs", "src", "src/b.rs"]
+        );
+        assert_eq!((first.total, first.next_offset), (6, Some(4)));
+
+        let last = paginate(entries.clone(), 4, 4, false);
+        assert_eq!(paths(&last.entries), ["src/deep", "src/deep/c.rs"]);
+        assert_eq!(last.next_offset, None);
+
+        let past = paginate(entries, 10, 4, true);
+        assert!(past.entries.is_empty());
+        assert_eq!((past.total, past.next_offset), (6, None));
+        assert!(past.walk_truncated);
+    }
+}
@@ -0,0 +1,329 @@
+//! Noticing files that change outside the agent.
+//!
+//! For each session the bridge records the size and modification time of
+//! every file it reads or writes on the session's behalf. A watcher thread
+//! looks at those files again when they may have changed: on Linux when
+//! inotify reports activity in their directories, elsewhere on a timer. A
+//! file that no longer matches what a session recorded is queued for that
+//! session and goes out with the session's next bridge response, so its
+//! `acp_fs` server can drop staged copies and tell the model.
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, Weak},
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use tracing::{debug, warn};
+
+/// How often tracked files are checked without inotify.
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// What a file looked like when a session last saw it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Stamp {
+    len: u64,
+    modified: Option<SystemTime>,
+}
+
+fn stamp(path: &Path) -> Option<Stamp> {
+    fs::metadata(path).ok().map(|metadata| Stamp {
+        len: metadata.len(),
+        modified: metadata.modified().ok(),
+    })
+}
+
+struct Seen {
+    /// The path as the session's tools name it.
+    requested: String,
+    state: SeenState,
+}
+
+enum SeenState {
+    /// The session is changing the file itself.
+    Writing,
+    /// `None` if the file did not exist.
+    At(Option<Stamp>),
+}
+
+#[derive(Default)]
+struct State {
+    /// Canonical path -> session id -> what the session saw.
+    files: HashMap<PathBuf, HashMap<String, Seen>>,
+    /// Session id -> changed paths not yet reported.
+    pending: HashMap<String, BTreeSet<String>>,
+    #[cfg(target_os = "linux")]
+    watches: inotify::Watches,
+}
+
+impl State {
+    fn set(&mut self, session: &str, path: &Path, requested: &str, seen: SeenState) {
+        self.files.entry(path.to_path_buf()).or_default().insert(
+            session.to_string(),
+            Seen {
+                requested: requested.to_string(),
+                state: seen,
+            },
+        );
+    }
+}
+
+/// Files the sessions have seen, and external changes to them.
+pub(crate) struct ChangeTracker {
+    state: Mutex<State>,
+    #[cfg(target_os = "linux")]
+    inotify: Option<Arc<nix::sys::inotify::Inotify>>,
+}
+
+impl ChangeTracker {
+    /// Create a tracker and start its watcher thread.
+    pub fn start() -> Arc<Self> {
+        let tracker = Arc::new(Self {
+            state: Mutex::default(),
+            #[cfg(target_os = "linux")]
+            inotify: inotify::init(),
+        });
+        let weak = Arc::downgrade(&tracker);
+
+        #[cfg(target_os = "linux")]
+        let watching = match tracker.inotify.clone() {
+            Some(instance) => {
+                let weak = weak.clone();
+                spawn_watcher("codex-acp-fs-watch", move || inotify::run(&instance, &weak));
+                true
+            }
+            None => false,
+        };
+        #[cfg(not(target_os = "linux"))]
+        let watching = false;
+
+        if !watching {
+            spawn_watcher("codex-acp-fs-poll", move || {
+                while let Some(tracker) = sleep_then_upgrade(&weak) {
+                    tracker.check_all();
+                }
+            });
+        }
+        tracker
+    }
+
+    /// Note that `session` is about to change `path` itself, so the watcher
+    /// does not report the change back to it.
+    pub fn begin_write(&self, session: &str, path: &Path, requested: &str) {
+        if let Ok(mut state) = self.state.lock() {
+            state.set(session, path, requested, SeenState::Writing);
+        }
+    }
+
+    /// Record what `path` looks like now as what `session` has seen.
+    pub fn record(&self, session: &str, path: &Path, requested: &str) {
+        let current = stamp(path);
+        let Ok(mut state) = self.state.lock() else {
+            return;
+        };
+        state.set(session, path, requested, SeenState::At(current));
+        if let Some(pending) = state.pending.get_mut(session) {
+            pending.remove(requested);
+        }
+        #[cfg(target_os = "linux")]
+        if let Some(instance) = &self.inotify
+            && let Some(directory) = path.parent()
+        {
+            state.watches.add(instance, directory);
+        }
+    }
+
+    /// Changed paths not yet reported to `session`.
+    pub fn take(&self, session: &str) -> Vec<String> {
+        self.state
+            .lock()
+            .ok()
+            .and_then(|mut state| state.pending.remove(session))
+            .map(|paths| paths.into_iter().collect())
+            .unwrap_or_default()
+    }
+
+    /// Drop everything recorded for `session`, and stop watching directories
+    /// no other session has files in.
+    pub fn forget(&self, session: &str) {
+        let Ok(mut state) = self.state.lock() else {
+            return;
+        };
+        state.pending.remove(session);
+        state.files.retain(|_, sessions| {
+            sessions.remove(session);
+            !sessions.is_empty()
+        });
+        #[cfg(target_os = "linux")]
+        if let Some(instance) = &self.inotify {
+            let State { files, watches, .. } = &mut *state;
+            let needed = files
+                .keys()
+                .filter_map(|path| path.parent())
+                .collect::<std::collections::HashSet<_>>();
+            watches.retain(instance, |directory| needed.contains(directory));
+        }
+    }
+
+    /// Queue `path` for every session whose record of it is out of date.
+    fn check(&self, path: &Path) {
+        let current = stamp(path);
+        let Ok(mut state) = self.state.lock() else {
+            return;
+        };
+        let State { files, pending, .. } = &mut *state;
+        let Some(sessions) = files.get_mut(path) else {
+            return;
+        };
+        for (session, seen) in sessions {
+            if matches!(seen.state, SeenState::At(previous) if previous != current) {
+                debug!(path = %path.display(), session, "file changed outside the session");
+                seen.state = SeenState::At(current);
+                pending
+                    .entry(session.clone())
+                    .or_default()
+                    .insert(seen.requested.clone());
+            }
+        }
+    }
+
+    fn check_all(&self) {
+        let paths = match self.state.lock() {
+            Ok(state) => state.files.keys().cloned().collect::<Vec<_>>(),
+            Err(_) => return,
+        };
+        for path in paths {
+            self.check(&path);
+        }
+    }
+}
+
+fn spawn_watcher(name: &str, run: impl FnOnce() + Send + 'static) {
+    if let Err(err) = thread::Builder::new().name(name.to_string()).spawn(run) {
+        warn!(error = %err, "failed to start fs change watcher");
+    }
+}
+
+fn sleep_then_upgrade(tracker: &Weak<ChangeTracker>) -> Option<Arc<ChangeTracker>> {
+    thread::sleep(POLL_INTERVAL);
+    tracker.upgrade()
+}
+
+#[cfg(target_os = "linux")]
+mod inotify {
+    use std::{
+        collections::HashMap,
+        path::{Path, PathBuf},
+        sync::{Arc, Weak},
+    };
+
+    use nix::{
+        errno::Errno,
+        sys::inotify::{AddWatchFlags, InitFlags, Inotify, WatchDescriptor},
+    };
+    use tracing::{debug, warn};
+
+    use super::ChangeTracker;
+
+    /// Watched directories, both ways round.
+    #[derive(Default)]
+    pub(super) struct Watches {
+        by_directory: HashMap<PathBuf, WatchDescriptor>,
+        by_descriptor: HashMap<WatchDescriptor, PathBuf>,
+    }
+
+    impl Watches {
+        pub fn add(&mut self, instance: &Inotify, directory: &Path) {
+            if self.by_directory.contains_key(directory) {
+                return;
+            }
+            let mask = AddWatchFlags::IN_CLOSE_WRITE
+                | AddWatchFlags::IN_MOVED_TO
+                | AddWatchFlags::IN_MOVED_FROM
+                | AddWatchFlags::IN_CREATE
+                | AddWatchFlags::IN_DELETE
+                | AddWatchFlags::IN_ONLYDIR;
+            match instance.add_watch(directory, mask) {
+                Ok(descriptor) => {
+                    self.by_directory
+                        .insert(directory.to_path_buf(), descriptor);
+                    self.by_descriptor
+                        .insert(descriptor, directory.to_path_buf());
+                }
+                Err(err) => {
+                    debug!(error = %err, directory = %directory.display(), "cannot watch directory")
+                }
+            }
+        }
+
+        /// Stop watching the directories `keep` rejects.
+        pub fn retain(&mut self, instance: &Inotify, keep: impl Fn(&Path) -> bool) {
+            self.by_directory.retain(|directory, descriptor| {
+                if keep(directory) {
+                    return true;
+                }
+                if let Err(err) = instance.rm_watch(*descriptor) {
+                    debug!(error = %err, directory = %directory.display(), "cannot unwatch directory");
+                }
+                self.by_descriptor.remove(descriptor);
+                false
+            });
+        }
+
+        fn remove(&mut self, descriptor: WatchDescriptor) {
+            if let Some(directory) = self.by_descriptor.remove(&descriptor) {
+                self.by_directory.remove(&directory);
+            }
+        }
+    }
+
+    pub(super) fn init() -> Option<Arc<Inotify>> {
+        match Inotify::init(InitFlags::IN_CLOEXEC) {
+            Ok(instance) => Some(Arc::new(instance)),
+            Err(err) => {
+                warn!(error = %err, "inotify unavailable; polling for file changes instead");
+                None
+            }
+        }
+    }
+
+    /// Check the files named by inotify events until the tracker is gone.
+    pub(super) fn run(instance: &Inotify, tracker: &Weak<ChangeTracker>) {
+        loop {
+            let events = match instance.read_events() {
+                Ok(events) => events,
+                Err(Errno::EINTR) => continue,
+                Err(err) => {
+                    warn!(error = %err, "inotify failed; no longer watching for file changes");
+                    return;
+                }
+            };
+            let Some(tracker) = tracker.upgrade() else {
+                return;
+            };
+            for event in events {
+                if event.mask.contains(AddWatchFlags::IN_Q_OVERFLOW) {
+                    tracker.check_all();
+                    continue;
+                }
+                let Ok(mut state) = tracker.state.lock() else {
+                    return;
+                };
+                if event.mask.contains(AddWatchFlags::IN_IGNORED) {
+                    state.watches.remove(event.wd);
+                    continue;
+                }
+                let path = event
+                    .name
+                    .and_then(|name| Some(state.watches.by_descriptor.get(&event.wd)?.join(name)));
+                drop(state);
+                if let Some(path) = path {
+                    tracker.check(&path);
+                }
+            }
+        }
+    }
+}
@@ -9,15 +9,18 @@
 //! Modules:
 //! - `agent`: The core ACP agent implementation and its submodules.
 //! - `fs`: Filesystem bridge and MCP server entrypoint used by the agent.
+//! - `settings`: Agent-specific settings read from the `[acp]` config table.
 
 pub mod agent;
 pub mod fs;
 pub mod logging;
+pub mod settings;
 
 // Common re-exports for convenience.
 pub use agent::{CodexAgent, SessionManager};
 pub use fs::FsBridge;
 pub use logging::init_from_env;
+pub use settings::AcpSettings;
 
 /// A small prelude with the most commonly used items when embedding the agent.
 pub mod prelude {
@@ -1,19 +1,19 @@
 use codex_acp::{
-    CodexAgent, FsBridge,
-    agent::ClientOp::{ReadTextFile, RequestPermission, WriteTextFile},
+    AcpSettings, CodexAgent, FsBridge,
+    agent::ClientOp::{ExtNotification, ReadTextFile, RequestPermission, WriteTextFile},
 };
 
 use agent_client_protocol::{AgentSideConnection, Client, Error};
 use anyhow::{Result, bail};
 use codex_core::config::{self, Config, ConfigOverrides};
-use std::env;
+use std::{env, rc::Rc};
 use tokio::{
     io,
     sync::mpsc,
     task::{self, LocalSet},
 };
 use tokio_util::compat::{TokioAsyncReadCompatExt as _, TokioAsyncWriteCompatExt as _};
-use tracing::error;
+use tracing::{error, warn};
 
 #[tokio::main(flavor = "current_thread")]
 async fn main() -> Result<()> {
@@ -45,12 +45,21 @@ async fn main() -> Result<()> {
             vec![],
         ).await?;
         let profiles = config_toml.profiles;
+        let settings = AcpSettings::load(&config.codex_home)?;
         let fs_bridge = FsBridge::start(client_tx.clone(), config.cwd.clone()).await?;
-        let agent = CodexAgent::with_config(tx, client_tx, config, profiles, Some(fs_bridge));
+        let agent = CodexAgent::with_config(
+            tx,
+            client_tx,
+            config,
+            profiles,
+            settings,
+            Some(fs_bridge),
+        );
         let session_manager = agent.session_manager().clone();
         let (conn, handle_io) = AgentSideConnection::new(agent, outgoing, incoming, |fut| {
             task::spawn_local(fut);
         });
+        let conn = Rc::new(conn);
 
         task::spawn_local(async move {
             loop {
@@ -67,9 +76,17 @@ async fn main() -> Result<()> {
                     }
                     op = client_rx.recv() => {
                         match op {
-                            Some(RequestPermission { request: req, response_tx: tx }) => {
-                                let res = conn.request_permission(req).await;
-                                let _ = tx.send(res);
+                            Some(RequestPermission { request: mut req, response_tx: tx }) => {
+                                // The FS bridge raises permission requests with its FS session id.
+                                if let Some(resolved_id) = session_manager.resolve_acp_session_id(&req.session_id) {
+                                    req.session_id = resolved_id;
+                                }
+                                // A prompt can stay open for minutes; waiting for it here
+                                // would hold up every other session's client calls.
+                                let conn = conn.clone();
+                                task::spawn_local(async move {
+                                    let _ = tx.send(conn.request_permission(req).await);
+                                });
                             }
                             Some(ReadTextFile { request: mut req, response_tx: tx }) => {
                                 match session_manager.resolve_acp_session_id(&req.session_id) {
@@ -105,6 +122,11 @@ async fn main() -> Result<()> {
                                     }
                                 }
                             }
+                            Some(ExtNotification { notification }) => {
+                                if let Err(e) = conn.ext_notification(notification).await {
+                                    warn!(error = ?e, "failed to send extension notification");
+                                }
+                            }
                             None => break,
                         }
                     }
@@ -0,0 +1,580 @@
+//! codex-acp specific settings loaded from Codex's `config.toml`.
+//!
+//! Codex ignores tables it does not know about, so agent-only knobs live in
+//! an `[acp]` table next to the regular Codex configuration:
+//!
+//! ```toml
+//! [acp.auto_compact]
+//! enabled = true
+//! threshold_percent = 85
+//!
+//! # Per-profile overrides (keys match `[profiles.<name>]`).
+//! [acp.profiles.long-context.auto_compact]
+//! threshold_percent = 95
+//!
+//! [acp.rate_limits]
+//! warnings = true
+//! warn_at_percent = [75, 90]
+//!
+//! [acp.provider_auth]
+//! probe_models = true
+//! probe_timeout_secs = 5
+//!
+//! [acp.fs]
+//! read_allow = ["~/.cargo/registry", "../shared-docs"]
+//! read_timeout_secs = 60
+//! write_timeout_secs = 300
+//! connect_timeout_secs = 5
+//! startup_timeout_secs = 5
+//! read_line_limit = 1000
+//! max_read_bytes = 51200
+//! disk_fallback = true
+//! tools = "both"  # or "acp_fs_only" / "native_only"
+//!
+//! # MCP servers passed in by the client.
+//! [acp.mcp]
+//! startup_timeout_secs = 5
+//! tool_timeout_secs = 30
+//! ```
+//!
+//! The timeouts and read limits can also be set with environment variables,
+//! which win over `config.toml`: `ACP_FS_READ_TIMEOUT_SECS`,
+//! `ACP_FS_WRITE_TIMEOUT_SECS`, `ACP_FS_CONNECT_TIMEOUT_SECS`,
+//! `ACP_FS_STARTUP_TIMEOUT_SECS`, `ACP_FS_READ_LINE_LIMIT`,
+//! `ACP_FS_MAX_READ_BYTES`, `ACP_MCP_STARTUP_TIMEOUT_SECS` and
+//! `ACP_MCP_TOOL_TIMEOUT_SECS`.
+//!
+//! Settings are parsed once at startup and validated before the agent starts
+//! serving requests. A missing file or table yields the defaults.
+
+use std::{
+    collections::HashMap,
+    env,
+    fmt::Display,
+    fs, io,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+use anyhow::{Context, Result, anyhow, bail};
+use serde::Deserialize;
+
+/// Name of the Codex configuration file inside `CODEX_HOME`.
+const CONFIG_TOML_FILE: &str = "config.toml";
+
+/// Smallest `acp.fs.max_read_bytes`: a read must fit at least a long line.
+const MIN_READ_BYTES: usize = 1024;
+
+/// Defaults of the `[acp.fs]` limits. The `acp_fs` server falls back to the
+/// same values for variables missing from its environment.
+pub(crate) const DEFAULT_FS_READ_TIMEOUT_SECS: u64 = 60;
+pub(crate) const DEFAULT_FS_WRITE_TIMEOUT_SECS: u64 = 300;
+pub(crate) const DEFAULT_FS_CONNECT_TIMEOUT_SECS: u64 = 5;
+pub(crate) const DEFAULT_FS_READ_LINE_LIMIT: u32 = 1000;
+pub(crate) const DEFAULT_FS_MAX_READ_BYTES: usize = 50 * 1024;
+
+/// Top-level `[acp]` table.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct AcpSettings {
+    pub auto_compact: AutoCompactSettings,
+    pub rate_limits: RateLimitSettings,
+    pub provider_auth: ProviderAuthSettings,
+    pub fs: FsSettings,
+    pub mcp: McpSettings,
+    pub profiles: HashMap<String, AcpProfileSettings>,
+}
+
+/// Automatic compaction of the conversation when the context window fills up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct AutoCompactSettings {
+    /// Whether the agent submits `Op::Compact` on its own between turns.
+    pub enabled: bool,
+    /// Percentage (1-100) of the model context window that triggers compaction.
+    pub threshold_percent: u8,
+}
+
+impl Default for AutoCompactSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            threshold_percent: 85,
+        }
+    }
+}
+
+/// Proactive warnings about ChatGPT plan rate-limit usage.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct RateLimitSettings {
+    /// Whether to post a warning message when usage crosses a threshold.
+    pub warnings: bool,
+    /// Usage percentages (1-100) of a rate-limit window that trigger a warning.
+    pub warn_at_percent: Vec<u8>,
+}
+
+impl Default for RateLimitSettings {
+    fn default() -> Self {
+        Self {
+            warnings: true,
+            warn_at_percent: vec![75, 90],
+        }
+    }
+}
+
+/// Checks performed when authenticating against a custom model provider.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct ProviderAuthSettings {
+    /// Whether to call the provider's `/models` endpoint to verify connectivity.
+    pub probe_models: bool,
+    /// Timeout for the `/models` probe, in seconds.
+    pub probe_timeout_secs: u64,
+}
+
+impl Default for ProviderAuthSettings {
+    fn default() -> Self {
+        Self {
+            probe_models: false,
+            probe_timeout_secs: 5,
+        }
+    }
+}
+
+impl ProviderAuthSettings {
+    pub fn probe_timeout(&self) -> Duration {
+        Duration::from_secs(self.probe_timeout_secs)
+    }
+}
+
+/// Settings for the `acp_fs` bridge.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct FsSettings {
+    /// Paths outside the workspace that `acp_fs` may read without asking.
+    /// `~/` expands to the home directory; relative paths resolve against the workspace.
+    pub read_allow: Vec<String>,
+    /// How long `acp_fs` waits for the bridge to answer a read, in seconds.
+    pub read_timeout_secs: u64,
+    /// How long `acp_fs` waits for the bridge to answer a write, in seconds.
+    /// Writes may wait on a permission prompt in the client.
+    pub write_timeout_secs: u64,
+    /// How long `acp_fs` waits to connect to the bridge, in seconds.
+    pub connect_timeout_secs: u64,
+    /// How long Codex waits for the `acp_fs` server to start, in seconds.
+    pub startup_timeout_secs: u64,
+    /// Lines `read_text_file` returns when the model does not pass a limit.
+    pub read_line_limit: u32,
+    /// Most bytes one `read_text_file` call returns.
+    pub max_read_bytes: usize,
+    /// Whether reads and writes may go to disk when the client fails them.
+    /// Sessions can override it with `_meta.acp_fs.disk_fallback`.
+    pub disk_fallback: bool,
+    /// Which file tools sessions get. Sessions can override it with
+    /// `_meta.acp_fs.tools`.
+    pub tools: FsToolMode,
+}
+
+/// Which file tools a session gets: the `acp_fs` MCP server, Codex's own
+/// (shell and `apply_patch`), or both.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FsToolMode {
+    #[default]
+    Both,
+    /// `acp_fs` only; Codex's `apply_patch`, `read_file`, `list_dir` and
+    /// `grep_files` tools are turned off.
+    AcpFsOnly,
+    /// Codex's tools only; `acp_fs` is not registered.
+    NativeOnly,
+}
+
+impl FsToolMode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            FsToolMode::Both => "both",
+            FsToolMode::AcpFsOnly => "acp_fs_only",
+            FsToolMode::NativeOnly => "native_only",
+        }
+    }
+
+    /// Whether the session gets the `acp_fs` server.
+    pub fn uses_acp_fs(self) -> bool {
+        self != FsToolMode::NativeOnly
+    }
+}
+
+impl Default for FsSettings {
+    fn default() -> Self {
+        Self {
+            read_allow: Vec::new(),
+            read_timeout_secs: DEFAULT_FS_READ_TIMEOUT_SECS,
+            write_timeout_secs: DEFAULT_FS_WRITE_TIMEOUT_SECS,
+            connect_timeout_secs: DEFAULT_FS_CONNECT_TIMEOUT_SECS,
+            startup_timeout_secs: 5,
+            read_line_limit: DEFAULT_FS_READ_LINE_LIMIT,
+            max_read_bytes: DEFAULT_FS_MAX_READ_BYTES,
+            disk_fallback: true,
+            tools: FsToolMode::default(),
+        }
+    }
+}
+
+impl FsSettings {
+    /// The read allow-list with `~/` expanded.
+    pub fn read_allow_paths(&self) -> Vec<PathBuf> {
+        let home = env::var_os("HOME").map(PathBuf::from);
+        self.read_allow
+            .iter()
+            .map(|entry| match (entry.strip_prefix("~/"), home.as_ref()) {
+                (Some(rest), Some(home)) => home.join(rest),
+                _ => PathBuf::from(entry),
+            })
+            .collect()
+    }
+
+    /// The longest bridge timeout, which bounds a whole `acp_fs` tool call.
+    pub fn max_timeout(&self) -> Duration {
+        Duration::from_secs(self.read_timeout_secs.max(self.write_timeout_secs))
+    }
+
+    pub fn startup_timeout(&self) -> Duration {
+        Duration::from_secs(self.startup_timeout_secs)
+    }
+}
+
+/// Timeouts for the MCP servers a client passes in `session/new`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct McpSettings {
+    /// How long Codex waits for a server to start, in seconds.
+    pub startup_timeout_secs: u64,
+    /// How long Codex waits for one tool call, in seconds.
+    pub tool_timeout_secs: u64,
+}
+
+impl Default for McpSettings {
+    fn default() -> Self {
+        Self {
+            startup_timeout_secs: 5,
+            tool_timeout_secs: 30,
+        }
+    }
+}
+
+impl McpSettings {
+    pub fn startup_timeout(&self) -> Duration {
+        Duration::from_secs(self.startup_timeout_secs)
+    }
+
+    pub fn tool_timeout(&self) -> Duration {
+        Duration::from_secs(self.tool_timeout_secs)
+    }
+}
+
+/// Per-profile overrides under `[acp.profiles.<name>]`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct AcpProfileSettings {
+    pub auto_compact: AutoCompactOverride,
+}
+
+/// Partial `auto_compact` settings; unset fields inherit the global value.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(default)]
+pub struct AutoCompactOverride {
+    pub enabled: Option<bool>,
+    pub threshold_percent: Option<u8>,
+}
+
+impl AcpSettings {
+    /// Load the `[acp]` table from `<codex_home>/config.toml` and apply the
+    /// environment overrides.
+    ///
+    /// The file is validated on its own, so an error names either the file or
+    /// the environment variable that holds the bad value.
+    pub fn load(codex_home: &Path) -> Result<Self> {
+        let path = codex_home.join(CONFIG_TOML_FILE);
+        let mut settings = match fs::read_to_string(&path) {
+            Ok(raw) => Self::from_toml_str(&raw)
+                .with_context(|| format!("invalid [acp] settings in {}", path.display()))?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Self::default(),
+            Err(err) => {
+                return Err(err).with_context(|| format!("failed to read {}", path.display()));
+            }
+        };
+        settings.apply_env(|key| env::var(key).ok())?;
+        Ok(settings)
+    }
+
+    /// Parse settings from the full contents of a `config.toml` file.
+    pub fn from_toml_str(raw: &str) -> Result<Self> {
+        let settings = Self::parse(raw)?;
+        settings.validate()?;
+        Ok(settings)
+    }
+
+    fn parse(raw: &str) -> Result<Self> {
+        let mut table: toml::Table = toml::from_str(raw)?;
+        Ok(match table.remove("acp") {
+            Some(value) => value.try_into::<Self>()?,
+            None => Self::default(),
+        })
+    }
+
+    /// Override settings from the environment variables that are set, as
+    /// looked up by `var`. Each value is checked as it is applied.
+    fn apply_env(&mut self, var: impl Fn(&str) -> Option<String>) -> Result<()> {
+        let fs = &mut self.fs;
+        env_override(
+            &var,
+            "ACP_FS_READ_TIMEOUT_SECS",
+            &mut fs.read_timeout_secs,
+            1,
+        )?;
+        env_override(
+            &var,
+            "ACP_FS_WRITE_TIMEOUT_SECS",
+            &mut fs.write_timeout_secs,
+            1,
+        )?;
+        env_override(
+            &var,
+            "ACP_FS_CONNECT_TIMEOUT_SECS",
+            &mut fs.connect_timeout_secs,
+            1,
+        )?;
+        env_override(
+            &var,
+            "ACP_FS_STARTUP_TIMEOUT_SECS",
+            &mut fs.startup_timeout_secs,
+            1,
+        )?;
+        env_override(&var, "ACP_FS_READ_LINE_LIMIT", &mut fs.read_line_limit, 1)?;
+        env_override(
+            &var,
+            "ACP_FS_MAX_READ_BYTES",
+            &mut fs.max_read_bytes,
+            MIN_READ_BYTES,
+        )?;
+        let mcp = &mut self.mcp;
+        env_override(
+            &var,
+            "ACP_MCP_STARTUP_TIMEOUT_SECS",
+            &mut mcp.startup_timeout_secs,
+            1,
+        )?;
+        env_override(
+            &var,
+            "ACP_MCP_TOOL_TIMEOUT_SECS",
+            &mut mcp.tool_timeout_secs,
+            1,
+        )?;
+        Ok(())
+    }
+
+    /// Reject values that would make the agent misbehave at runtime.
+    pub fn validate(&self) -> Result<()> {
+        validate_percent(
+            "acp.auto_compact.threshold_percent",
+            Some(self.auto_compact.threshold_percent),
+        )?;
+        for threshold in &self.rate_limits.warn_at_percent {
+            validate_percent("acp.rate_limits.warn_at_percent", Some(*threshold))?;
+        }
+        if self.provider_auth.probe_timeout_secs == 0 {
+            bail!("acp.provider_auth.probe_timeout_secs must be greater than 0");
+        }
+        for (key, value) in [
+            ("acp.fs.read_timeout_secs", self.fs.read_timeout_secs),
+            ("acp.fs.write_timeout_secs", self.fs.write_timeout_secs),
+            ("acp.fs.connect_timeout_secs", self.fs.connect_timeout_secs),
+            ("acp.fs.startup_timeout_secs", self.fs.startup_timeout_secs),
+            ("acp.fs.read_line_limit", u64::from(self.fs.read_line_limit)),
+            (
+                "acp.mcp.startup_timeout_secs",
+                self.mcp.startup_timeout_secs,
+            ),
+            ("acp.mcp.tool_timeout_secs", self.mcp.tool_timeout_secs),
+        ] {
+            if value == 0 {
+                bail!("{key} must be greater than 0");
+            }
+        }
+        if self.fs.max_read_bytes < MIN_READ_BYTES {
+            bail!(
+                "acp.fs.max_read_bytes must be at least {MIN_READ_BYTES} (got {})",
+                self.fs.max_read_bytes
+            );
+        }
+        for (name, profile) in &self.profiles {
+            validate_percent(
+                &format!("acp.profiles.{name}.auto_compact.threshold_percent"),
+                profile.auto_compact.threshold_percent,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Rate-limit warning thresholds, sorted ascending; empty when warnings are disabled.
+    pub fn rate_limit_thresholds(&self) -> Vec<u8> {
+        if !self.rate_limits.warnings {
+            return Vec::new();
+        }
+        let mut thresholds = self.rate_limits.warn_at_percent.clone();
+        thresholds.sort_unstable();
+        thresholds.dedup();
+        thresholds
+    }
+
+    /// Resolve auto-compaction settings for the given Codex profile name.
+    pub fn auto_compact_for(&self, profile: Option<&str>) -> AutoCompactSettings {
+        let overrides = profile
+            .and_then(|name| self.profiles.get(name))
+            .map(|p| p.auto_compact)
+            .unwrap_or_default();
+        AutoCompactSettings {
+            enabled: overrides.enabled.unwrap_or(self.auto_compact.enabled),
+            threshold_percent: overrides
+                .threshold_percent
+                .unwrap_or(self.auto_compact.threshold_percent),
+        }
+    }
+}
+
+/// Set `target` from the variable `key` if it is set, refusing values below
+/// `min`.
+fn env_override<T>(
+    var: &impl Fn(&str) -> Option<String>,
+    key: &str,
+    target: &mut T,
+    min: T,
+) -> Result<()>
+where
+    T: FromStr + PartialOrd + Display,
+    T::Err: Display,
+{
+    let Some(raw) = var(key) else {
+        return Ok(());
+    };
+    let value: T = raw
+        .trim()
+        .parse()
+        .map_err(|err| anyhow!("{key} has an invalid value {raw:?}: {err}"))?;
+    if value < min {
+        bail!("{key} must be at least {min} (got {value})");
+    }
+    *target = value;
+    Ok(())
+}
+
+fn validate_percent(key: &str, percent: Option<u8>) -> Result<()> {
+    match percent {
+        Some(value) if value == 0 || value > 100 => {
+            bail!("{key} must be between 1 and 100 (got {value})")
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_env(vars: &[(&str, &str)]) -> Result<AcpSettings> {
+        let mut settings = AcpSettings::default();
+        settings.apply_env(|key| {
+            vars.iter()
+                .find(|(name, _)| *name == key)
+                .map(|(_, value)| value.to_string())
+        })?;
+        Ok(settings)
+    }
+
+    #[test]
+    fn missing_table_gives_the_defaults() {
+        let settings = AcpSettings::from_toml_str("model = \"o3\"\n").unwrap();
+        assert_eq!(settings.fs.read_timeout_secs, DEFAULT_FS_READ_TIMEOUT_SECS);
+        assert_eq!(
+            settings.fs.write_timeout_secs,
+            DEFAULT_FS_WRITE_TIMEOUT_SECS
+        );
+        assert_eq!(settings.fs.read_line_limit, DEFAULT_FS_READ_LINE_LIMIT);
+        assert_eq!(settings.fs.max_read_bytes, DEFAULT_FS_MAX_READ_BYTES);
+        assert_eq!(settings.fs.tools, FsToolMode::Both);
+    }
+
+    #[test]
+    fn file_values_are_validated() {
+        let settings = AcpSettings::from_toml_str(
+            "[acp.fs]\nread_timeout_secs = 10\ntools = \"acp_fs_only\"\n",
+        )
+        .unwrap();
+        assert_eq!(settings.fs.read_timeout_secs, 10);
+        assert_eq!(settings.fs.tools, FsToolMode::AcpFsOnly);
+
+        let err = AcpSettings::from_toml_str("[acp.fs]\nwrite_timeout_secs = 0\n").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "acp.fs.write_timeout_secs must be greater than 0"
+        );
+        let err = AcpSettings::from_toml_str("[acp.fs]\nmax_read_bytes = 10\n").unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("acp.fs.max_read_bytes must be at least")
+        );
+    }
+
+    #[test]
+    fn environment_overrides_the_file() {
+        let settings = with_env(&[
+            ("ACP_FS_READ_TIMEOUT_SECS", " 90 "),
+            ("ACP_FS_MAX_READ_BYTES", "2048"),
+            ("ACP_MCP_TOOL_TIMEOUT_SECS", "45"),
+        ])
+        .unwrap();
+        assert_eq!(settings.fs.read_timeout_secs, 90);
+        assert_eq!(settings.fs.max_read_bytes, 2048);
+        assert_eq!(settings.mcp.tool_timeout_secs, 45);
+        assert_eq!(
+            settings.fs.write_timeout_secs,
+            DEFAULT_FS_WRITE_TIMEOUT_SECS
+        );
+    }
+
+    #[test]
+    fn invalid_environment_values_name_the_variable() {
+        let err = with_env(&[("ACP_FS_WRITE_TIMEOUT_SECS", "0")]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "ACP_FS_WRITE_TIMEOUT_SECS must be at least 1 (got 0)"
+        );
+
+        let err = with_env(&[("ACP_FS_READ_LINE_LIMIT", "lots")]).unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("ACP_FS_READ_LINE_LIMIT has an invalid value \"lots\""),
+            "{err}"
+        );
+
+        let err = with_env(&[("ACP_FS_MAX_READ_BYTES", "100")]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "ACP_FS_MAX_READ_BYTES must be at least 1024 (got 100)"
+        );
+    }
+
+    #[test]
+    fn invalid_files_are_named_when_loading() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(CONFIG_TOML_FILE);
+        fs::write(&path, "[acp.auto_compact]\nthreshold_percent = 0\n").unwrap();
+        let err = AcpSettings::load(dir.path()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!("invalid [acp] settings in {}", path.display())
+        );
+    }
+}